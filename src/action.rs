@@ -0,0 +1,218 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use class::Class;
+use pathfinding::manhattan_distance;
+use terrain::Terrain;
+use unit::Unit;
+
+// Experience for using a staff
+pub const STAFF_EXP: u32 = 15;
+
+// What a unit can do after moving.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Attack,
+    Staff,
+    Item,
+    Trade,
+    Seize,
+    Wait,
+}
+
+impl Action {
+    // Name used for the action in scripts.
+    pub fn from_name(name: &str) -> Option<Action> {
+        match name {
+            "attack" => Some(Action::Attack),
+            "staff" => Some(Action::Staff),
+            "item" => Some(Action::Item),
+            "trade" => Some(Action::Trade),
+            "seize" => Some(Action::Seize),
+            "wait" => Some(Action::Wait),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            &Action::Attack => "Attack",
+            &Action::Staff => "Staff",
+            &Action::Item => "Item",
+            &Action::Trade => "Trade",
+            &Action::Seize => "Seize",
+            &Action::Wait => "Wait",
+        }
+    }
+}
+
+// An entry in the trade menu. The index is into the inventory of the unit
+// giving the item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TradeEntry {
+    Give(usize),
+    Take(usize),
+}
+
+// Locations of the units other than unit itself that match pred, sorted so
+// that cycling through targets is stable.
+fn targets<F>(
+    unit: &Unit,
+    units: &HashMap<(u32, u32), Rc<RefCell<Unit>>>,
+    pred: F,
+) -> Vec<(u32, u32)>
+where
+    F: Fn(&Unit, u32) -> bool,
+{
+    let mut targets: Vec<(u32, u32)> = units
+        .iter()
+        .filter(|&(&location, other)| {
+            location != unit.location
+                && pred(&other.borrow(), manhattan_distance(unit.location, location))
+        })
+        .map(|(&location, _)| location)
+        .collect();
+    targets.sort();
+    targets
+}
+
+// Enemies that the unit can reach with any of its weapons from where it
+// stands.
+pub fn attack_targets(
+    unit: &Unit,
+    units: &HashMap<(u32, u32), Rc<RefCell<Unit>>>,
+) -> Vec<(u32, u32)> {
+    targets(unit, units, |other, distance| {
+        unit.faction.is_hostile_to(&other.faction)
+            && unit
+                .inventory
+                .weapon_index_in_range(&unit.class, distance)
+                .is_some()
+    })
+}
+
+// Injured allies that the unit can reach with any of its staves.
+pub fn staff_targets(
+    unit: &Unit,
+    units: &HashMap<(u32, u32), Rc<RefCell<Unit>>>,
+) -> Vec<(u32, u32)> {
+    targets(unit, units, |other, distance| {
+        !unit.faction.is_hostile_to(&other.faction)
+            && other.hp < other.stats.hp
+            && unit
+                .inventory
+                .staff_index_in_range(&unit.class, distance)
+                .is_some()
+    })
+}
+
+// Adjacent units of the same faction.
+pub fn trade_targets(
+    unit: &Unit,
+    units: &HashMap<(u32, u32), Rc<RefCell<Unit>>>,
+) -> Vec<(u32, u32)> {
+    targets(unit, units, |other, distance| {
+        other.faction == unit.faction && distance == 1
+    })
+}
+
+// Only lords can seize, and only by standing on the throne.
+pub fn can_seize(unit: &Unit, terrain: Terrain) -> bool {
+    unit.class == Class::Lord && terrain == Terrain::Throne
+}
+
+// The actions to offer a unit standing on terrain, in menu order.
+pub fn available_actions(
+    unit: &Unit,
+    terrain: Terrain,
+    units: &HashMap<(u32, u32), Rc<RefCell<Unit>>>,
+) -> Vec<Action> {
+    let mut actions = vec![];
+    if !attack_targets(unit, units).is_empty() {
+        actions.push(Action::Attack);
+    }
+    if !staff_targets(unit, units).is_empty() {
+        actions.push(Action::Staff);
+    }
+    if unit.inventory.len() > 0 {
+        actions.push(Action::Item);
+    }
+    if !trade_targets(unit, units).is_empty() {
+        actions.push(Action::Trade);
+    }
+    if can_seize(unit, terrain) {
+        actions.push(Action::Seize);
+    }
+    actions.push(Action::Wait);
+    actions
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use testing::add_unit;
+    use unit::Faction;
+
+    #[test]
+    fn test_attack_targets_use_every_weapon() {
+        let mut units = HashMap::new();
+        add_unit(
+            &mut units,
+            Faction::Player,
+            Class::Cavalier,
+            (2, 2),
+            &["Iron Lance", "Javelin"],
+        );
+        add_unit(&mut units, Faction::Enemy, Class::Fighter, (2, 3), &[]);
+        add_unit(&mut units, Faction::Enemy, Class::Fighter, (4, 2), &[]);
+        add_unit(&mut units, Faction::Enemy, Class::Fighter, (5, 2), &[]);
+        add_unit(&mut units, Faction::Other, Class::Fighter, (1, 2), &[]);
+        let unit = units[&(2, 2)].borrow();
+        assert_eq!(attack_targets(&unit, &units), vec![(2, 3), (4, 2)]);
+    }
+
+    #[test]
+    fn test_staff_targets_are_injured_allies() {
+        let mut units = HashMap::new();
+        add_unit(
+            &mut units,
+            Faction::Player,
+            Class::Cleric,
+            (2, 2),
+            &["Heal"],
+        );
+        add_unit(&mut units, Faction::Player, Class::Lord, (2, 3), &[]);
+        add_unit(&mut units, Faction::Other, Class::Lord, (2, 1), &[]);
+        add_unit(&mut units, Faction::Enemy, Class::Lord, (1, 2), &[]);
+        units[&(2, 1)].borrow_mut().hp = 1;
+        units[&(1, 2)].borrow_mut().hp = 1;
+        let unit = units[&(2, 2)].borrow();
+        assert_eq!(staff_targets(&unit, &units), vec![(2, 1)]);
+    }
+
+    #[test]
+    fn test_available_actions() {
+        let mut units = HashMap::new();
+        add_unit(
+            &mut units,
+            Faction::Player,
+            Class::Lord,
+            (2, 2),
+            &["Iron Sword"],
+        );
+        add_unit(&mut units, Faction::Player, Class::Cleric, (2, 3), &[]);
+        {
+            let unit = units[&(2, 2)].borrow();
+            assert_eq!(
+                available_actions(&unit, Terrain::Throne, &units),
+                vec![Action::Item, Action::Trade, Action::Seize, Action::Wait]
+            );
+        }
+        add_unit(&mut units, Faction::Enemy, Class::Fighter, (3, 2), &[]);
+        let unit = units[&(2, 2)].borrow();
+        assert_eq!(
+            available_actions(&unit, Terrain::None, &units),
+            vec![Action::Attack, Action::Item, Action::Trade, Action::Wait]
+        );
+    }
+}
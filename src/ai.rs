@@ -0,0 +1,462 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use num::Rational;
+use combat;
+use item::Item;
+use pathfinding::{self, manhattan_distance};
+use terrain::Terrain;
+use unit::Unit;
+
+// Bonus for attacks that are expected to defeat the target outright
+const KILL_BONUS: i32 = 5000;
+// Bonus for attacking units that carry a staff, for Behavior::TargetHealers
+const HEALER_BONUS: i32 = 3000;
+
+// How a computer controlled unit picks what to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Behavior {
+    // Attacks the best target in reach, otherwise advances toward the
+    // nearest enemy.
+    Aggressive,
+    // Doesn't move until an enemy comes within reach, then turns aggressive.
+    WaitInRange,
+    // Never leaves the given tile, walking back to it if displaced. Attacks
+    // whatever is in range of the tile.
+    Guard((u32, u32)),
+    // Like Aggressive, but goes after units carrying staves first.
+    TargetHealers,
+}
+
+impl Default for Behavior {
+    fn default() -> Self {
+        Behavior::Aggressive
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AiAction {
+    Attack((u32, u32)),
+    Heal((u32, u32)),
+    Wait,
+}
+
+// Where a unit moves to and what it does there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decision {
+    pub destination: (u32, u32),
+    pub action: AiAction,
+}
+
+fn terrain_at(terrain: &Vec<Vec<Terrain>>, (x, y): (u32, u32)) -> Terrain {
+    terrain[x as usize][y as usize]
+}
+
+fn carries_staff(unit: &Unit) -> bool {
+    unit.inventory.items().iter().any(|item| match item {
+        &Item::Staff(_) => true,
+        _ => false,
+    })
+}
+
+// How good it is for unit to attack target from destination. Roughly the
+// expected damage dealt minus half the expected damage taken, in hundredths
+// of HP.
+fn attack_score(
+    unit: &Unit,
+    destination: (u32, u32),
+    target: &Unit,
+    terrain: &Vec<Vec<Terrain>>,
+) -> Option<i32> {
+    let distance = manhattan_distance(destination, target.location);
+    let forecast = combat::forecast(
+        &unit.attacking_combatant(terrain_at(terrain, destination), distance),
+        &target.combatant(terrain_at(terrain, target.location)),
+        distance,
+    )?;
+    let dealt = forecast.attacker.damage * forecast.attacker.attacks;
+    let taken = forecast.defender.damage * forecast.defender.attacks;
+    let mut score =
+        (dealt * forecast.attacker.hit) as i32 - (taken * forecast.defender.hit) as i32 / 2;
+    if dealt >= target.hp && forecast.attacker.hit > 0 {
+        score += KILL_BONUS;
+    }
+    if unit.behavior == Behavior::TargetHealers && carries_staff(target) {
+        score += HEALER_BONUS;
+    }
+    Some(score)
+}
+
+// How good it is for unit to heal target from destination.
+fn heal_score(unit: &Unit, destination: (u32, u32), target: &Unit) -> Option<i32> {
+    let distance = manhattan_distance(destination, target.location);
+    unit.inventory.staff_index_in_range(&unit.class, distance)?;
+    let missing_hp = target.stats.hp - target.hp;
+    if missing_hp == 0 {
+        return None;
+    }
+    Some(missing_hp as i32 * 100)
+}
+
+// The best attack or heal unit can make after moving to one of destinations.
+// Ties go to the cheaper destination so that units don't wander.
+fn best_action(
+    unit: &Unit,
+    destinations: &[(u32, u32)],
+    units: &HashMap<(u32, u32), Rc<RefCell<Unit>>>,
+    terrain: &Vec<Vec<Terrain>>,
+) -> Option<Decision> {
+    let mut others: Vec<(&(u32, u32), &Rc<RefCell<Unit>>)> = units
+        .iter()
+        .filter(|&(&location, _)| location != unit.location)
+        .collect();
+    others.sort_by_key(|&(&location, _)| location);
+
+    let mut best: Option<(i32, Rational, Decision)> = None;
+    for &destination in destinations {
+        let cost = unit
+            .costs
+            .get(&destination)
+            .cloned()
+            .unwrap_or(Rational::from_integer(0));
+        for &(&location, other) in &others {
+            let other = other.borrow();
+            let (score, action) = if unit.faction.is_hostile_to(&other.faction) {
+                match attack_score(unit, destination, &other, terrain) {
+                    Some(score) => (score, AiAction::Attack(location)),
+                    None => continue,
+                }
+            } else {
+                match heal_score(unit, destination, &other) {
+                    Some(score) => (score, AiAction::Heal(location)),
+                    None => continue,
+                }
+            };
+            let better = match best {
+                Some((best_score, ref best_cost, _)) => {
+                    score > best_score || (score == best_score && cost < *best_cost)
+                }
+                None => true,
+            };
+            if better {
+                best = Some((
+                    score,
+                    cost,
+                    Decision {
+                        destination,
+                        action,
+                    },
+                ));
+            }
+        }
+    }
+    best.map(|(_, _, decision)| decision)
+}
+
+// Furthest cell along the cheapest path from unit to goal that the unit can
+// reach this turn. The path goes around hostile units, which can't be passed.
+fn advance_toward<F>(
+    unit: &Unit,
+    goal: (u32, u32),
+    units: &HashMap<(u32, u32), Rc<RefCell<Unit>>>,
+    terrain: &Vec<Vec<Terrain>>,
+    cost_fn: F,
+) -> (u32, u32)
+where
+    F: Fn(&Terrain) -> Option<Rational>,
+{
+    let blocked: HashSet<(u32, u32)> = units
+        .iter()
+        .filter(|&(_, other)| unit.faction.is_hostile_to(&other.borrow().faction))
+        .map(|(&location, _)| location)
+        .collect();
+    match pathfinding::find_path(unit.location, goal, terrain, cost_fn, &blocked) {
+        Some((path, _)) => path
+            .into_iter()
+            .rev()
+            .find(|coord| unit.reachable_coords.contains(coord))
+            .unwrap_or(unit.location),
+        None => unit.location,
+    }
+}
+
+// Decides what unit does this phase. The unit's cached path data must be up
+// to date. cost_fn is the unit's movement cost for each terrain.
+pub fn decide<F>(
+    unit: &Unit,
+    units: &HashMap<(u32, u32), Rc<RefCell<Unit>>>,
+    terrain: &Vec<Vec<Terrain>>,
+    cost_fn: F,
+) -> Decision
+where
+    F: Fn(&Terrain) -> Option<Rational>,
+{
+    let stay = Decision {
+        destination: unit.location,
+        action: AiAction::Wait,
+    };
+    let mut destinations: Vec<(u32, u32)> = match unit.behavior {
+        Behavior::Guard(tile) => {
+            if tile != unit.location && !unit.reachable_coords.contains(&tile) {
+                return Decision {
+                    destination: advance_toward(unit, tile, units, terrain, cost_fn),
+                    action: AiAction::Wait,
+                };
+            }
+            vec![tile]
+        }
+        _ => unit.reachable_coords.iter().cloned().collect(),
+    };
+    destinations.sort();
+    if let Some(decision) = best_action(unit, &destinations, units, terrain) {
+        return decision;
+    }
+    match unit.behavior {
+        Behavior::Aggressive | Behavior::TargetHealers => {
+            // Head for the nearest enemy
+            let nearest = units
+                .iter()
+                .filter(|&(_, other)| unit.faction.is_hostile_to(&other.borrow().faction))
+                .map(|(&location, _)| (manhattan_distance(unit.location, location), location))
+                .min();
+            match nearest {
+                Some((_, location)) => Decision {
+                    destination: advance_toward(unit, location, units, terrain, cost_fn),
+                    action: AiAction::Wait,
+                },
+                None => stay,
+            }
+        }
+        Behavior::Guard(tile) => Decision {
+            destination: tile,
+            action: AiAction::Wait,
+        },
+        Behavior::WaitInRange => stay,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use class::Class;
+    use pathfinding::MoveRules;
+    use testing::add_unit;
+    use unit::Faction;
+
+    fn cost(terrain: &Terrain) -> Option<Rational> {
+        match terrain {
+            &Terrain::Wall => None,
+            _ => Some(Rational::from_integer(1)),
+        }
+    }
+
+    fn empty_terrain(w: usize, h: usize) -> Vec<Vec<Terrain>> {
+        vec![vec![Terrain::None; h]; w]
+    }
+
+    // Fills in the path data that Battle would normally compute.
+    fn compute_paths(units: &HashMap<(u32, u32), Rc<RefCell<Unit>>>, terrain: &Vec<Vec<Terrain>>) {
+        let occupants = units
+            .iter()
+            .map(|(&location, unit)| (location, unit.borrow().faction))
+            .collect();
+        for (&location, unit) in units {
+            let mut unit = unit.borrow_mut();
+            let (paths, costs, boundary, reachable_coords) = pathfinding::compute_path_costs(
+                location,
+                terrain,
+                terrain.len() as u32,
+                terrain[0].len() as u32,
+                unit.movement_range,
+                cost,
+                &MoveRules {
+                    faction: unit.faction,
+                    occupants: &occupants,
+                    zone_of_control: false,
+                },
+            );
+            unit.paths = paths;
+            unit.costs = costs;
+            unit.boundary = boundary;
+            unit.reachable_coords = reachable_coords;
+        }
+    }
+
+    #[test]
+    fn test_attacks_target_in_reach() {
+        let terrain = empty_terrain(8, 8);
+        let mut units = HashMap::new();
+        add_unit(
+            &mut units,
+            Faction::Enemy,
+            Class::Fighter,
+            (0, 0),
+            &["Iron Axe"],
+        );
+        add_unit(
+            &mut units,
+            Faction::Player,
+            Class::Lord,
+            (3, 0),
+            &["Iron Sword"],
+        );
+        compute_paths(&units, &terrain);
+        let decision = decide(&units[&(0, 0)].borrow(), &units, &terrain, cost);
+        assert_eq!(decision.action, AiAction::Attack((3, 0)));
+        assert_eq!(manhattan_distance(decision.destination, (3, 0)), 1);
+    }
+
+    #[test]
+    fn test_aggressive_advances() {
+        let terrain = empty_terrain(20, 1);
+        let mut units = HashMap::new();
+        add_unit(
+            &mut units,
+            Faction::Enemy,
+            Class::Fighter,
+            (0, 0),
+            &["Iron Axe"],
+        );
+        add_unit(
+            &mut units,
+            Faction::Player,
+            Class::Lord,
+            (19, 0),
+            &["Iron Sword"],
+        );
+        compute_paths(&units, &terrain);
+        let unit = units[&(0, 0)].borrow();
+        let decision = decide(&unit, &units, &terrain, cost);
+        assert_eq!(decision.action, AiAction::Wait);
+        assert_eq!(decision.destination, (unit.movement_range, 0));
+    }
+
+    #[test]
+    fn test_wait_in_range_stays() {
+        let terrain = empty_terrain(20, 1);
+        let mut units = HashMap::new();
+        add_unit(
+            &mut units,
+            Faction::Enemy,
+            Class::Fighter,
+            (0, 0),
+            &["Iron Axe"],
+        );
+        add_unit(
+            &mut units,
+            Faction::Player,
+            Class::Lord,
+            (19, 0),
+            &["Iron Sword"],
+        );
+        units[&(0, 0)].borrow_mut().behavior = Behavior::WaitInRange;
+        compute_paths(&units, &terrain);
+        let decision = decide(&units[&(0, 0)].borrow(), &units, &terrain, cost);
+        assert_eq!(decision.destination, (0, 0));
+        assert_eq!(decision.action, AiAction::Wait);
+    }
+
+    #[test]
+    fn test_guard_only_attacks_from_tile() {
+        let terrain = empty_terrain(8, 8);
+        let mut units = HashMap::new();
+        add_unit(
+            &mut units,
+            Faction::Enemy,
+            Class::Fighter,
+            (0, 0),
+            &["Iron Axe"],
+        );
+        add_unit(
+            &mut units,
+            Faction::Player,
+            Class::Lord,
+            (3, 0),
+            &["Iron Sword"],
+        );
+        units[&(0, 0)].borrow_mut().behavior = Behavior::Guard((0, 0));
+        compute_paths(&units, &terrain);
+        let decision = decide(&units[&(0, 0)].borrow(), &units, &terrain, cost);
+        assert_eq!(decision.destination, (0, 0));
+        assert_eq!(decision.action, AiAction::Wait);
+
+        // Displaced guards head back
+        units[&(0, 0)].borrow_mut().behavior = Behavior::Guard((7, 7));
+        let decision = decide(&units[&(0, 0)].borrow(), &units, &terrain, cost);
+        let reachable: HashSet<(u32, u32)> = units[&(0, 0)].borrow().reachable_coords.clone();
+        assert!(reachable.contains(&decision.destination));
+        assert!(manhattan_distance(decision.destination, (7, 7)) < 14);
+    }
+
+    #[test]
+    fn test_advance_goes_around_hostile_units() {
+        let terrain = empty_terrain(12, 2);
+        let mut units = HashMap::new();
+        add_unit(
+            &mut units,
+            Faction::Enemy,
+            Class::Fighter,
+            (0, 0),
+            &["Iron Axe"],
+        );
+        add_unit(&mut units, Faction::Player, Class::Lord, (3, 0), &[]);
+        units[&(0, 0)].borrow_mut().behavior = Behavior::Guard((11, 0));
+        compute_paths(&units, &terrain);
+        // Walking straight along the top row would stop short of the lord
+        let decision = decide(&units[&(0, 0)].borrow(), &units, &terrain, cost);
+        assert_eq!(decision.destination, (4, 1));
+    }
+
+    #[test]
+    fn test_target_healers_first() {
+        let terrain = empty_terrain(8, 8);
+        let mut units = HashMap::new();
+        add_unit(
+            &mut units,
+            Faction::Enemy,
+            Class::Fighter,
+            (3, 3),
+            &["Iron Axe"],
+        );
+        add_unit(
+            &mut units,
+            Faction::Player,
+            Class::Lord,
+            (3, 1),
+            &["Iron Sword"],
+        );
+        add_unit(
+            &mut units,
+            Faction::Player,
+            Class::Lord,
+            (3, 5),
+            &["Iron Sword", "Heal"],
+        );
+        compute_paths(&units, &terrain);
+        // Both targets are equally good, so the first one found wins
+        let decision = decide(&units[&(3, 3)].borrow(), &units, &terrain, cost);
+        assert_eq!(decision.action, AiAction::Attack((3, 1)));
+        units[&(3, 3)].borrow_mut().behavior = Behavior::TargetHealers;
+        let decision = decide(&units[&(3, 3)].borrow(), &units, &terrain, cost);
+        assert_eq!(decision.action, AiAction::Attack((3, 5)));
+    }
+
+    #[test]
+    fn test_heals_injured_ally() {
+        let terrain = empty_terrain(8, 8);
+        let mut units = HashMap::new();
+        add_unit(&mut units, Faction::Enemy, Class::Cleric, (0, 0), &["Heal"]);
+        add_unit(
+            &mut units,
+            Faction::Enemy,
+            Class::Fighter,
+            (4, 0),
+            &["Iron Axe"],
+        );
+        units[&(4, 0)].borrow_mut().hp = 5;
+        compute_paths(&units, &terrain);
+        let decision = decide(&units[&(0, 0)].borrow(), &units, &terrain, cost);
+        assert_eq!(decision.action, AiAction::Heal((4, 0)));
+        assert_eq!(manhattan_distance(decision.destination, (4, 0)), 1);
+    }
+}
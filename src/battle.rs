@@ -0,0 +1,886 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use action::{self, Action, TradeEntry};
+use ai::{self, AiAction, Behavior};
+use chapter::{Chapter, UnitPlacement, VictoryCondition};
+use class::Class;
+use combat::{self, CombatResult, Forecast, Side};
+use history;
+use item::{Inventory, Item};
+use pathfinding::{self, MoveRules};
+use rng::Rng;
+use script::Order;
+use skill::Skill;
+use terrain::{CostTable, Terrain};
+use turn::{Phase, TurnManager};
+use unit::{Faction, Unit};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Outcome {
+    Victory,
+    Defeat,
+}
+
+// What a unit did with its turn, for logging battles that are played out
+// without a frontend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub unit: u32,
+    pub from: (u32, u32),
+    pub to: (u32, u32),
+    pub action: Action,
+    // Who was attacked or healed
+    pub target: Option<(u32, u32)>,
+    // How the fight went, if the unit attacked
+    pub combat: Option<CombatResult>,
+}
+
+// A chapter being played: the map, the units on it, whose turn it is and the
+// rules for changing any of that. Frontends keep their own state (selection,
+// menus, animations) on top of this.
+#[derive(Debug)]
+pub struct Battle {
+    pub chapter: Chapter,
+    // 2d vec (x, y) of terrain features
+    pub terrain: Vec<Vec<Terrain>>,
+    pub width: u32,
+    pub height: u32,
+    pub cost_table: CostTable,
+    // Whether units stop moving when they come next to a hostile unit
+    pub zone_of_control: bool,
+    pub units: HashMap<(u32, u32), Rc<RefCell<Unit>>>,
+    pub turn_manager: TurnManager,
+    pub rng: Rng,
+    // Number of enemy units that can attack each coord next phase
+    pub danger_zone: HashMap<(u32, u32), u32>,
+    // Set once the chapter is won or lost
+    pub outcome: Option<Outcome>,
+    // Rewinds left for the rest of the chapter (see History::rewind())
+    pub rewinds: u32,
+}
+
+impl Battle {
+    // Places the chapter's starting units.
+    pub fn new(chapter: Chapter, cost_table: CostTable, seed: u64) -> Self {
+        let mut battle = Battle {
+            terrain: chapter.terrain.clone(),
+            width: chapter.width,
+            height: chapter.height,
+            cost_table,
+            zone_of_control: chapter.zone_of_control,
+            units: HashMap::new(),
+            turn_manager: TurnManager::new(),
+            rng: Rng::new(seed),
+            danger_zone: HashMap::new(),
+            outcome: None,
+            rewinds: history::DEFAULT_REWINDS,
+            chapter,
+        };
+        for placement in battle.chapter.units.clone() {
+            battle.place_unit(&placement);
+        }
+        battle.spawn_reinforcements();
+        battle
+    }
+
+    pub fn terrain_at(&self, (x, y): (u32, u32)) -> Terrain {
+        self.terrain[x as usize][y as usize]
+    }
+
+    pub fn add_unit(
+        &mut self,
+        id: u32,
+        faction: Faction,
+        class: Class,
+        level: u32,
+        location: (u32, u32),
+        inventory: Inventory,
+    ) {
+        let unit = Unit::new(id, faction, class, level, location, inventory);
+        self.units.insert(location, Rc::new(RefCell::new(unit)));
+        // Units block each other, so every unit's paths need to be updated
+        self.recompute_unit_paths();
+    }
+
+    pub fn place_unit(&mut self, placement: &UnitPlacement) {
+        self.add_unit(
+            placement.id,
+            placement.faction,
+            placement.class,
+            placement.level,
+            placement.location,
+            placement.inventory.clone(),
+        );
+        self.units[&placement.location].borrow_mut().behavior = placement.behavior;
+    }
+
+    // Places the chapter's reinforcements for the current turn. Ones whose
+    // location is taken don't arrive.
+    pub fn spawn_reinforcements(&mut self) {
+        let turn = self.turn_manager.turn;
+        let arrivals: Vec<UnitPlacement> = self.chapter
+            .reinforcements_on(turn)
+            .into_iter()
+            .filter(|placement| !self.units.contains_key(&placement.location))
+            .cloned()
+            .collect();
+        for placement in arrivals.iter() {
+            self.place_unit(placement);
+        }
+    }
+
+    pub fn occupants(&self) -> HashMap<(u32, u32), Faction> {
+        self.units
+            .iter()
+            .map(|(&location, unit)| (location, unit.borrow().faction))
+            .collect()
+    }
+
+    // Recomputes the cached path data of every unit. This needs to be called
+    // whenever a unit is added, moved or removed.
+    pub fn recompute_unit_paths(&mut self) {
+        let occupants = self.occupants();
+        for (&location, unit) in self.units.iter() {
+            let mut unit = unit.borrow_mut();
+            let movement_class = unit.movement_class;
+            let rules = MoveRules {
+                faction: unit.faction,
+                occupants: &occupants,
+                zone_of_control: self.zone_of_control && !unit.has_skill(Skill::Pass),
+            };
+            let (paths, costs, boundary, reachable_coords) = pathfinding::compute_path_costs(
+                location,
+                &self.terrain,
+                self.width,
+                self.height,
+                unit.movement_range,
+                |terr| self.cost_table.cost(terr, movement_class),
+                &rules,
+            );
+            unit.attack_coords = pathfinding::compute_attack_coords(
+                &reachable_coords,
+                &unit.attack_distances(),
+                self.width,
+                self.height,
+            );
+            // The search boundary misses cells next to holes in the reachable
+            // area, so every reachable cell is a candidate for the border.
+            unit.border = pathfinding::find_boundary_neighbor_directions(
+                &reachable_coords,
+                &reachable_coords,
+                self.width,
+                self.height,
+            );
+            unit.paths = paths;
+            unit.costs = costs;
+            unit.boundary = boundary;
+            unit.reachable_coords = reachable_coords;
+        }
+        self.update_danger_zone();
+    }
+
+    // Recomputes the danger zone from the cached movement ranges of all enemy
+    // units.
+    pub fn update_danger_zone(&mut self) {
+        let threatened_areas: Vec<HashSet<(u32, u32)>> = self.units
+            .values()
+            .map(|unit| unit.borrow())
+            .filter(|unit| unit.faction == Faction::Enemy)
+            .map(|unit| {
+                pathfinding::compute_threatened_coords(
+                    &unit.reachable_coords,
+                    &unit.attack_distances(),
+                    self.width,
+                    self.height,
+                )
+            })
+            .collect();
+        self.danger_zone = pathfinding::compute_threat_counts(threatened_areas.iter());
+    }
+
+    // Whether the player can move the unit right now.
+    pub fn can_move(&self, unit: &Unit) -> bool {
+        self.outcome.is_none() && self.turn_manager.phase == Phase::Player
+            && unit.faction == Faction::Player && !unit.has_moved
+            && !unit.is_spent()
+    }
+
+    // Rekeys the unit on from by its new location. Moving uses up the unit's
+    // move but not its action.
+    pub fn move_unit(&mut self, from: (u32, u32), to: (u32, u32)) -> Rc<RefCell<Unit>> {
+        let unit = self.units.remove(&from).unwrap();
+        {
+            let mut unit = unit.borrow_mut();
+            unit.location = to;
+            unit.has_moved = true;
+        }
+        self.units.insert(to, unit.clone());
+        self.recompute_unit_paths();
+        unit
+    }
+
+    // Puts a unit that has moved but not acted back on origin.
+    pub fn undo_move(&mut self, location: (u32, u32), origin: (u32, u32)) {
+        let unit = self.units.remove(&location).unwrap();
+        {
+            let mut unit = unit.borrow_mut();
+            unit.location = origin;
+            unit.has_moved = false;
+        }
+        self.units.insert(origin, unit);
+        self.recompute_unit_paths();
+    }
+
+    // The actions to offer the unit on location, in menu order.
+    pub fn available_actions(&self, location: (u32, u32)) -> Vec<Action> {
+        let unit = self.units[&location].borrow();
+        action::available_actions(&unit, self.terrain_at(location), &self.units)
+            .into_iter()
+            .filter(|&action| {
+                action != Action::Seize || self.chapter.victory == VictoryCondition::Seize
+            })
+            .collect()
+    }
+
+    // Forecast for the unit on attacker attacking the unit on target.
+    pub fn forecast(&self, attacker: (u32, u32), target: (u32, u32)) -> Option<Forecast> {
+        let attacker_unit = self.units.get(&attacker)?.borrow();
+        let defender = self.units.get(&target)?.borrow();
+        let distance = pathfinding::manhattan_distance(attacker, target);
+        combat::forecast(
+            &attacker_unit.attacking_combatant(self.terrain_at(attacker), distance),
+            &defender.combatant(self.terrain_at(target)),
+            distance,
+        )
+    }
+
+    // Equips the attacker's best weapon for the distance and rolls the fight.
+    // Nothing else changes until the result is applied with
+    // apply_combat().
+    pub fn resolve_attack(
+        &mut self,
+        attacker: (u32, u32),
+        target: (u32, u32),
+    ) -> Option<CombatResult> {
+        let distance = pathfinding::manhattan_distance(attacker, target);
+        self.units
+            .get(&attacker)?
+            .borrow_mut()
+            .equip_for_distance(distance);
+        let forecast = self.forecast(attacker, target)?;
+        Some(combat::resolve(&forecast, &mut self.rng))
+    }
+
+    // Applies the result of a fight, which ends the attacker's action.
+    pub fn apply_combat(
+        &mut self,
+        attacker: (u32, u32),
+        defender: (u32, u32),
+        result: &CombatResult,
+    ) {
+        {
+            let mut attacker = self.units[&attacker].borrow_mut();
+            let mut defender = self.units[&defender].borrow_mut();
+            attacker.hp = result.attacker_hp;
+            defender.hp = result.defender_hp;
+            attacker.use_equipped_weapon(result.strikes_by(Side::Attacker));
+            defender.use_equipped_weapon(result.strikes_by(Side::Defender));
+            award_combat_exp(&mut attacker, &defender, &mut self.rng);
+            award_combat_exp(&mut defender, &attacker, &mut self.rng);
+        }
+        self.wait(attacker);
+        self.remove_dead_units();
+    }
+
+    // Heals the unit on target with the first staff that reaches it. Returns
+    // false if there is none.
+    pub fn use_staff(&mut self, location: (u32, u32), target: (u32, u32)) -> bool {
+        {
+            let mut unit = self.units[&location].borrow_mut();
+            let distance = pathfinding::manhattan_distance(location, target);
+            let index = match unit.inventory.staff_index_in_range(&unit.class, distance) {
+                Some(index) => index,
+                None => return false,
+            };
+            let heal = match unit.inventory.get(index) {
+                Some(&Item::Staff(ref staff)) => staff.heal + unit.stats.magic,
+                _ => return false,
+            };
+            self.units[&target].borrow_mut().heal(heal);
+            unit.inventory.use_item(index, 1);
+            if unit.faction == Faction::Player {
+                award_exp(&mut unit, action::STAFF_EXP, &mut self.rng);
+            }
+        }
+        self.wait(location);
+        true
+    }
+
+    // Uses a consumable, which ends the unit's action. Returns false if the
+    // item isn't a consumable.
+    pub fn use_item(&mut self, location: (u32, u32), index: usize) -> bool {
+        {
+            let mut unit = self.units[&location].borrow_mut();
+            let heal = match unit.inventory.get(index) {
+                Some(&Item::Consumable(ref consumable)) => consumable.heal,
+                _ => return false,
+            };
+            unit.heal(heal);
+            unit.inventory.use_item(index, 1);
+        }
+        self.wait(location);
+        true
+    }
+
+    // Equipping doesn't use up the unit's action. Returns false if the unit
+    // can't wield the item.
+    pub fn equip(&mut self, location: (u32, u32), index: usize) -> bool {
+        let equipped = {
+            let mut unit = self.units[&location].borrow_mut();
+            let class = unit.class;
+            unit.inventory.equip(index, &class)
+        };
+        // Weapons decide attack ranges
+        self.recompute_unit_paths();
+        equipped
+    }
+
+    // Moves an item between the unit on location and partner if the receiver
+    // has room. Returns false if nothing moved.
+    pub fn trade(&mut self, location: (u32, u32), partner: (u32, u32), entry: TradeEntry) -> bool {
+        let traded = {
+            let mut unit = self.units[&location].borrow_mut();
+            let mut partner_unit = self.units[&partner].borrow_mut();
+            let (giver, receiver, index) = match entry {
+                TradeEntry::Give(index) => (&mut *unit, &mut *partner_unit, index),
+                TradeEntry::Take(index) => (&mut *partner_unit, &mut *unit, index),
+            };
+            if receiver.inventory.is_full() {
+                false
+            } else {
+                match giver.inventory.remove(index) {
+                    Some(item) => {
+                        receiver.inventory.add(item).unwrap();
+                        true
+                    }
+                    None => false,
+                }
+            }
+        };
+        self.recompute_unit_paths();
+        traded
+    }
+
+    pub fn seize(&mut self, location: (u32, u32)) {
+        self.wait(location);
+        self.outcome = Some(Outcome::Victory);
+    }
+
+    // The unit on location is done for the turn.
+    pub fn wait(&mut self, location: (u32, u32)) {
+        if let Some(unit) = self.units.get(&location) {
+            let mut unit = unit.borrow_mut();
+            unit.has_moved = true;
+            unit.has_acted = true;
+        }
+        self.recompute_unit_paths();
+    }
+
+    pub fn remove_dead_units(&mut self) {
+        self.units.retain(|_, unit| unit.borrow().is_alive());
+        self.recompute_unit_paths();
+        self.check_outcome();
+    }
+
+    // Ends the chapter if one of its defeat conditions or its victory
+    // condition is met. Defeat takes priority.
+    pub fn check_outcome(&mut self) {
+        if self.outcome.is_some() {
+            return;
+        }
+        let turn = self.turn_manager.turn;
+        if self.chapter
+            .defeat
+            .iter()
+            .any(|condition| condition.is_met(&self.units, turn))
+        {
+            self.outcome = Some(Outcome::Defeat);
+        } else if self.chapter.victory.is_met(&self.units, turn) {
+            self.outcome = Some(Outcome::Victory);
+        }
+    }
+
+    pub fn has_units(&self, faction: Faction) -> bool {
+        self.units.values().any(|unit| {
+            let unit = unit.borrow();
+            unit.faction == faction && unit.is_alive()
+        })
+    }
+
+    // True once every unit of the current phase's faction has acted.
+    pub fn is_phase_over(&self) -> bool {
+        let faction = self.turn_manager.phase.faction();
+        self.units
+            .values()
+            .map(|unit| unit.borrow())
+            .filter(|unit| unit.faction == faction)
+            .all(|unit| unit.is_spent())
+    }
+
+    // Ends the current phase and readies the units of the next faction with
+    // units on the map. Returns the new phase.
+    pub fn end_phase(&mut self) -> Phase {
+        let mut turn_manager = self.turn_manager.clone();
+        let turn = turn_manager.turn;
+        let phase = turn_manager.end_phase(|faction| self.has_units(faction));
+        self.turn_manager = turn_manager;
+        // Reinforcements arrive at the start of each turn
+        if self.turn_manager.turn != turn {
+            self.spawn_reinforcements();
+        }
+        // Units whose phase starts recover on forts, gates and thrones
+        for unit in self.units.values() {
+            let mut unit = unit.borrow_mut();
+            if unit.faction == phase.faction() {
+                unit.reset_turn_flags();
+                let heal = unit.stats.hp * self.terrain_at(unit.location).heal_percent() / 100;
+                unit.heal(heal);
+            }
+        }
+        self.check_outcome();
+        phase
+    }
+
+    // Location of the next unit (by id) of the current phase's faction that
+    // hasn't acted.
+    pub fn next_ai_unit(&self) -> Option<(u32, u32)> {
+        let faction = self.turn_manager.phase.faction();
+        self.units
+            .values()
+            .map(|unit| unit.borrow())
+            .filter(|unit| unit.faction == faction && !unit.is_spent())
+            .min_by_key(|unit| unit.id)
+            .map(|unit| unit.location)
+    }
+
+    // Decides what the unit on location does, along with the path to walk to
+    // the decision's destination.
+    pub fn plan_ai_turn(&self, location: (u32, u32)) -> (ai::Decision, Vec<(u32, u32)>) {
+        let unit = self.units[&location].borrow();
+        let movement_class = unit.movement_class;
+        let decision = ai::decide(&unit, &self.units, &self.terrain, |terr| {
+            self.cost_table.cost(terr, movement_class)
+        });
+        let path = pathfinding::get_path(decision.destination, &unit.paths);
+        if path.is_empty() {
+            (decision, vec![location])
+        } else {
+            (decision, path)
+        }
+    }
+
+    // Starts an attack decided by the AI. Units waiting in range turn
+    // aggressive once they attack. Returns None if the target can't be
+    // attacked after all.
+    pub fn resolve_ai_attack(
+        &mut self,
+        location: (u32, u32),
+        target: (u32, u32),
+    ) -> Option<CombatResult> {
+        self.forecast(location, target)?;
+        {
+            let mut unit = self.units[&location].borrow_mut();
+            if unit.behavior == Behavior::WaitInRange {
+                unit.behavior = Behavior::Aggressive;
+            }
+        }
+        self.resolve_attack(location, target)
+    }
+
+    // Where the unit on location can seize the chapter from, if anywhere.
+    fn seize_destination(&self, location: (u32, u32)) -> Option<(u32, u32)> {
+        if self.chapter.victory != VictoryCondition::Seize {
+            return None;
+        }
+        let unit = self.units[&location].borrow();
+        let mut candidates: Vec<(u32, u32)> = unit.reachable_coords.iter().cloned().collect();
+        candidates.push(location);
+        candidates.sort();
+        candidates
+            .into_iter()
+            .find(|&coord| action::can_seize(&unit, self.terrain_at(coord)))
+    }
+
+    // Plays out the whole turn of the unit on location with the AI, without
+    // walking or combat playback. Player units controlled by the AI also
+    // seize the chapter whenever they can.
+    pub fn play_ai_unit(&mut self, location: (u32, u32)) -> Report {
+        let faction = self.units[&location].borrow().faction;
+        let seize = if faction == Faction::Player {
+            self.seize_destination(location)
+        } else {
+            None
+        };
+        if let Some(destination) = seize {
+            let unit = self.move_unit(location, destination);
+            self.seize(destination);
+            let id = unit.borrow().id;
+            return Report {
+                unit: id,
+                from: location,
+                to: destination,
+                action: Action::Seize,
+                target: None,
+                combat: None,
+            };
+        }
+        let (decision, _) = self.plan_ai_turn(location);
+        let to = decision.destination;
+        let unit = self.move_unit(location, to);
+        let id = unit.borrow().id;
+        let (action, target, combat) = match decision.action {
+            AiAction::Attack(target) => match self.resolve_ai_attack(to, target) {
+                Some(result) => {
+                    self.apply_combat(to, target, &result);
+                    (Action::Attack, Some(target), Some(result))
+                }
+                None => {
+                    self.wait(to);
+                    (Action::Wait, None, None)
+                }
+            },
+            AiAction::Heal(target) => {
+                if self.use_staff(to, target) {
+                    (Action::Staff, Some(target), None)
+                } else {
+                    self.wait(to);
+                    (Action::Wait, None, None)
+                }
+            }
+            AiAction::Wait => {
+                self.wait(to);
+                (Action::Wait, None, None)
+            }
+        };
+        Report {
+            unit: id,
+            from: location,
+            to,
+            action,
+            target,
+            combat,
+        }
+    }
+
+    // Carries out a scripted order for the player unit on location. Returns
+    // None without changing anything if the unit can't follow it.
+    pub fn play_order(&mut self, location: (u32, u32), order: &Order) -> Option<Report> {
+        let id = {
+            let unit = self.units.get(&location)?.borrow();
+            if !self.can_move(&unit)
+                || (order.destination != location
+                    && !unit.reachable_coords.contains(&order.destination))
+            {
+                return None;
+            }
+            unit.id
+        };
+        let to = order.destination;
+        self.move_unit(location, to);
+        let combat = match self.act(to, order) {
+            Some(combat) => combat,
+            None => {
+                self.undo_move(to, location);
+                return None;
+            }
+        };
+        Some(Report {
+            unit: id,
+            from: location,
+            to,
+            action: order.action,
+            target: order.target,
+            combat,
+        })
+    }
+
+    // Carries out the action of an order for the unit that has moved to
+    // location, with the fight if it attacked. Returns None if it can't.
+    fn act(&mut self, location: (u32, u32), order: &Order) -> Option<Option<CombatResult>> {
+        let targets = {
+            let unit = self.units[&location].borrow();
+            match order.action {
+                Action::Attack => action::attack_targets(&unit, &self.units),
+                Action::Staff => action::staff_targets(&unit, &self.units),
+                _ => vec![],
+            }
+        };
+        let valid = self.available_actions(location).contains(&order.action)
+            && order.target.map_or(true, |target| targets.contains(&target));
+        if !valid {
+            return None;
+        }
+        match (order.action, order.target) {
+            (Action::Attack, Some(target)) => {
+                let result = self.resolve_attack(location, target)?;
+                self.apply_combat(location, target, &result);
+                Some(Some(result))
+            }
+            (Action::Staff, Some(target)) => {
+                if !self.use_staff(location, target) {
+                    return None;
+                }
+                Some(None)
+            }
+            (Action::Seize, _) => {
+                self.seize(location);
+                Some(None)
+            }
+            (Action::Wait, _) => {
+                self.wait(location);
+                Some(None)
+            }
+            _ => None,
+        }
+    }
+}
+
+// Only player units gain experience.
+fn award_combat_exp(unit: &mut Unit, enemy: &Unit, rng: &mut Rng) {
+    if unit.faction == Faction::Player && unit.is_alive() {
+        let amount = combat::experience(unit.level, enemy.level, !enemy.is_alive());
+        award_exp(unit, amount, rng);
+    }
+}
+
+// Adds experience and rolls stat gains for every level gained.
+fn award_exp(unit: &mut Unit, amount: u32, rng: &mut Rng) {
+    for _ in 0..unit.gain_exp(amount) {
+        let gains = unit.roll_stat_gains(rng);
+        unit.apply_stat_gains(&gains);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chapter::Reinforcement;
+    use testing::{self, placement};
+
+    fn battle(units: Vec<UnitPlacement>, reinforcements: Vec<Reinforcement>) -> Battle {
+        let mut chapter = testing::chapter(6, 6, units);
+        chapter.reinforcements = reinforcements;
+        Battle::new(chapter, testing::cost_table(), 1)
+    }
+
+    #[test]
+    fn test_new_places_units() {
+        let battle = battle(
+            vec![
+                placement(1, Faction::Player, (0, 0), &["Iron Sword"]),
+                placement(2, Faction::Enemy, (5, 5), &["Iron Sword"]),
+            ],
+            vec![],
+        );
+        assert_eq!(battle.units.len(), 2);
+        let unit = battle.units[&(0, 0)].borrow();
+        assert!(unit.reachable_coords.contains(&(2, 3)));
+        assert!(battle.danger_zone.contains_key(&(5, 0)));
+    }
+
+    #[test]
+    fn test_move_and_undo() {
+        let mut battle = battle(
+            vec![
+                placement(1, Faction::Player, (0, 0), &[]),
+                placement(2, Faction::Enemy, (5, 5), &[]),
+            ],
+            vec![],
+        );
+        battle.move_unit((0, 0), (2, 1));
+        assert!(battle.units[&(2, 1)].borrow().has_moved);
+        assert!(!battle.can_move(&battle.units[&(2, 1)].borrow()));
+        battle.undo_move((2, 1), (0, 0));
+        assert!(battle.can_move(&battle.units[&(0, 0)].borrow()));
+        assert!(!battle.units.contains_key(&(2, 1)));
+    }
+
+    #[test]
+    fn test_defeating_last_enemy_wins() {
+        let mut battle = battle(
+            vec![
+                placement(1, Faction::Player, (0, 0), &["Iron Sword"]),
+                placement(2, Faction::Enemy, (1, 0), &[]),
+            ],
+            vec![],
+        );
+        battle.units[&(1, 0)].borrow_mut().hp = 1;
+        // The seeded rng lands the first strike
+        let result = battle.resolve_attack((0, 0), (1, 0)).unwrap();
+        assert_eq!(result.defender_hp, 0);
+        assert_eq!(battle.outcome, None);
+        battle.apply_combat((0, 0), (1, 0), &result);
+        assert!(!battle.units.contains_key(&(1, 0)));
+        assert!(battle.units[&(0, 0)].borrow().is_spent());
+        assert_eq!(battle.outcome, Some(Outcome::Victory));
+    }
+
+    #[test]
+    fn test_level_up_raises_stats() {
+        let mut battle = battle(
+            vec![
+                placement(1, Faction::Player, (0, 0), &["Iron Sword"]),
+                placement(2, Faction::Enemy, (1, 0), &[]),
+                placement(3, Faction::Enemy, (5, 5), &[]),
+            ],
+            vec![],
+        );
+        battle.units[&(0, 0)].borrow_mut().exp = 99;
+        let result = battle.resolve_attack((0, 0), (1, 0)).unwrap();
+        battle.apply_combat((0, 0), (1, 0), &result);
+        let unit = battle.units[&(0, 0)].borrow();
+        assert_eq!(unit.level, 2);
+        assert_ne!(unit.stats, Class::Lord.base_stats());
+        // Current HP goes up along with max HP
+        assert_eq!(unit.stats.hp - unit.hp, Class::Lord.base_stats().hp - result.attacker_hp);
+    }
+
+    #[test]
+    fn test_play_ai_unit() {
+        let mut battle = battle(
+            vec![
+                placement(1, Faction::Player, (0, 0), &[]),
+                placement(2, Faction::Enemy, (3, 0), &["Iron Sword"]),
+            ],
+            vec![],
+        );
+        battle.end_phase();
+        assert_eq!(battle.next_ai_unit(), Some((3, 0)));
+        let report = battle.play_ai_unit((3, 0));
+        assert_eq!(report.unit, 2);
+        assert_eq!(pathfinding::manhattan_distance(report.to, (0, 0)), 1);
+        assert_eq!(report.action, Action::Attack);
+        assert_eq!(report.target, Some((0, 0)));
+        assert!(report.combat.is_some());
+        assert_eq!(battle.next_ai_unit(), None);
+    }
+
+    #[test]
+    fn test_play_order() {
+        let mut battle = battle(
+            vec![
+                placement(1, Faction::Player, (0, 0), &["Iron Sword"]),
+                placement(2, Faction::Enemy, (3, 3), &[]),
+            ],
+            vec![],
+        );
+        let order = |destination, action, target| Order {
+            turn: 1,
+            unit: 1,
+            destination,
+            action,
+            target,
+        };
+        // Out of reach, nothing to attack and not a throne
+        for bad in [
+            order((5, 5), Action::Wait, None),
+            order((1, 0), Action::Attack, Some((3, 3))),
+            order((0, 0), Action::Seize, None),
+        ].iter()
+        {
+            assert_eq!(battle.play_order((0, 0), bad), None);
+            assert!(battle.can_move(&battle.units[&(0, 0)].borrow()));
+        }
+        let report = battle
+            .play_order((0, 0), &order((3, 2), Action::Attack, Some((3, 3))))
+            .unwrap();
+        assert_eq!((report.from, report.to), ((0, 0), (3, 2)));
+        assert!(report.combat.is_some());
+        assert!(battle.units[&(3, 2)].borrow().is_spent());
+    }
+
+    #[test]
+    fn test_play_order_moves_back_when_the_action_fails() {
+        let mut battle = battle(
+            vec![
+                placement(1, Faction::Player, (0, 0), &["Iron Sword"]),
+                placement(2, Faction::Enemy, (3, 3), &[]),
+            ],
+            vec![],
+        );
+        // Attacking is possible from (3, 2), but the order has no target
+        let order = Order {
+            turn: 1,
+            unit: 1,
+            destination: (3, 2),
+            action: Action::Attack,
+            target: None,
+        };
+        assert_eq!(battle.play_order((0, 0), &order), None);
+        assert!(!battle.units.contains_key(&(3, 2)));
+        assert!(battle.can_move(&battle.units[&(0, 0)].borrow()));
+        // So the AI can still take over, as feg-sim does
+        let report = battle.play_ai_unit((0, 0));
+        assert_eq!(report.from, (0, 0));
+    }
+
+    #[test]
+    fn test_end_phase_heals_on_forts() {
+        let mut chapter = testing::chapter(
+            6,
+            6,
+            vec![
+                placement(1, Faction::Player, (0, 0), &[]),
+                placement(2, Faction::Player, (1, 0), &[]),
+                placement(3, Faction::Enemy, (5, 5), &[]),
+            ],
+        );
+        chapter.terrain[0][0] = Terrain::Fort;
+        chapter.terrain[1][0] = Terrain::Fort;
+        chapter.terrain[5][5] = Terrain::Fort;
+        let mut battle = Battle::new(chapter, testing::cost_table(), 1);
+        for unit in battle.units.values() {
+            let mut unit = unit.borrow_mut();
+            unit.stats.hp = 20;
+            unit.hp = 10;
+        }
+        battle.units[&(1, 0)].borrow_mut().hp = 19;
+        // Only the enemy phase starts, so only the enemy heals
+        battle.end_phase();
+        assert_eq!(battle.units[&(0, 0)].borrow().hp, 10);
+        assert_eq!(battle.units[&(5, 5)].borrow().hp, 14);
+        // Healing is capped at max HP
+        battle.end_phase();
+        assert_eq!(battle.units[&(0, 0)].borrow().hp, 14);
+        assert_eq!(battle.units[&(1, 0)].borrow().hp, 20);
+    }
+
+    #[test]
+    fn test_end_phase_brings_reinforcements() {
+        let mut battle = battle(
+            vec![
+                placement(1, Faction::Player, (0, 0), &[]),
+                placement(2, Faction::Enemy, (5, 5), &[]),
+            ],
+            vec![
+                Reinforcement {
+                    turn: 2,
+                    unit: placement(3, Faction::Enemy, (5, 0), &[]),
+                },
+                // Blocked by the enemy already there
+                Reinforcement {
+                    turn: 2,
+                    unit: placement(4, Faction::Enemy, (5, 5), &[]),
+                },
+            ],
+        );
+        assert_eq!(battle.end_phase(), Phase::Enemy);
+        assert_eq!(battle.units.len(), 2);
+        assert_eq!(battle.end_phase(), Phase::Player);
+        assert_eq!(battle.turn_manager.turn, 2);
+        assert_eq!(battle.units.len(), 3);
+        assert_eq!(battle.units[&(5, 0)].borrow().id, 3);
+        assert_eq!(battle.units[&(5, 5)].borrow().id, 2);
+    }
+}
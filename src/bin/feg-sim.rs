@@ -0,0 +1,388 @@
+// Plays chapters out without a window, for balancing. Every unit is moved by
+// the AI unless a script gives it orders. A single run prints a turn by turn
+// log. Several runs use consecutive seeds and print one line per run followed
+// by win rates and casualties.
+//
+//     feg-sim CHAPTER [--seed N] [--turns N] [--runs N] [--script FILE]
+//
+// CHAPTER is found in the resources dir like the game's own files, while the
+// script FILE is opened as given.
+extern crate feg;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::process;
+use feg::battle::{Battle, Outcome, Report};
+use feg::chapter::Chapter;
+use feg::dataparser;
+use feg::script::Script;
+use feg::terrain::CostTable;
+use feg::unit::Faction;
+
+const USAGE: &str = "usage: feg-sim CHAPTER [--seed N] [--turns N] [--runs N] [--script FILE]
+CHAPTER is a file in the resources dir, FILE is a path to a script";
+
+struct Options {
+    chapter: String,
+    seed: u64,
+    // Runs that haven't been won or lost by the end of this turn are
+    // undecided
+    turns: u32,
+    runs: u32,
+    script: Option<String>,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        chapter: String::new(),
+        seed: 0,
+        turns: 30,
+        runs: 1,
+        script: None,
+    };
+    let mut chapter = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            if chapter.is_some() {
+                return Err(format!("unexpected argument {}", arg));
+            }
+            chapter = Some(arg.clone());
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", arg))?;
+        let number = || {
+            value
+                .parse::<u64>()
+                .map_err(|_| format!("bad {} {}", arg, value))
+        };
+        match arg.as_str() {
+            "--seed" => options.seed = number()?,
+            "--turns" => options.turns = number()? as u32,
+            "--runs" => options.runs = number()? as u32,
+            "--script" => options.script = Some(value.clone()),
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+    options.chapter = chapter.ok_or_else(|| "no chapter given".to_string())?;
+    if options.runs == 0 {
+        return Err("--runs must be at least 1".to_string());
+    }
+    Ok(options)
+}
+
+// How a run went.
+struct RunResult {
+    outcome: Option<Outcome>,
+    // Last turn played
+    turn: u32,
+    // Ids of the player units that were defeated
+    player_losses: Vec<u32>,
+    enemy_losses: u32,
+}
+
+fn outcome_name(outcome: Option<Outcome>) -> &'static str {
+    match outcome {
+        Some(Outcome::Victory) => "Victory",
+        Some(Outcome::Defeat) => "Defeat",
+        None => "Undecided",
+    }
+}
+
+fn describe(report: &Report, names: &BTreeMap<u32, (Faction, String)>) -> String {
+    let mut line = format!(
+        "  {} {}: {:?} -> {:?}, {}",
+        names[&report.unit].1,
+        report.unit,
+        report.from,
+        report.to,
+        report.action.label()
+    );
+    if let Some(target) = report.target {
+        line += &format!(" {:?}", target);
+    }
+    if let Some(ref combat) = report.combat {
+        line += &format!(" ({} HP vs {} HP)", combat.attacker_hp, combat.defender_hp);
+    }
+    line
+}
+
+// Notes the units on the map that haven't been seen before, logging their
+// arrival if log is set.
+fn note_units(battle: &Battle, names: &mut BTreeMap<u32, (Faction, String)>, log: bool) {
+    let mut arrivals: Vec<(u32, (u32, u32))> = battle
+        .units
+        .iter()
+        .map(|(&location, unit)| (unit.borrow().id, location))
+        .filter(|&(id, _)| !names.contains_key(&id))
+        .collect();
+    arrivals.sort();
+    for (id, location) in arrivals {
+        let unit = battle.units[&location].borrow();
+        let name = unit.class.name().to_string();
+        if log {
+            println!("  {} {} arrives at {:?}", name, id, location);
+        }
+        names.insert(id, (unit.faction, name));
+    }
+}
+
+fn run(
+    chapter: &Chapter,
+    cost_table: &CostTable,
+    script: &Script,
+    seed: u64,
+    turns: u32,
+    log: bool,
+) -> RunResult {
+    let mut battle = Battle::new(chapter.clone(), cost_table.clone(), seed);
+    let mut names = BTreeMap::new();
+    note_units(&battle, &mut names, false);
+    if log {
+        println!("Turn 1, {}", battle.turn_manager.phase.name());
+    }
+    while battle.outcome.is_none() && battle.turn_manager.turn <= turns {
+        let location = match battle.next_ai_unit() {
+            Some(location) => location,
+            None => {
+                let phase = battle.end_phase();
+                // Nothing is logged past the last turn
+                let log_phase = log && battle.turn_manager.turn <= turns;
+                if log_phase {
+                    println!("Turn {}, {}", battle.turn_manager.turn, phase.name());
+                }
+                note_units(&battle, &mut names, log_phase);
+                continue;
+            }
+        };
+        let id = battle.units[&location].borrow().id;
+        let order = script.order_for(battle.turn_manager.turn, id);
+        let report = match order.and_then(|order| battle.play_order(location, order)) {
+            Some(report) => report,
+            None => {
+                if log && order.is_some() {
+                    println!("  Unit {} can't follow its order, the AI moves it", id);
+                }
+                battle.play_ai_unit(location)
+            }
+        };
+        if log {
+            println!("{}", describe(&report, &names));
+        }
+    }
+
+    let alive: Vec<u32> = battle.units.values().map(|unit| unit.borrow().id).collect();
+    let lost = |faction: Faction| -> Vec<u32> {
+        names
+            .iter()
+            .filter(|&(id, &(unit_faction, _))| unit_faction == faction && !alive.contains(id))
+            .map(|(&id, _)| id)
+            .collect()
+    };
+    RunResult {
+        outcome: battle.outcome,
+        turn: battle.turn_manager.turn.min(turns),
+        player_losses: lost(Faction::Player),
+        enemy_losses: lost(Faction::Enemy).len() as u32,
+    }
+}
+
+fn percent(count: u32, total: u32) -> f32 {
+    100.0 * count as f32 / total as f32
+}
+
+// Win rates and casualties over several runs, one line per item.
+fn summary(results: &[RunResult], names: &BTreeMap<u32, String>) -> Vec<String> {
+    let runs = results.len() as u32;
+    let mut lines = vec![format!("{} runs", runs)];
+    for &outcome in [Some(Outcome::Victory), Some(Outcome::Defeat), None].iter() {
+        let count = results
+            .iter()
+            .filter(|result| result.outcome == outcome)
+            .count() as u32;
+        lines.push(format!(
+            "  {:<10} {:>6} ({:.1}%)",
+            outcome_name(outcome),
+            count,
+            percent(count, runs)
+        ));
+    }
+    let total_turns: u32 = results.iter().map(|result| result.turn).sum();
+    lines.push(format!(
+        "Average turns: {:.1}",
+        total_turns as f32 / runs as f32
+    ));
+    let enemy_losses: u32 = results.iter().map(|result| result.enemy_losses).sum();
+    lines.push(format!(
+        "Average enemies defeated: {:.1}",
+        enemy_losses as f32 / runs as f32
+    ));
+
+    let mut loss_counts = BTreeMap::new();
+    let mut deaths = BTreeMap::new();
+    for result in results {
+        *loss_counts.entry(result.player_losses.len()).or_insert(0) += 1;
+        for &id in result.player_losses.iter() {
+            *deaths.entry(id).or_insert(0) += 1;
+        }
+    }
+    lines.push("Player units lost per run:".to_string());
+    for (losses, &count) in loss_counts.iter() {
+        lines.push(format!(
+            "  {:<10} {:>6} ({:.1}%)",
+            losses,
+            count,
+            percent(count, runs)
+        ));
+    }
+    if !deaths.is_empty() {
+        lines.push("Deaths by unit:".to_string());
+        for (id, &count) in deaths.iter() {
+            let name = format!("{} {}", names[id], id);
+            lines.push(format!(
+                "  {:<10} {:>6} ({:.1}%)",
+                name,
+                count,
+                percent(count, runs)
+            ));
+        }
+    }
+    lines
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_options(&args) {
+        Ok(options) => options,
+        Err(message) => {
+            println!("{}\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+    let loaded = dataparser::parse_chapter(&options.chapter).and_then(|chapter| {
+        let cost_table = dataparser::parse_cost_table("movement_costs.txt")?;
+        let script = match options.script {
+            Some(ref path) => dataparser::parse_script(path)?,
+            None => Script::default(),
+        };
+        Ok((chapter, cost_table, script))
+    });
+    let (chapter, cost_table, script) = match loaded {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            println!("{}", err);
+            process::exit(1);
+        }
+    };
+
+    let log = options.runs == 1;
+    let mut results = vec![];
+    for run_index in 0..options.runs {
+        let seed = options.seed + run_index as u64;
+        let result = run(&chapter, &cost_table, &script, seed, options.turns, log);
+        println!(
+            "Seed {}: {} on turn {}, player losses {}",
+            seed,
+            outcome_name(result.outcome),
+            result.turn,
+            result.player_losses.len()
+        );
+        results.push(result);
+    }
+    if options.runs > 1 {
+        let names = chapter
+            .units
+            .iter()
+            .chain(
+                chapter
+                    .reinforcements
+                    .iter()
+                    .map(|reinforcement| &reinforcement.unit),
+            )
+            .map(|unit| (unit.id, unit.class.name().to_string()))
+            .collect();
+        println!();
+        for line in summary(&results, &names) {
+            println!("{}", line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = parse_options(&args("chapter1.ron")).unwrap();
+        assert_eq!(options.chapter, "chapter1.ron");
+        assert_eq!((options.seed, options.turns, options.runs), (0, 30, 1));
+        assert_eq!(options.script, None);
+
+        let options = parse_options(&args(
+            "--seed 7 chapter1.ron --turns 10 --runs 50 --script plans/ch1.txt",
+        )).unwrap();
+        assert_eq!(options.chapter, "chapter1.ron");
+        assert_eq!((options.seed, options.turns, options.runs), (7, 10, 50));
+        assert_eq!(options.script, Some("plans/ch1.txt".to_string()));
+    }
+
+    #[test]
+    fn test_parse_options_invalid() {
+        let invalid = [
+            "",
+            "--seed 7",
+            "chapter1.ron chapter2.ron",
+            "chapter1.ron --seed",
+            "chapter1.ron --seed seven",
+            "chapter1.ron --runs 0",
+            "chapter1.ron --speed 2",
+        ];
+        for line in invalid.iter() {
+            assert!(parse_options(&args(line)).is_err(), "{:?} parsed", line);
+        }
+    }
+
+    #[test]
+    fn test_summary() {
+        let result = |outcome, turn, player_losses: Vec<u32>, enemy_losses| RunResult {
+            outcome,
+            turn,
+            player_losses,
+            enemy_losses,
+        };
+        let results = vec![
+            result(Some(Outcome::Victory), 8, vec![], 6),
+            result(Some(Outcome::Victory), 10, vec![2], 6),
+            result(Some(Outcome::Defeat), 5, vec![1, 2], 3),
+            result(None, 31, vec![], 1),
+        ];
+        let names = vec![(1, "Lord".to_string()), (2, "Archer".to_string())]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            summary(&results, &names),
+            vec![
+                "4 runs",
+                "  Victory         2 (50.0%)",
+                "  Defeat          1 (25.0%)",
+                "  Undecided       1 (25.0%)",
+                "Average turns: 13.5",
+                "Average enemies defeated: 4.0",
+                "Player units lost per run:",
+                "  0               2 (50.0%)",
+                "  1               1 (25.0%)",
+                "  2               1 (25.0%)",
+                "Deaths by unit:",
+                "  Lord 1          1 (25.0%)",
+                "  Archer 2        2 (50.0%)",
+            ]
+        );
+    }
+}
@@ -0,0 +1,672 @@
+// Plays a chapter in the terminal, for playing and debugging maps over SSH.
+// Input is read a line at a time and every character of a line is a command
+// (see HELP), so "ddx" moves the cursor two cells right and selects. The map
+// is redrawn after every line. Lines starting with ':' are save commands
+// instead. A suspended chapter is resumed on the next start. Units are
+// selected and ordered around through a feg::session, like in the window.
+//
+//     feg-term [CHAPTER] [--seed N]
+extern crate feg;
+#[cfg(test)]
+extern crate num;
+
+use std::collections::HashSet;
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+use feg::battle::{Battle, Outcome, Report};
+use feg::combat::{CombatResult, Side, SideForecast};
+use feg::dataparser;
+use feg::pathfinding;
+use feg::save::{self, Slot};
+use feg::session::{Event, Mode, Session};
+use feg::terrain::Terrain;
+use feg::turn::Phase;
+use feg::unit::{Faction, Unit};
+
+const HELP: &str = "\
+w a s d  move the cursor, or the menu cursor
+x        select a unit, move it, or choose the menu entry
+c        cancel
+n        jump to the next unit that can move
+u        undo the selected unit's move
+r        redo the move that was undone
+e        end the player phase
+z        show the danger zone
+?        show this help
+q        quit
+:save N  save to slot N
+:load N  load slot N
+:suspend save and quit, to carry on next time
+:rewind N take back the last N actions of the phase, a few times a chapter";
+
+struct Term {
+    session: Session,
+    show_danger_zone: bool,
+    // What happened since the last redraw
+    messages: Vec<String>,
+    save_dir: PathBuf,
+}
+
+fn faction_char(faction: Faction) -> char {
+    match faction {
+        Faction::Player => 'P',
+        Faction::Enemy => 'E',
+        Faction::Other => 'O',
+    }
+}
+
+fn describe_unit(unit: &Unit) -> String {
+    let weapon = unit
+        .equipped_weapon()
+        .map_or("no weapon".to_string(), |weapon| weapon.name.clone());
+    format!(
+        "{} {} ({:?})  HP {}/{}  Lv {}  {}",
+        unit.class.name(),
+        unit.id,
+        unit.faction,
+        unit.hp,
+        unit.stats.hp,
+        unit.level,
+        weapon
+    )
+}
+
+fn describe_forecast(side: &SideForecast) -> String {
+    let attacks = if side.attacks > 1 { " x2" } else { "" };
+    format!(
+        "HP {}  Dmg {}{}  Hit {}  Crit {}",
+        side.hp, side.damage, attacks, side.hit, side.crit
+    )
+}
+
+fn describe_combat(result: &CombatResult) -> String {
+    let strikes: Vec<String> = result
+        .strikes
+        .iter()
+        .map(|strike| {
+            let striker = match strike.striker {
+                Side::Attacker => "attacker",
+                Side::Defender => "defender",
+            };
+            if !strike.hit {
+                format!("{} misses", striker)
+            } else if strike.crit {
+                format!("{} crits for {}", striker, strike.damage)
+            } else {
+                format!("{} hits for {}", striker, strike.damage)
+            }
+        })
+        .collect();
+    format!(
+        "{} ({} HP vs {} HP)",
+        strikes.join(", "),
+        result.attacker_hp,
+        result.defender_hp
+    )
+}
+
+fn describe_report(report: &Report) -> String {
+    let mut line = format!(
+        "Unit {}: {:?} -> {:?}, {}",
+        report.unit,
+        report.from,
+        report.to,
+        report.action.label()
+    );
+    if let Some(target) = report.target {
+        line += &format!(" {:?}", target);
+    }
+    if let Some(ref combat) = report.combat {
+        line += &format!(": {}", describe_combat(combat));
+    }
+    line
+}
+
+impl Term {
+    fn new(battle: Battle, save_dir: &Path) -> Self {
+        Term {
+            session: Session::new(battle),
+            show_danger_zone: false,
+            messages: vec![],
+            save_dir: save_dir.to_path_buf(),
+        }
+    }
+
+    // Handles a line of input. Returns false to quit.
+    fn input(&mut self, line: &str) -> bool {
+        let line = line.trim();
+        if line.starts_with(':') {
+            self.command(&line[1..])
+        } else {
+            line.chars().all(|command| self.press(command))
+        }
+    }
+
+    // Handles a save command. Returns false to quit.
+    fn command(&mut self, command: &str) -> bool {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let number = words.get(1).and_then(|number| number.parse::<u32>().ok());
+        match (words.get(0).cloned(), number) {
+            (Some("save"), Some(number)) => {
+                // A move that hasn't been acted on yet can't be saved
+                if let Mode::Normal = self.session.mode {
+                    self.save(Slot::Numbered(number));
+                } else {
+                    self.messages
+                        .push("Finish or cancel the current move first".to_string());
+                }
+            }
+            (Some("load"), Some(number)) => {
+                let cost_table = self.session.battle.cost_table.clone();
+                match save::load(&self.save_dir, Slot::Numbered(number), cost_table) {
+                    Ok(battle) => {
+                        self.session = Session::new(battle);
+                        self.messages.push(format!("Loaded slot {}", number));
+                    }
+                    Err(err) => self
+                        .messages
+                        .push(format!("Couldn't load slot {}: {}", number, err)),
+                }
+            }
+            (Some("suspend"), None) => {
+                if self.session.battle.outcome.is_some() {
+                    return false;
+                }
+                self.session.abandon_move();
+                return !self.save(Slot::Suspend);
+            }
+            (Some("rewind"), Some(actions)) => self.rewind(actions as usize),
+            _ => self
+                .messages
+                .push(format!("Unknown command :{}, ? for help", command)),
+        }
+        true
+    }
+
+    // Returns whether the battle was saved.
+    fn save(&mut self, slot: Slot) -> bool {
+        match save::save(&self.session.battle, &self.save_dir, slot) {
+            Ok(()) => {
+                let message = match slot {
+                    Slot::Numbered(number) => format!("Saved to slot {}", number),
+                    Slot::Suspend => "Suspended".to_string(),
+                };
+                self.messages.push(message);
+                true
+            }
+            Err(err) => {
+                self.messages.push(format!("Couldn't save: {}", err));
+                false
+            }
+        }
+    }
+
+    // Handles one command character. Returns false to quit.
+    fn press(&mut self, command: char) -> bool {
+        match command {
+            'q' => return false,
+            'w' => self.session.step_cursor((0, -1)),
+            'a' => self.session.step_cursor((-1, 0)),
+            's' => self.session.step_cursor((0, 1)),
+            'd' => self.session.step_cursor((1, 0)),
+            '?' => self.messages.push(HELP.to_string()),
+            'z' => self.show_danger_zone = !self.show_danger_zone,
+            _ if self.session.battle.outcome.is_some() => (),
+            'x' => self.confirm(),
+            'c' => self.session.cancel(),
+            'n' => self.session.select_next_unit(),
+            'u' => {
+                self.session.undo();
+            }
+            'r' => {
+                if self.session.is_idle() && !self.session.redo() {
+                    self.messages.push("Nothing to redo".to_string());
+                }
+            }
+            'e' => {
+                if self.session.is_idle() {
+                    self.end_player_phase();
+                }
+            }
+            _ => self
+                .messages
+                .push(format!("Unknown command {:?}, ? for help", command)),
+        }
+        if self.session.battle.outcome.is_none() && self.session.battle.is_phase_over() {
+            self.end_player_phase();
+        }
+        true
+    }
+
+    // Confirms, carrying out walks and fights straight away since there is
+    // nothing to animate.
+    fn confirm(&mut self) {
+        match self.session.confirm() {
+            Some(Event::Move(path)) => self.session.finish_move(path[path.len() - 1]),
+            Some(Event::Attack(attacker, defender, result)) => {
+                self.messages.push(describe_combat(&result));
+                self.session.finish_attack(attacker, defender, &result);
+            }
+            None => (),
+        }
+    }
+
+    fn rewind(&mut self, actions: usize) {
+        if !self.session.is_idle() {
+            self.messages
+                .push("Finish or cancel the current move first".to_string());
+        } else if self.session.battle.rewinds == 0 {
+            self.messages.push("No rewinds left".to_string());
+        } else if self.session.rewind(actions) {
+            self.messages.push(format!(
+                "Rewound {} actions, {} rewinds left",
+                actions, self.session.battle.rewinds
+            ));
+        } else {
+            self.messages.push(format!(
+                "Only {} actions can be rewound",
+                self.session.history.actions()
+            ));
+        }
+    }
+
+    // Ends the player phase and lets the AI play every other phase until
+    // it's the player's turn again.
+    fn end_player_phase(&mut self) {
+        let mut phase = self.session.end_phase();
+        let battle = &mut self.session.battle;
+        while battle.outcome.is_none()
+            && phase != Phase::Player
+            && battle.has_units(Faction::Player)
+        {
+            self.messages.push(format!(
+                "Turn {}, {}",
+                battle.turn_manager.turn,
+                phase.name()
+            ));
+            while let Some(location) = battle.next_ai_unit() {
+                let report = battle.play_ai_unit(location);
+                self.messages.push(describe_report(&report));
+                if battle.outcome.is_some() {
+                    return;
+                }
+            }
+            phase = battle.end_phase();
+        }
+    }
+
+    // The map and everything around it, as it would be printed.
+    fn render(&self) -> String {
+        let session = &self.session;
+        let battle = &session.battle;
+        let mut lines = vec![];
+        lines.push(format!(
+            "{}  Turn {}  {}",
+            battle.chapter.name,
+            battle.turn_manager.turn,
+            battle.turn_manager.phase.name()
+        ));
+
+        // Cells highlighted around their terrain or unit
+        let mut reachable = HashSet::new();
+        let mut attackable = HashSet::new();
+        let mut path = HashSet::new();
+        match session.mode {
+            Mode::Normal => {
+                if let Some(location) = session.selected {
+                    let unit = battle.units[&location].borrow();
+                    reachable = unit.reachable_coords.clone();
+                    attackable = unit.attack_coords.clone();
+                    if battle.can_move(&unit) {
+                        path = pathfinding::get_path(session.cursor, &unit.paths)
+                            .into_iter()
+                            .collect();
+                    }
+                }
+            }
+            Mode::SelectTarget(_, ref menu) => {
+                attackable = menu.values().into_iter().cloned().collect();
+            }
+            _ => (),
+        }
+
+        let mut header = "   ".to_string();
+        for x in 0..battle.width {
+            header += &format!("{:^3}", x % 100);
+        }
+        lines.push(header);
+        for y in 0..battle.height {
+            let mut line = format!("{:>2} ", y);
+            for x in 0..battle.width {
+                let coord = (x, y);
+                let content = match battle.units.get(&coord) {
+                    Some(unit) => {
+                        let unit = unit.borrow();
+                        let ch = faction_char(unit.faction);
+                        if unit.is_spent() {
+                            ch.to_ascii_lowercase()
+                        } else {
+                            ch
+                        }
+                    }
+                    None => match battle.terrain_at(coord) {
+                        Terrain::None => '.',
+                        terrain => terrain.to_char(),
+                    },
+                };
+                let (left, right) = if coord == session.cursor {
+                    ('[', ']')
+                } else if path.contains(&coord) {
+                    ('*', ' ')
+                } else if reachable.contains(&coord) {
+                    ('+', ' ')
+                } else if attackable.contains(&coord) {
+                    ('!', ' ')
+                } else if self.show_danger_zone && battle.danger_zone.contains_key(&coord) {
+                    ('#', ' ')
+                } else {
+                    (' ', ' ')
+                };
+                line.push(left);
+                line.push(content);
+                line.push(right);
+            }
+            lines.push(line);
+        }
+
+        let terrain = battle.terrain_at(session.cursor);
+        let mut status = format!("{:?} {}", session.cursor, terrain.name());
+        if let Some(unit) = battle.units.get(&session.cursor) {
+            status += &format!("  {}", describe_unit(&unit.borrow()));
+        }
+        lines.push(status);
+
+        if let Some((labels, cursor)) = session.menu_labels() {
+            for (index, label) in labels.iter().enumerate() {
+                let marker = if index == cursor { '>' } else { ' ' };
+                lines.push(format!("  {} {}", marker, label));
+            }
+        }
+        if let Some(forecast) = session.forecast() {
+            lines.push(format!("  You   {}", describe_forecast(&forecast.attacker)));
+            lines.push(format!("  Enemy {}", describe_forecast(&forecast.defender)));
+        }
+
+        match battle.outcome {
+            Some(Outcome::Victory) => lines.push("Chapter Clear".to_string()),
+            Some(Outcome::Defeat) => lines.push("Game Over".to_string()),
+            None => (),
+        }
+        lines.join("\n")
+    }
+}
+
+fn main() {
+    let mut chapter_path = "chapter1.ron".to_string();
+    let mut seed = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--seed" {
+            seed = args.next().and_then(|value| value.parse().ok());
+            if seed.is_none() {
+                println!("--seed needs a number");
+                process::exit(2);
+            }
+        } else {
+            chapter_path = arg;
+        }
+    }
+    let seed = seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)
+    });
+
+    let loaded = dataparser::parse_chapter(&chapter_path).and_then(|chapter| {
+        let cost_table = dataparser::parse_cost_table("movement_costs.txt")?;
+        Ok((chapter, cost_table))
+    });
+    let (chapter, cost_table) = match loaded {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            println!("{}", err);
+            process::exit(1);
+        }
+    };
+
+    let save_dir = Path::new(save::SAVE_DIR);
+    let battle = if save::exists(save_dir, Slot::Suspend) {
+        match save::load(save_dir, Slot::Suspend, cost_table.clone()) {
+            Ok(battle) => {
+                println!("Resuming the suspended chapter");
+                battle
+            }
+            Err(err) => {
+                println!("Couldn't resume the suspended chapter: {}", err);
+                Battle::new(chapter, cost_table, seed)
+            }
+        }
+    } else {
+        Battle::new(chapter, cost_table, seed)
+    };
+    let mut term = Term::new(battle, save_dir);
+    println!("{}\n", HELP);
+    let stdin = io::stdin();
+    loop {
+        for message in term.messages.drain(..) {
+            println!("{}", message);
+        }
+        println!("{}", term.render());
+        print!("> ");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+        if !term.input(&line) {
+            break;
+        }
+    }
+    for message in term.messages.drain(..) {
+        println!("{}", message);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use feg::ai::Behavior;
+    use feg::chapter::{Chapter, DefeatCondition, UnitPlacement, VictoryCondition};
+    use feg::class::Class;
+    use feg::history;
+    use feg::item::{Inventory, Item};
+    use feg::terrain::{CostTable, MovementClass};
+    use num::rational::Ratio;
+
+    // A level 1 Lord carrying the named items.
+    fn placement(id: u32, faction: Faction, location: (u32, u32), items: &[&str]) -> UnitPlacement {
+        let mut inventory = Inventory::new();
+        for name in items {
+            inventory.add(Item::by_name(name).unwrap()).unwrap();
+        }
+        UnitPlacement {
+            id,
+            faction,
+            class: Class::Lord,
+            level: 1,
+            location,
+            inventory,
+            behavior: Behavior::Aggressive,
+        }
+    }
+
+    // An open 8x3 field with a forest at (2, 1), won by defeating every enemy.
+    fn term(units: Vec<UnitPlacement>) -> Term {
+        let mut terrain = vec![vec![Terrain::None; 3]; 8];
+        terrain[2][1] = Terrain::Forest;
+        let chapter = Chapter {
+            name: "Test".to_string(),
+            width: 8,
+            height: 3,
+            terrain,
+            zone_of_control: false,
+            units,
+            victory: VictoryCondition::Rout,
+            defeat: vec![DefeatCondition::AllUnitsDie],
+            reinforcements: vec![],
+        };
+        let mut cost_table = CostTable::new();
+        cost_table.set_cost(Terrain::None, MovementClass::Infantry, Ratio::from_integer(1));
+        cost_table.set_cost(Terrain::Forest, MovementClass::Infantry, Ratio::from_integer(2));
+        let battle = Battle::new(chapter, cost_table, 1);
+        let save_dir = env::temp_dir().join(format!("feg-term-{}", process::id()));
+        Term::new(battle, &save_dir)
+    }
+
+    fn press_all(term: &mut Term, commands: &str) {
+        for command in commands.chars() {
+            term.press(command);
+        }
+    }
+
+    #[test]
+    fn test_render_map() {
+        let term = term(vec![
+            placement(1, Faction::Player, (0, 0), &[]),
+            placement(2, Faction::Enemy, (7, 2), &[]),
+        ]);
+        let render = term.render();
+        let lines: Vec<&str> = render.lines().collect();
+        assert_eq!(lines[0], "Test  Turn 1  Player Phase");
+        assert_eq!(lines[2], " 0 [P] .  .  .  .  .  .  . ");
+        assert_eq!(lines[3], " 1  .  .  f  .  .  .  .  . ");
+        assert_eq!(lines[4], " 2  .  .  .  .  .  .  .  E ");
+        assert!(lines[5].starts_with("(0, 0) none  lord 1 (Player)"));
+    }
+
+    #[test]
+    fn test_render_selection() {
+        let mut term = term(vec![
+            placement(1, Faction::Player, (0, 0), &[]),
+            placement(2, Faction::Enemy, (7, 2), &[]),
+        ]);
+        press_all(&mut term, "xdd");
+        let render = term.render();
+        let lines: Vec<&str> = render.lines().collect();
+        assert_eq!(lines[2], " 0 *P *. [.]+. +. +.  .  . ");
+        assert_eq!(lines[3], " 1 +. +. +f +. +.  .  .  . ");
+    }
+
+    #[test]
+    fn test_move_and_wait_ends_phase() {
+        let mut term = term(vec![
+            placement(1, Faction::Player, (0, 0), &[]),
+            placement(2, Faction::Enemy, (7, 2), &[]),
+        ]);
+        // Select, move two cells right and wait, the only action
+        press_all(&mut term, "xddx");
+        match term.session.mode {
+            Mode::ActionMenu(ref menu) => assert_eq!(menu.labels(), vec!["Wait"]),
+            ref mode => panic!("{:?}", mode),
+        }
+        term.press('x');
+        assert!(term.session.battle.units[&(2, 0)].borrow().id == 1);
+        // The enemy phase was played out
+        assert_eq!(term.session.battle.turn_manager.turn, 2);
+        assert_eq!(term.session.battle.turn_manager.phase, Phase::Player);
+        assert!(term.session.battle.can_move(&term.session.battle.units[&(2, 0)].borrow()));
+        assert!(!term.session.battle.units.contains_key(&(7, 2)));
+    }
+
+    #[test]
+    fn test_attack() {
+        let mut term = term(vec![
+            placement(1, Faction::Player, (0, 0), &["Iron Sword"]),
+            placement(2, Faction::Enemy, (2, 0), &[]),
+            placement(3, Faction::Enemy, (7, 2), &[]),
+        ]);
+        term.session.battle.units[&(2, 0)].borrow_mut().hp = 1;
+        // Move next to the enemy and attack it
+        press_all(&mut term, "xdx");
+        match term.session.mode {
+            Mode::ActionMenu(ref menu) => assert_eq!(menu.labels()[0], "Attack"),
+            ref mode => panic!("{:?}", mode),
+        }
+        press_all(&mut term, "x");
+        assert!(term.render().contains("  You   HP"));
+        press_all(&mut term, "x");
+        assert!(!term.session.battle.units.contains_key(&(2, 0)));
+        assert!(term.messages[0].starts_with("attacker hits"));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut term = term(vec![
+            placement(1, Faction::Player, (0, 0), &[]),
+            placement(2, Faction::Player, (0, 2), &[]),
+            placement(3, Faction::Enemy, (7, 2), &[]),
+        ]);
+        term.save_dir = term.save_dir.join("save_and_load");
+        press_all(&mut term, "xddxx");
+        assert!(term.input(":save 1"));
+        assert_eq!(term.messages.last().unwrap(), "Saved to slot 1");
+        // Move the other unit and wait, then go back to the save
+        term.input("ndxx");
+        assert!(term.session.battle.units.contains_key(&(1, 2)));
+        term.input(":load 1");
+        assert!(term.session.battle.units.contains_key(&(2, 0)));
+        assert!(term.session.battle.units.contains_key(&(0, 2)));
+        assert!(!term.session.battle.units.contains_key(&(1, 2)));
+        assert!(term.session.battle.units[&(2, 0)].borrow().has_acted);
+
+        // Suspending undoes the move in progress and quits
+        term.input("ndx");
+        assert!(!term.input(":suspend"));
+        assert!(save::exists(&term.save_dir, Slot::Suspend));
+        let cost_table = term.session.battle.cost_table.clone();
+        let battle = save::load(&term.save_dir, Slot::Suspend, cost_table).unwrap();
+        assert!(battle.units.contains_key(&(0, 2)));
+        assert!(!save::exists(&term.save_dir, Slot::Suspend));
+        fs::remove_dir_all(&term.save_dir).unwrap();
+    }
+
+    #[test]
+    fn test_undo_redo_and_rewind() {
+        let mut term = term(vec![
+            placement(1, Faction::Player, (0, 0), &[]),
+            placement(2, Faction::Player, (0, 2), &[]),
+            placement(3, Faction::Player, (5, 0), &[]),
+            placement(4, Faction::Enemy, (7, 2), &[]),
+        ]);
+        press_all(&mut term, "xdxu");
+        assert!(term.session.battle.units.contains_key(&(0, 0)));
+        assert_eq!(term.session.selected, Some((0, 0)));
+        term.press('r');
+        assert!(term.session.battle.units.contains_key(&(1, 0)));
+        assert_eq!(term.session.selected, Some((1, 0)));
+        match term.session.mode {
+            Mode::ActionMenu(_) => (),
+            ref mode => panic!("{:?}", mode),
+        }
+
+        // Wait with both units, then take both actions back
+        press_all(&mut term, "xndx");
+        term.press('x');
+        assert!(term.session.battle.units.contains_key(&(1, 2)));
+        term.input(":rewind 3");
+        assert_eq!(
+            term.messages.last().unwrap(),
+            "Only 2 actions can be rewound"
+        );
+        term.input(":rewind 2");
+        assert!(term.session.battle.units.contains_key(&(0, 0)));
+        assert!(term.session.battle.units.contains_key(&(0, 2)));
+        assert!(term.session.battle.can_move(&term.session.battle.units[&(0, 0)].borrow()));
+        assert_eq!(term.session.battle.rewinds, history::DEFAULT_REWINDS - 1);
+    }
+}
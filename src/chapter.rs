@@ -0,0 +1,149 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use ai::Behavior;
+use class::Class;
+use item::Inventory;
+use terrain::Terrain;
+use unit::{Faction, Unit};
+
+// How the player wins the chapter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VictoryCondition {
+    // A lord uses the Seize action on the throne
+    Seize,
+    // Defeat every enemy unit
+    Rout,
+    // Last the given number of turns
+    Survive(u32),
+}
+
+impl VictoryCondition {
+    // Checked whenever units die and at the start of every phase. Seizing is
+    // handled by the Seize action itself.
+    pub fn is_met(&self, units: &HashMap<(u32, u32), Rc<RefCell<Unit>>>, turn: u32) -> bool {
+        match self {
+            &VictoryCondition::Seize => false,
+            &VictoryCondition::Rout => !units
+                .values()
+                .any(|unit| unit.borrow().faction == Faction::Enemy),
+            &VictoryCondition::Survive(turns) => turn > turns,
+        }
+    }
+}
+
+// How the player loses the chapter. Any one of them is enough.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DefeatCondition {
+    // Every player unit is defeated
+    AllUnitsDie,
+    // The unit with the given id is defeated
+    UnitDies(u32),
+    // The chapter isn't won by the end of the given turn
+    TurnLimit(u32),
+}
+
+impl DefeatCondition {
+    pub fn is_met(&self, units: &HashMap<(u32, u32), Rc<RefCell<Unit>>>, turn: u32) -> bool {
+        match self {
+            &DefeatCondition::AllUnitsDie => !units
+                .values()
+                .any(|unit| unit.borrow().faction == Faction::Player),
+            &DefeatCondition::UnitDies(id) => !units.values().any(|unit| unit.borrow().id == id),
+            &DefeatCondition::TurnLimit(turns) => turn > turns,
+        }
+    }
+}
+
+// A unit to put on the map.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnitPlacement {
+    pub id: u32,
+    pub faction: Faction,
+    pub class: Class,
+    pub level: u32,
+    pub location: (u32, u32),
+    pub inventory: Inventory,
+    pub behavior: Behavior,
+}
+
+// A unit that arrives at the start of the given turn. It doesn't arrive if
+// its location is occupied at that point.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Reinforcement {
+    pub turn: u32,
+    pub unit: UnitPlacement,
+}
+
+// Everything needed to start a chapter, as loaded by
+// dataparser::parse_chapter().
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Chapter {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    // 2d vec (x, y) of terrain features
+    pub terrain: Vec<Vec<Terrain>>,
+    pub zone_of_control: bool,
+    // Units on the map at the start of the chapter
+    pub units: Vec<UnitPlacement>,
+    pub victory: VictoryCondition,
+    pub defeat: Vec<DefeatCondition>,
+    pub reinforcements: Vec<Reinforcement>,
+}
+
+impl Chapter {
+    // Units that arrive at the start of the given turn.
+    pub fn reinforcements_on(&self, turn: u32) -> Vec<&UnitPlacement> {
+        self.reinforcements
+            .iter()
+            .filter(|reinforcement| reinforcement.turn == turn)
+            .map(|reinforcement| &reinforcement.unit)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn units(placements: &[(u32, Faction, (u32, u32))]) -> HashMap<(u32, u32), Rc<RefCell<Unit>>> {
+        placements
+            .iter()
+            .map(|&(id, faction, location)| {
+                let unit = Unit::new(
+                    id,
+                    faction,
+                    Class::Lord,
+                    1,
+                    location,
+                    Inventory::new(),
+                );
+                (location, Rc::new(RefCell::new(unit)))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_victory_conditions() {
+        let both = units(&[(1, Faction::Player, (0, 0)), (2, Faction::Enemy, (1, 0))]);
+        let players = units(&[(1, Faction::Player, (0, 0)), (3, Faction::Other, (1, 0))]);
+        assert!(!VictoryCondition::Rout.is_met(&both, 1));
+        assert!(VictoryCondition::Rout.is_met(&players, 1));
+        assert!(!VictoryCondition::Seize.is_met(&players, 1));
+        assert!(!VictoryCondition::Survive(3).is_met(&both, 3));
+        assert!(VictoryCondition::Survive(3).is_met(&both, 4));
+    }
+
+    #[test]
+    fn test_defeat_conditions() {
+        let both = units(&[(1, Faction::Player, (0, 0)), (2, Faction::Enemy, (1, 0))]);
+        let enemies = units(&[(2, Faction::Enemy, (1, 0))]);
+        assert!(!DefeatCondition::AllUnitsDie.is_met(&both, 1));
+        assert!(DefeatCondition::AllUnitsDie.is_met(&enemies, 1));
+        assert!(!DefeatCondition::UnitDies(1).is_met(&both, 1));
+        assert!(DefeatCondition::UnitDies(1).is_met(&enemies, 1));
+        assert!(!DefeatCondition::TurnLimit(10).is_met(&both, 10));
+        assert!(DefeatCondition::TurnLimit(10).is_met(&both, 11));
+    }
+}
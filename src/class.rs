@@ -0,0 +1,159 @@
+use std::cmp::Ordering;
+use item::{WeaponRank, WeaponType};
+use skill::Skill;
+use stats::Stats;
+use terrain::MovementClass;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Class {
+    Lord,
+    Mercenary,
+    Fighter,
+    Brigand,
+    Thief,
+    Archer,
+    Cavalier,
+    Knight,
+    PegasusKnight,
+    Mage,
+    Cleric,
+}
+
+impl Class {
+    // Name used for the class in data files.
+    pub fn from_name(name: &str) -> Option<Class> {
+        match name {
+            "lord" => Some(Class::Lord),
+            "mercenary" => Some(Class::Mercenary),
+            "fighter" => Some(Class::Fighter),
+            "brigand" => Some(Class::Brigand),
+            "thief" => Some(Class::Thief),
+            "archer" => Some(Class::Archer),
+            "cavalier" => Some(Class::Cavalier),
+            "knight" => Some(Class::Knight),
+            "pegasus_knight" => Some(Class::PegasusKnight),
+            "mage" => Some(Class::Mage),
+            "cleric" => Some(Class::Cleric),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            &Class::Lord => "lord",
+            &Class::Mercenary => "mercenary",
+            &Class::Fighter => "fighter",
+            &Class::Brigand => "brigand",
+            &Class::Thief => "thief",
+            &Class::Archer => "archer",
+            &Class::Cavalier => "cavalier",
+            &Class::Knight => "knight",
+            &Class::PegasusKnight => "pegasus_knight",
+            &Class::Mage => "mage",
+            &Class::Cleric => "cleric",
+        }
+    }
+
+    // Stats of a level 1 unit of this class.
+    pub fn base_stats(&self) -> Stats {
+        match self {
+            &Class::Lord => Stats::new(18, 5, 0, 5, 7, 5, 5, 0, 7),
+            &Class::Mercenary => Stats::new(17, 4, 0, 8, 8, 0, 4, 0, 9),
+            &Class::Fighter => Stats::new(20, 5, 0, 2, 4, 0, 2, 0, 11),
+            &Class::Brigand => Stats::new(20, 5, 0, 1, 5, 0, 3, 0, 12),
+            &Class::Thief => Stats::new(16, 3, 0, 1, 9, 0, 2, 0, 6),
+            &Class::Archer => Stats::new(18, 4, 0, 3, 3, 0, 3, 0, 7),
+            &Class::Cavalier => Stats::new(20, 5, 0, 2, 5, 0, 6, 0, 9),
+            &Class::Knight => Stats::new(17, 5, 0, 2, 0, 0, 9, 0, 13),
+            &Class::PegasusKnight => Stats::new(14, 4, 0, 6, 8, 0, 2, 5, 5),
+            &Class::Mage => Stats::new(16, 0, 3, 2, 3, 0, 1, 3, 6),
+            &Class::Cleric => Stats::new(16, 0, 1, 2, 3, 0, 1, 6, 4),
+        }
+    }
+
+    // Percent chance of each stat going up by one on a level up.
+    // Constitution doesn't grow.
+    pub fn growths(&self) -> Stats {
+        match self {
+            &Class::Lord => Stats::new(80, 45, 0, 50, 50, 50, 30, 25, 0),
+            &Class::Mercenary => Stats::new(80, 40, 0, 40, 40, 30, 25, 20, 0),
+            &Class::Fighter => Stats::new(85, 55, 0, 30, 30, 15, 20, 5, 0),
+            &Class::Brigand => Stats::new(90, 60, 0, 20, 30, 15, 15, 5, 0),
+            &Class::Thief => Stats::new(70, 30, 0, 40, 60, 50, 10, 20, 0),
+            &Class::Archer => Stats::new(70, 40, 0, 50, 40, 25, 20, 15, 0),
+            &Class::Cavalier => Stats::new(75, 45, 0, 35, 35, 25, 25, 15, 0),
+            &Class::Knight => Stats::new(90, 40, 0, 30, 15, 20, 40, 10, 0),
+            &Class::PegasusKnight => Stats::new(60, 35, 0, 45, 55, 40, 15, 35, 0),
+            &Class::Mage => Stats::new(60, 0, 55, 40, 40, 20, 10, 35, 0),
+            &Class::Cleric => Stats::new(55, 0, 50, 35, 35, 40, 10, 50, 0),
+        }
+    }
+
+    // Stats of a unit of this class that starts at the given level: the base
+    // stats plus the average gains of the levels above 1.
+    pub fn stats_at_level(&self, level: u32) -> Stats {
+        let levels = level.saturating_sub(1);
+        self.base_stats()
+            .add(&self.growths().scaled(levels, 100))
+            .capped(&self.stat_caps())
+    }
+
+    pub fn stat_caps(&self) -> Stats {
+        match self {
+            &Class::Knight => Stats::new(60, 20, 20, 20, 20, 30, 25, 20, 20),
+            &Class::Mage | &Class::Cleric => Stats::new(60, 20, 25, 20, 20, 30, 20, 25, 20),
+            _ => Stats::new(60, 20, 20, 20, 20, 30, 20, 20, 20),
+        }
+    }
+
+    pub fn movement_class(&self) -> MovementClass {
+        match self {
+            &Class::Cavalier => MovementClass::Cavalry,
+            &Class::Knight => MovementClass::Armor,
+            &Class::PegasusKnight => MovementClass::Flier,
+            _ => MovementClass::Infantry,
+        }
+    }
+
+    pub fn movement_range(&self) -> u32 {
+        match self {
+            &Class::Cavalier | &Class::PegasusKnight => 7,
+            &Class::Knight => 4,
+            &Class::Thief => 6,
+            _ => 5,
+        }
+    }
+
+    // Skills that every unit of the class starts with.
+    pub fn skills(&self) -> Vec<Skill> {
+        match self {
+            &Class::Thief => vec![Skill::Pass],
+            _ => vec![],
+        }
+    }
+
+    // Highest rank of weapon of the given type that the class can wield, or
+    // None if it can't use that type at all.
+    pub fn weapon_rank(&self, weapon_type: WeaponType) -> Option<WeaponRank> {
+        use item::WeaponRank::*;
+        use item::WeaponType::*;
+        match (self, weapon_type) {
+            (&Class::Lord, Sword) => Some(D),
+            (&Class::Mercenary, Sword) => Some(C),
+            (&Class::Thief, Sword) => Some(D),
+            (&Class::Fighter, Axe) | (&Class::Brigand, Axe) => Some(C),
+            (&Class::Archer, Bow) => Some(C),
+            (&Class::Cavalier, Sword) => Some(D),
+            (&Class::Cavalier, Lance) => Some(C),
+            (&Class::Knight, Lance) | (&Class::PegasusKnight, Lance) => Some(C),
+            (&Class::Mage, Anima) => Some(C),
+            (&Class::Cleric, Staff) => Some(C),
+            _ => None,
+        }
+    }
+
+    pub fn can_wield(&self, weapon_type: WeaponType, rank: WeaponRank) -> bool {
+        self.weapon_rank(weapon_type)
+            .map_or(false, |class_rank| class_rank.cmp(&rank) != Ordering::Less)
+    }
+}
@@ -0,0 +1,480 @@
+use std::cmp;
+use item::{Weapon, TRIANGLE_DAMAGE_BONUS, TRIANGLE_HIT_BONUS};
+use rng::Rng;
+use stats::Stats;
+use terrain::Terrain;
+
+// A unit attacks twice if its attack speed is at least this much higher than
+// its opponent's.
+pub const DOUBLE_ATTACK_THRESHOLD: u32 = 4;
+pub const CRIT_MULTIPLIER: u32 = 3;
+pub const MAX_EXP_GAIN: u32 = 100;
+
+// Everything combat needs to know about one side of a fight.
+#[derive(Debug, Clone)]
+pub struct Combatant {
+    pub stats: Stats,
+    pub hp: u32,
+    pub weapon: Option<Weapon>,
+    // Terrain the combatant is standing on
+    pub terrain: Terrain,
+}
+
+impl Combatant {
+    pub fn new(stats: Stats, hp: u32, weapon: Option<Weapon>, terrain: Terrain) -> Self {
+        Combatant {
+            stats,
+            hp,
+            weapon,
+            terrain,
+        }
+    }
+
+    // Speed after the penalty for wielding a weapon heavier than the
+    // combatant's constitution.
+    pub fn attack_speed(&self) -> u32 {
+        let weight = self.weapon.as_ref().map_or(0, |weapon| weapon.weight);
+        let penalty = weight.saturating_sub(self.stats.constitution);
+        self.stats.speed.saturating_sub(penalty)
+    }
+
+    pub fn can_attack_at(&self, distance: u32) -> bool {
+        self.weapon
+            .as_ref()
+            .map_or(false, |weapon| weapon.in_range(distance))
+    }
+
+    fn attack_power(&self) -> u32 {
+        match self.weapon {
+            Some(ref weapon) if weapon.weapon_type.is_magic() => self.stats.magic + weapon.might,
+            Some(ref weapon) => self.stats.strength + weapon.might,
+            None => 0,
+        }
+    }
+
+    fn hit_rate(&self) -> u32 {
+        let weapon_hit = self.weapon.as_ref().map_or(0, |weapon| weapon.hit);
+        weapon_hit + self.stats.skill * 2 + self.stats.luck / 2
+    }
+
+    fn avoid(&self) -> u32 {
+        self.attack_speed() * 2 + self.stats.luck + self.terrain.avoid_bonus()
+    }
+
+    fn crit_rate(&self) -> u32 {
+        let weapon_crit = self.weapon.as_ref().map_or(0, |weapon| weapon.crit);
+        weapon_crit + self.stats.skill / 2
+    }
+
+    fn crit_avoid(&self) -> u32 {
+        self.stats.luck
+    }
+
+    // Defense against the given weapon, including terrain.
+    fn defense_against(&self, weapon: &Weapon) -> u32 {
+        let defense = if weapon.weapon_type.is_magic() {
+            self.stats.resistance
+        } else {
+            self.stats.defense
+        };
+        defense + self.terrain.defense_bonus()
+    }
+}
+
+// What one side of a fight will do to the other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SideForecast {
+    // HP before combat
+    pub hp: u32,
+    // Damage per hit, not counting crits
+    pub damage: u32,
+    // Chance to hit, in percent
+    pub hit: u32,
+    // Chance to crit when hitting, in percent
+    pub crit: u32,
+    // Number of attacks (0 if the side can't counter)
+    pub attacks: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Forecast {
+    pub attacker: SideForecast,
+    pub defender: SideForecast,
+}
+
+fn side_forecast(striker: &Combatant, target: &Combatant, distance: u32) -> SideForecast {
+    let weapon = match striker.weapon {
+        Some(ref weapon) if weapon.in_range(distance) => weapon,
+        _ => {
+            return SideForecast {
+                hp: striker.hp,
+                damage: 0,
+                hit: 0,
+                crit: 0,
+                attacks: 0,
+            }
+        }
+    };
+    // Weapon triangle
+    let advantage = target.weapon.as_ref().map_or(0, |target_weapon| {
+        weapon
+            .weapon_type
+            .triangle_advantage(&target_weapon.weapon_type)
+    });
+    let damage = cmp::max(
+        0,
+        striker.attack_power() as i32 + advantage * TRIANGLE_DAMAGE_BONUS
+            - target.defense_against(weapon) as i32,
+    ) as u32;
+    let hit = cmp::max(
+        0,
+        cmp::min(
+            100,
+            striker.hit_rate() as i32 + advantage * TRIANGLE_HIT_BONUS - target.avoid() as i32,
+        ),
+    ) as u32;
+    let crit = cmp::min(100, striker.crit_rate().saturating_sub(target.crit_avoid()));
+    let attacks = if striker.attack_speed() >= target.attack_speed() + DOUBLE_ATTACK_THRESHOLD {
+        2
+    } else {
+        1
+    };
+    SideForecast {
+        hp: striker.hp,
+        damage,
+        hit,
+        crit,
+        attacks,
+    }
+}
+
+// Computes the outcome of attacker attacking defender from distance cells
+// away. Returns None if the attacker can't attack from that distance.
+pub fn forecast(attacker: &Combatant, defender: &Combatant, distance: u32) -> Option<Forecast> {
+    if !attacker.can_attack_at(distance) {
+        return None;
+    }
+    Some(Forecast {
+        attacker: side_forecast(attacker, defender, distance),
+        defender: side_forecast(defender, attacker, distance),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Attacker,
+    Defender,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Strike {
+    pub striker: Side,
+    pub hit: bool,
+    pub crit: bool,
+    pub damage: u32,
+    // HP of the target after the strike
+    pub target_hp: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CombatResult {
+    // Strikes in the order they happened, for playing back the fight
+    pub strikes: Vec<Strike>,
+    pub attacker_hp: u32,
+    pub defender_hp: u32,
+}
+
+impl CombatResult {
+    // Number of strikes made by side. Each strike uses up one use of the
+    // striker's weapon.
+    pub fn strikes_by(&self, side: Side) -> u32 {
+        self.strikes
+            .iter()
+            .filter(|strike| strike.striker == side)
+            .count() as u32
+    }
+}
+
+// Rolls the fight described by forecast. The attacker strikes first, then the
+// defender counters, then whoever doubles strikes again. Combat ends as soon
+// as either side is defeated.
+pub fn resolve(forecast: &Forecast, rng: &mut Rng) -> CombatResult {
+    let mut order = vec![];
+    if forecast.attacker.attacks > 0 {
+        order.push(Side::Attacker);
+    }
+    if forecast.defender.attacks > 0 {
+        order.push(Side::Defender);
+    }
+    if forecast.attacker.attacks > 1 {
+        order.push(Side::Attacker);
+    }
+    if forecast.defender.attacks > 1 {
+        order.push(Side::Defender);
+    }
+
+    let mut attacker_hp = forecast.attacker.hp;
+    let mut defender_hp = forecast.defender.hp;
+    let mut strikes = vec![];
+    for striker in order {
+        if attacker_hp == 0 || defender_hp == 0 {
+            break;
+        }
+        let (side, target_hp) = match striker {
+            Side::Attacker => (&forecast.attacker, &mut defender_hp),
+            Side::Defender => (&forecast.defender, &mut attacker_hp),
+        };
+        let hit = rng.check_2rn(side.hit);
+        let crit = hit && rng.check(side.crit);
+        let damage = if !hit {
+            0
+        } else if crit {
+            side.damage * CRIT_MULTIPLIER
+        } else {
+            side.damage
+        };
+        *target_hp = target_hp.saturating_sub(damage);
+        strikes.push(Strike {
+            striker,
+            hit,
+            crit,
+            damage,
+            target_hp: *target_hp,
+        });
+    }
+    CombatResult {
+        strikes,
+        attacker_hp,
+        defender_hp,
+    }
+}
+
+// Experience for fighting an enemy of enemy_level. Fighting stronger enemies
+// gives more, and defeating the enemy gives a bonus on top.
+pub fn experience(level: u32, enemy_level: u32, defeated: bool) -> u32 {
+    let level_difference = enemy_level as i32 - level as i32;
+    let mut exp = cmp::max(1, 10 + level_difference);
+    if defeated {
+        exp += cmp::max(0, 20 + level_difference * 3);
+    }
+    cmp::min(MAX_EXP_GAIN, exp as u32)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use item::Item;
+
+    fn weapon(name: &str) -> Weapon {
+        match Item::by_name(name) {
+            Some(Item::Weapon(weapon)) => weapon,
+            _ => unreachable!(),
+        }
+    }
+
+    fn iron_sword() -> Weapon {
+        weapon("Iron Sword")
+    }
+
+    fn iron_bow() -> Weapon {
+        weapon("Iron Bow")
+    }
+
+    fn fire() -> Weapon {
+        weapon("Fire")
+    }
+
+    fn combatant(weapon: Option<Weapon>) -> Combatant {
+        // hp 20, str 6, mag 4, skl 5, spd 6, lck 4, def 3, res 2, con 7
+        Combatant::new(
+            Stats::new(20, 6, 4, 5, 6, 4, 3, 2, 7),
+            20,
+            weapon,
+            Terrain::None,
+        )
+    }
+
+    #[test]
+    fn test_forecast_even() {
+        let attacker = combatant(Some(iron_sword()));
+        let defender = combatant(Some(iron_sword()));
+        let forecast = forecast(&attacker, &defender, 1).unwrap();
+        // 6 str + 5 mt - 3 def
+        assert_eq!(forecast.attacker.damage, 8);
+        // 90 + 5 * 2 + 4 / 2 - (6 * 2 + 4)
+        assert_eq!(forecast.attacker.hit, 86);
+        // 0 + 5 / 2 - 4
+        assert_eq!(forecast.attacker.crit, 0);
+        assert_eq!(forecast.attacker.attacks, 1);
+        assert_eq!(forecast.defender, forecast.attacker);
+    }
+
+    #[test]
+    fn test_forecast_terrain() {
+        let attacker = combatant(Some(iron_sword()));
+        let mut defender = combatant(Some(iron_sword()));
+        defender.terrain = Terrain::Fort;
+        let forecast = forecast(&attacker, &defender, 1).unwrap();
+        assert_eq!(forecast.attacker.damage, 6);
+        assert_eq!(forecast.attacker.hit, 66);
+    }
+
+    #[test]
+    fn test_forecast_doubling() {
+        let mut attacker = combatant(Some(iron_sword()));
+        attacker.stats.speed = 10;
+        let defender = combatant(Some(iron_sword()));
+        let forecast = forecast(&attacker, &defender, 1).unwrap();
+        assert_eq!(forecast.attacker.attacks, 2);
+        assert_eq!(forecast.defender.attacks, 1);
+    }
+
+    #[test]
+    fn test_forecast_weight_prevents_doubling() {
+        let mut attacker = combatant(Some(iron_sword()));
+        attacker.stats.speed = 10;
+        attacker.stats.constitution = 2;
+        let defender = combatant(Some(iron_sword()));
+        assert_eq!(attacker.attack_speed(), 7);
+        let forecast = forecast(&attacker, &defender, 1).unwrap();
+        assert_eq!(forecast.attacker.attacks, 1);
+    }
+
+    #[test]
+    fn test_forecast_triangle() {
+        let attacker = combatant(Some(weapon("Iron Lance")));
+        let defender = combatant(Some(iron_sword()));
+        let forecast = forecast(&attacker, &defender, 1).unwrap();
+        // 6 str + 7 mt + 1 - 3 def
+        assert_eq!(forecast.attacker.damage, 11);
+        // 80 + 10 + 2 + 15 - 16
+        assert_eq!(forecast.attacker.hit, 91);
+        // 6 str + 5 mt - 1 - 3 def
+        assert_eq!(forecast.defender.damage, 7);
+        // 90 + 10 + 2 - 15 - (6 * 2 + 4) with the lance's weight slowing the
+        // attacker down by 1
+        assert_eq!(forecast.defender.hit, 73);
+    }
+
+    #[test]
+    fn test_forecast_magic_targets_resistance() {
+        let attacker = combatant(Some(fire()));
+        let defender = combatant(Some(iron_sword()));
+        let forecast = forecast(&attacker, &defender, 1).unwrap();
+        // 4 mag + 5 mt - 2 res
+        assert_eq!(forecast.attacker.damage, 7);
+    }
+
+    #[test]
+    fn test_forecast_out_of_range() {
+        let attacker = combatant(Some(iron_sword()));
+        let defender = combatant(Some(iron_bow()));
+        assert!(forecast(&attacker, &defender, 2).is_none());
+        assert!(forecast(&combatant(None), &defender, 1).is_none());
+        // Bow can't counter at range 1
+        let forecast = forecast(&attacker, &defender, 1).unwrap();
+        assert_eq!(forecast.defender.attacks, 0);
+    }
+
+    #[test]
+    fn test_resolve_deterministic() {
+        let attacker = combatant(Some(iron_sword()));
+        let defender = combatant(Some(iron_sword()));
+        let forecast = forecast(&attacker, &defender, 1).unwrap();
+        let result1 = resolve(&forecast, &mut Rng::new(1234));
+        let result2 = resolve(&forecast, &mut Rng::new(1234));
+        assert_eq!(result1, result2);
+        assert_eq!(result1.strikes.len(), 2);
+        assert_eq!(result1.strikes[0].striker, Side::Attacker);
+        assert_eq!(result1.strikes[1].striker, Side::Defender);
+        assert_eq!(result1.strikes_by(Side::Attacker), 1);
+        assert_eq!(result1.strikes_by(Side::Defender), 1);
+    }
+
+    #[test]
+    fn test_resolve_order_and_damage() {
+        let forecast = Forecast {
+            attacker: SideForecast {
+                hp: 20,
+                damage: 4,
+                hit: 100,
+                crit: 0,
+                attacks: 2,
+            },
+            defender: SideForecast {
+                hp: 20,
+                damage: 3,
+                hit: 100,
+                crit: 0,
+                attacks: 1,
+            },
+        };
+        let result = resolve(&forecast, &mut Rng::new(0));
+        let strikers: Vec<Side> = result.strikes.iter().map(|s| s.striker).collect();
+        assert_eq!(
+            strikers,
+            vec![Side::Attacker, Side::Defender, Side::Attacker]
+        );
+        assert_eq!(result.attacker_hp, 17);
+        assert_eq!(result.defender_hp, 12);
+        assert_eq!(result.strikes[2].target_hp, 12);
+    }
+
+    #[test]
+    fn test_resolve_stops_on_defeat() {
+        let forecast = Forecast {
+            attacker: SideForecast {
+                hp: 20,
+                damage: 25,
+                hit: 100,
+                crit: 0,
+                attacks: 2,
+            },
+            defender: SideForecast {
+                hp: 20,
+                damage: 3,
+                hit: 100,
+                crit: 0,
+                attacks: 1,
+            },
+        };
+        let result = resolve(&forecast, &mut Rng::new(0));
+        assert_eq!(result.strikes.len(), 1);
+        assert_eq!(result.defender_hp, 0);
+        assert_eq!(result.attacker_hp, 20);
+    }
+
+    #[test]
+    fn test_resolve_crit() {
+        let forecast = Forecast {
+            attacker: SideForecast {
+                hp: 20,
+                damage: 4,
+                hit: 100,
+                crit: 100,
+                attacks: 1,
+            },
+            defender: SideForecast {
+                hp: 20,
+                damage: 0,
+                hit: 0,
+                crit: 0,
+                attacks: 1,
+            },
+        };
+        let result = resolve(&forecast, &mut Rng::new(0));
+        assert!(result.strikes[0].crit);
+        assert_eq!(result.defender_hp, 8);
+        assert!(!result.strikes[1].hit);
+        assert_eq!(result.attacker_hp, 20);
+    }
+
+    #[test]
+    fn test_experience() {
+        assert_eq!(experience(1, 1, false), 10);
+        assert_eq!(experience(1, 1, true), 30);
+        assert_eq!(experience(5, 2, true), 18);
+        assert_eq!(experience(20, 1, false), 1);
+        assert_eq!(experience(1, 20, true), MAX_EXP_GAIN);
+    }
+}
@@ -1,16 +1,34 @@
+use std::collections::HashSet;
+use std::fmt;
 use std::fs::File;
 use std::result;
 use std::path::Path;
 use std::io;
 use std::io::{BufRead, BufReader};
-use ggez::error::*;
-use terrain::Terrain;
+use num::rational::Ratio;
+use ron;
+use action::Action;
+use ai::Behavior;
+use chapter::{Chapter, DefeatCondition, Reinforcement, UnitPlacement, VictoryCondition};
+use class::Class;
+use item::{Inventory, Item};
+use script::{Order, Script};
+use terrain::{CostTable, MovementClass, Terrain};
+use unit::Faction;
+use utils;
 
 pub type Result<T> = result::Result<T, DataParserErr>;
 
 #[derive(Debug)]
 pub enum DataParserErr {
-    InvalidWallData(String),
+    // Path of the file and every problem found in its map
+    InvalidWallData(String, Vec<MapError>),
+    InvalidCostData(String),
+    InvalidBindingData(String),
+    // Path of the file followed by what's wrong with it
+    InvalidChapterData(String),
+    // Path of the file and the line with the bad order
+    InvalidScriptData(String, usize),
     Io(io::Error),
 }
 
@@ -22,58 +40,573 @@ impl From<io::Error> for DataParserErr {
     }
 }
 
-impl From<DataParserErr> for GameError {
-    fn from(err: DataParserErr) -> GameError {
-        match err {
-            InvalidWallData(path) => GameError::ResourceLoadError(path),
-            Io(err) => GameError::ResourceLoadError(err.to_string()),
+impl fmt::Display for DataParserErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &InvalidWallData(ref path, ref errors) => {
+                write!(f, "{}:", path)?;
+                for error in errors.iter() {
+                    write!(f, "\n  {}", error)?;
+                }
+                Ok(())
+            }
+            &InvalidCostData(ref path) => write!(f, "invalid cost table {}", path),
+            &InvalidBindingData(ref path) => write!(f, "invalid bindings {}", path),
+            &InvalidChapterData(ref message) => write!(f, "{}", message),
+            &InvalidScriptData(ref path, line) => {
+                write!(f, "invalid order in script {}, line {}", path, line)
+            }
+            &Io(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+// A problem with a map. Lines and columns start at 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapError {
+    // The map has no rows
+    Empty,
+    // A character that isn't in Terrain::legend()
+    UnknownTerrain {
+        line: usize,
+        column: usize,
+        found: char,
+    },
+    // The row's length differs from the first row's
+    RaggedRow {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    // The row is longer or shorter than the declared width of the map
+    WrongWidth {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    // There are more or fewer rows than the declared height of the map
+    WrongHeight {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &MapError::Empty => write!(f, "the map has no rows"),
+            &MapError::UnknownTerrain {
+                line,
+                column,
+                found,
+            } => write!(
+                f,
+                "line {}, column {}: unknown terrain '{}', expected one of \"{}\"",
+                line,
+                column,
+                found,
+                Terrain::legend()
+            ),
+            &MapError::RaggedRow {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: row has {} columns but the first row has {}",
+                line, found, expected
+            ),
+            &MapError::WrongWidth {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: row is too {} ({} columns, expected {})",
+                line,
+                if found > expected { "long" } else { "short" },
+                found,
+                expected
+            ),
+            &MapError::WrongHeight { expected, found } => write!(
+                f,
+                "too {} rows ({}, expected {})",
+                if found > expected { "many" } else { "few" },
+                found,
+                expected
+            ),
         }
     }
 }
 
+// Map dimensions are inferred from the data: the width is the length of the
+// rows (which must all be the same length) and the height is the number of
+// rows. Blank lines are ignored.
 pub fn parse_walls_from_bufread<T: BufRead + Sized, P: AsRef<Path>>(
     buf_reader: &mut T,
     path: P,
-    max_w: usize,
-    max_h: usize,
 ) -> Result<Vec<Vec<Terrain>>> {
-    let mut terrain = vec![vec![Terrain::None; max_h]; max_w];
-    for (y, line) in buf_reader.lines().enumerate() {
-        if y >= max_h {
-            return Err(InvalidWallData(path.as_ref().to_str().unwrap().to_string()));
-        }
+    let mut rows = vec![];
+    for (index, line) in buf_reader.lines().enumerate() {
         let line = line?;
-        for (x, ch) in line.chars().enumerate() {
-            if x >= max_w {
-                return Err(InvalidWallData(path.as_ref().to_str().unwrap().to_string()));
-            }
-            match ch {
-                '0' => terrain[x][y] = Terrain::None,
-                'w' => terrain[x][y] = Terrain::Wall,
-                's' => terrain[x][y] = Terrain::Sand,
-                _ => return Err(InvalidWallData(path.as_ref().to_str().unwrap().to_string())),
+        let line = line.trim_right();
+        if !line.is_empty() {
+            rows.push((index + 1, 1, line.to_string()));
+        }
+    }
+    terrain_from_rows(&rows, None)
+        .map_err(|errors| InvalidWallData(path.as_ref().to_str().unwrap().to_string(), errors))
+}
+
+// Converts rows of terrain characters (see Terrain::from_char()) into a 2d vec
+// in (x, y) order. Each row comes with the line and column where it starts in
+// the file, for error messages. If
+// dimensions (width, height) are given the map must match them, otherwise
+// every row must be as long as the first. Every problem is reported, not just
+// the first one.
+fn terrain_from_rows<S: AsRef<str>>(
+    rows: &[(usize, usize, S)],
+    dimensions: Option<(usize, usize)>,
+) -> result::Result<Vec<Vec<Terrain>>, Vec<MapError>> {
+    if rows.is_empty() {
+        return Err(vec![MapError::Empty]);
+    }
+    let width = match dimensions {
+        Some((width, _)) => width,
+        None => rows[0].2.as_ref().chars().count(),
+    };
+    let mut errors = vec![];
+    let mut terrain_rows = vec![];
+    for &(line, first_column, ref row) in rows.iter() {
+        let mut terrain_row = vec![];
+        for (index, ch) in row.as_ref().chars().enumerate() {
+            match Terrain::from_char(ch) {
+                Some(terrain_type) => terrain_row.push(terrain_type),
+                None => errors.push(MapError::UnknownTerrain {
+                    line,
+                    column: first_column + index,
+                    found: ch,
+                }),
             }
         }
+        let found = row.as_ref().chars().count();
+        if found != width {
+            errors.push(match dimensions {
+                Some(_) => MapError::WrongWidth {
+                    line,
+                    expected: width,
+                    found,
+                },
+                None => MapError::RaggedRow {
+                    line,
+                    expected: width,
+                    found,
+                },
+            });
+        }
+        terrain_rows.push(terrain_row);
+    }
+    if let Some((_, height)) = dimensions {
+        if rows.len() != height {
+            errors.push(MapError::WrongHeight {
+                expected: height,
+                found: rows.len(),
+            });
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    // Transpose rows into (x, y) order.
+    let mut terrain = vec![vec![Terrain::None; terrain_rows.len()]; width];
+    for (y, row) in terrain_rows.into_iter().enumerate() {
+        for (x, terrain_type) in row.into_iter().enumerate() {
+            terrain[x][y] = terrain_type;
+        }
     }
     Ok(terrain)
 }
 
 // Opens from "resources" dir. Caller does not need to insert leading slash
-// (this is different from how ggez does it).
-pub fn parse_walls<P: AsRef<Path>>(
+// (this is different from how the frontend loads images).
+pub fn parse_walls<P: AsRef<Path>>(path: P) -> Result<Vec<Vec<Terrain>>> {
+    let f = File::open(Path::new("resources").join(&path))?;
+    let mut buf_reader = BufReader::new(f);
+    parse_walls_from_bufread(&mut buf_reader, &path)
+}
+
+// The first line is a header naming the movement class of each column. Each
+// following line gives a terrain name followed by its cost for each of those
+// movement classes, or '-' if the terrain is impassable. Lines starting with
+// '#' are comments.
+pub fn parse_cost_table_from_bufread<T: BufRead + Sized, P: AsRef<Path>>(
+    buf_reader: &mut T,
     path: P,
-    max_w: usize,
-    max_h: usize,
-) -> Result<Vec<Vec<Terrain>>> {
+) -> Result<CostTable> {
+    let err = || InvalidCostData(path.as_ref().to_str().unwrap().to_string());
+    let mut movement_classes: Option<Vec<MovementClass>> = None;
+    let mut cost_table = CostTable::new();
+    for line in buf_reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let first = fields.next().unwrap();
+        match movement_classes {
+            None => {
+                if first != "terrain" {
+                    return Err(err());
+                }
+                let classes = fields
+                    .map(MovementClass::from_name)
+                    .collect::<Option<Vec<MovementClass>>>()
+                    .ok_or_else(&err)?;
+                movement_classes = Some(classes);
+            }
+            Some(ref classes) => {
+                let terrain = Terrain::from_name(first).ok_or_else(&err)?;
+                let costs: Vec<&str> = fields.collect();
+                if costs.len() != classes.len() {
+                    return Err(err());
+                }
+                for (&movement_class, &cost) in classes.iter().zip(costs.iter()) {
+                    if cost == "-" {
+                        continue;
+                    }
+                    let cost = utils::parse_rational(cost).ok_or_else(&err)?;
+                    // Path finding relies on every step costing at least 1
+                    if cost < Ratio::from_integer(1) {
+                        return Err(err());
+                    }
+                    cost_table.set_cost(terrain, movement_class, cost);
+                }
+            }
+        }
+    }
+    if movement_classes.is_none() {
+        return Err(err());
+    }
+    Ok(cost_table)
+}
+
+// Opens from "resources" dir.
+pub fn parse_cost_table<P: AsRef<Path>>(path: P) -> Result<CostTable> {
+    let f = File::open(Path::new("resources").join(&path))?;
+    let mut buf_reader = BufReader::new(f);
+    parse_cost_table_from_bufread(&mut buf_reader, &path)
+}
+
+// Layout of a chapter file. Names and dimensions are checked when it's
+// converted into a Chapter.
+#[derive(Deserialize)]
+struct ChapterData {
+    name: String,
+    width: u32,
+    height: u32,
+    #[serde(default)]
+    zone_of_control: bool,
+    // One string per row of the map, using the characters of
+    // Terrain::from_char()
+    tiles: Vec<String>,
+    units: Vec<UnitData>,
+    victory: VictoryCondition,
+    defeat: Vec<DefeatCondition>,
+    #[serde(default)]
+    reinforcements: Vec<ReinforcementData>,
+}
+
+#[derive(Deserialize)]
+struct UnitData {
+    id: u32,
+    faction: Faction,
+    class: String,
+    level: u32,
+    location: (u32, u32),
+    #[serde(default)]
+    items: Vec<String>,
+    #[serde(default)]
+    behavior: Behavior,
+}
+
+#[derive(Deserialize)]
+struct ReinforcementData {
+    turn: u32,
+    unit: UnitData,
+}
+
+// Returns a description of the problem if the unit data is invalid.
+fn unit_placement(
+    data: UnitData,
+    terrain: &[Vec<Terrain>],
+) -> result::Result<UnitPlacement, String> {
+    let class =
+        Class::from_name(&data.class).ok_or_else(|| format!("unknown class {}", data.class))?;
+    let mut inventory = Inventory::new();
+    for name in data.items.iter() {
+        let item = Item::by_name(name).ok_or_else(|| format!("unknown item {}", name))?;
+        inventory
+            .add(item)
+            .map_err(|_| format!("too many items for unit {}", data.id))?;
+    }
+    let (x, y) = data.location;
+    match terrain.get(x as usize).and_then(|column| column.get(y as usize)) {
+        Some(terrain) if terrain.is_solid() => {
+            return Err(format!("unit {} is on a {}", data.id, terrain.name()))
+        }
+        Some(_) => (),
+        None => return Err(format!("unit {} is off the map", data.id)),
+    }
+    Ok(UnitPlacement {
+        id: data.id,
+        faction: data.faction,
+        class,
+        level: data.level,
+        location: data.location,
+        inventory,
+        behavior: data.behavior,
+    })
+}
+
+// Finds the offset just past the key of one of the chapter's own fields.
+// Strings, comments and nested values are skipped, since the chapter's name
+// or a comment could mention the field too.
+fn field_offset(contents: &str, field: &str) -> Option<usize> {
+    let bytes = contents.as_bytes();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i += 2;
+                while i + 1 < bytes.len() && &bytes[i..i + 2] != b"*/" {
+                    i += 1;
+                }
+                i += 1;
+            }
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => depth -= 1,
+            b if b.is_ascii_alphabetic() || b == b'_' => {
+                let start = i;
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                if depth == 1
+                    && &contents[start..i] == field
+                    && contents[i..].chars().find(|c| !c.is_whitespace()) == Some(':')
+                {
+                    return Some(i);
+                }
+                continue;
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+    None
+}
+
+// Finds the line and column where each row of tiles starts in the chapter
+// file, by looking for the quoted rows in order after the tiles field. A row
+// that can't be found, because it's written with escapes, is assumed to be on
+// the line after the previous one.
+fn tile_positions(contents: &str, tiles: &[String]) -> Vec<(usize, usize)> {
+    let mut offset = field_offset(contents, "tiles").unwrap_or(0);
+    let mut positions = vec![];
+    for row in tiles.iter() {
+        let quoted = format!("\"{}\"", row);
+        let position = match contents[offset..].find(&quoted) {
+            Some(index) => {
+                let start = offset + index + 1;
+                offset = start + row.len() + 1;
+                let line_start = contents[..start].rfind('\n').map_or(0, |index| index + 1);
+                (
+                    contents[..start].matches('\n').count() + 1,
+                    contents[line_start..start].chars().count() + 1,
+                )
+            }
+            None => match positions.last() {
+                Some(&(line, column)) => (line + 1, column),
+                None => (contents[..offset].matches('\n').count() + 1, 1),
+            },
+        };
+        positions.push(position);
+    }
+    positions
+}
+
+// Chapters are RON files holding a ChapterData. Unit ids must be unique, no
+// two units can start on the same cell or on walls and doors, and UnitDies
+// defeat conditions must name one of the units or reinforcements.
+pub fn parse_chapter_from_bufread<T: BufRead + Sized, P: AsRef<Path>>(
+    buf_reader: &mut T,
+    path: P,
+) -> Result<Chapter> {
+    let path = path.as_ref().to_str().unwrap().to_string();
+    let err = |message: String| InvalidChapterData(format!("{}: {}", path, message));
+    let mut contents = String::new();
+    buf_reader.read_to_string(&mut contents)?;
+    let data: ChapterData = ron::de::from_str(&contents).map_err(|e| err(e.to_string()))?;
+
+    let rows: Vec<(usize, usize, &str)> = tile_positions(&contents, &data.tiles)
+        .into_iter()
+        .zip(data.tiles.iter())
+        .map(|((line, column), row)| (line, column, row.as_str()))
+        .collect();
+    let dimensions = (data.width as usize, data.height as usize);
+    let terrain = terrain_from_rows(&rows, Some(dimensions))
+        .map_err(|errors| InvalidWallData(path.clone(), errors))?;
+
+    let mut ids = HashSet::new();
+    let mut units = vec![];
+    for unit_data in data.units {
+        let unit = unit_placement(unit_data, &terrain).map_err(&err)?;
+        if units
+            .iter()
+            .any(|other: &UnitPlacement| other.location == unit.location)
+        {
+            return Err(err(format!("more than one unit at {:?}", unit.location)));
+        }
+        if !ids.insert(unit.id) {
+            return Err(err(format!("duplicate unit id {}", unit.id)));
+        }
+        units.push(unit);
+    }
+    let mut reinforcements = vec![];
+    for reinforcement_data in data.reinforcements {
+        let unit = unit_placement(reinforcement_data.unit, &terrain).map_err(&err)?;
+        if !ids.insert(unit.id) {
+            return Err(err(format!("duplicate unit id {}", unit.id)));
+        }
+        reinforcements.push(Reinforcement {
+            turn: reinforcement_data.turn,
+            unit,
+        });
+    }
+    for condition in data.defeat.iter() {
+        if let &DefeatCondition::UnitDies(id) = condition {
+            if !ids.contains(&id) {
+                return Err(err(format!("no unit with id {} for UnitDies", id)));
+            }
+        }
+    }
+
+    Ok(Chapter {
+        name: data.name,
+        width: data.width,
+        height: data.height,
+        terrain,
+        zone_of_control: data.zone_of_control,
+        units,
+        victory: data.victory,
+        defeat: data.defeat,
+        reinforcements,
+    })
+}
+
+// Opens from "resources" dir.
+pub fn parse_chapter<P: AsRef<Path>>(path: P) -> Result<Chapter> {
     let f = File::open(Path::new("resources").join(&path))?;
     let mut buf_reader = BufReader::new(f);
-    parse_walls_from_bufread(&mut buf_reader, &path, max_w, max_h)
+    parse_chapter_from_bufread(&mut buf_reader, &path)
+}
+
+// "x,y", as used by scripts.
+fn parse_coord(s: &str) -> Option<(u32, u32)> {
+    let mut parts = s.split(',');
+    let x = parts.next()?.trim().parse().ok()?;
+    let y = parts.next()?.trim().parse().ok()?;
+    match parts.next() {
+        Some(_) => None,
+        None => Some((x, y)),
+    }
+}
+
+// Each line of a script is an order of the form
+//     turn unit x,y action [x,y]
+// where unit is the id of a player unit, the first coord is where it moves to
+// and the second is the target of attack and staff actions. Only attack,
+// staff, seize and wait orders are supported.
+pub fn parse_script_from_bufread<T: BufRead + Sized, P: AsRef<Path>>(
+    buf_reader: &mut T,
+    path: P,
+) -> Result<Script> {
+    let mut orders = vec![];
+    for (index, line) in buf_reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let err = || InvalidScriptData(path.as_ref().to_str().unwrap().to_string(), index + 1);
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 4 || fields.len() > 5 {
+            return Err(err());
+        }
+        let turn = fields[0].parse().map_err(|_| err())?;
+        let unit = fields[1].parse().map_err(|_| err())?;
+        let destination = parse_coord(fields[2]).ok_or_else(&err)?;
+        let action = Action::from_name(fields[3]).ok_or_else(&err)?;
+        let target = match fields.get(4) {
+            Some(field) => Some(parse_coord(field).ok_or_else(&err)?),
+            None => None,
+        };
+        let needs_target = match action {
+            Action::Attack | Action::Staff => true,
+            Action::Seize | Action::Wait => false,
+            Action::Item | Action::Trade => return Err(err()),
+        };
+        if needs_target != target.is_some() {
+            return Err(err());
+        }
+        orders.push(Order {
+            turn,
+            unit,
+            destination,
+            action,
+            target,
+        });
+    }
+    Ok(Script::new(orders))
+}
+
+// Unlike the other files, scripts are written by players rather than shipped
+// with the game, so path is opened as given instead of from "resources" dir.
+pub fn parse_script<P: AsRef<Path>>(path: P) -> Result<Script> {
+    let f = File::open(&path)?;
+    let mut buf_reader = BufReader::new(f);
+    parse_script_from_bufread(&mut buf_reader, &path)
 }
 
 #[cfg(test)]
 mod test {
-    use super::parse_walls_from_bufread;
-    use terrain::Terrain;
+    use super::{
+        parse_chapter_from_bufread, parse_cost_table_from_bufread, parse_script_from_bufread,
+        parse_walls_from_bufread, DataParserErr, MapError,
+    };
+    use action::Action;
+    use ai::Behavior;
+    use chapter::{DefeatCondition, VictoryCondition};
+    use class::Class;
+    use unit::Faction;
+    use terrain::{MovementClass, Terrain};
+    use num::rational::Ratio;
     use std::io::Cursor;
 
     #[test]
@@ -87,7 +620,7 @@ mod test {
         "
         );
         let mut cursor = Cursor::new(walls);
-        let result = parse_walls_from_bufread(&mut cursor, "", 4, 4);
+        let result = parse_walls_from_bufread(&mut cursor, "");
         assert!(result.is_ok());
         let parsed_walls = result.unwrap();
         for (x, v) in parsed_walls.iter().enumerate() {
@@ -102,4 +635,346 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn test_ok_non_square() {
+        let walls = indoc!(
+            "
+            000000
+            0w000s
+            000000
+        "
+        );
+        let mut cursor = Cursor::new(walls);
+        let result = parse_walls_from_bufread(&mut cursor, "");
+        assert!(result.is_ok());
+        let parsed_walls = result.unwrap();
+        assert_eq!(parsed_walls.len(), 6);
+        for col in parsed_walls.iter() {
+            assert_eq!(col.len(), 3);
+        }
+        assert_eq!(parsed_walls[1][1], Terrain::Wall);
+        assert_eq!(parsed_walls[5][1], Terrain::Sand);
+    }
+
+    #[test]
+    fn test_ragged_rows() {
+        let walls = indoc!(
+            "
+            0000
+            000
+            0000
+        "
+        );
+        let mut cursor = Cursor::new(walls);
+        assert!(parse_walls_from_bufread(&mut cursor, "").is_err());
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut cursor = Cursor::new("");
+        assert!(parse_walls_from_bufread(&mut cursor, "").is_err());
+    }
+
+    #[test]
+    fn test_map_errors() {
+        // Blank lines still count towards line numbers
+        let mut cursor = Cursor::new("0000\n\n0x0?\n00\n");
+        let errors = match parse_walls_from_bufread(&mut cursor, "map.txt") {
+            Err(DataParserErr::InvalidWallData(path, errors)) => {
+                assert_eq!(path, "map.txt");
+                errors
+            }
+            result => panic!("unexpected result {:?}", result),
+        };
+        assert_eq!(
+            errors,
+            vec![
+                MapError::UnknownTerrain {
+                    line: 3,
+                    column: 2,
+                    found: 'x',
+                },
+                MapError::UnknownTerrain {
+                    line: 3,
+                    column: 4,
+                    found: '?',
+                },
+                MapError::RaggedRow {
+                    line: 4,
+                    expected: 4,
+                    found: 2,
+                },
+            ]
+        );
+        assert_eq!(
+            errors[0].to_string(),
+            "line 3, column 2: unknown terrain 'x', expected one of \"0wsfm~Fgvtbdc\""
+        );
+    }
+
+    #[test]
+    fn test_chapter_map_errors() {
+        let chapter = CHAPTER
+            .replace("width: 3", "width: 2")
+            .replace("height: 2", "height: 1");
+        let mut cursor = Cursor::new(chapter);
+        match parse_chapter_from_bufread(&mut cursor, "") {
+            Err(DataParserErr::InvalidWallData(_, errors)) => assert_eq!(
+                errors,
+                vec![
+                    MapError::WrongWidth {
+                        line: 8,
+                        expected: 2,
+                        found: 3,
+                    },
+                    MapError::WrongWidth {
+                        line: 9,
+                        expected: 2,
+                        found: 3,
+                    },
+                    MapError::WrongHeight {
+                        expected: 1,
+                        found: 2,
+                    },
+                ]
+            ),
+            result => panic!("unexpected result {:?}", result),
+        }
+        // Columns count from the start of the line too
+        let mut cursor = Cursor::new(CHAPTER.replace("\"0t0\"", "\"0?0\""));
+        match parse_chapter_from_bufread(&mut cursor, "") {
+            Err(DataParserErr::InvalidWallData(_, errors)) => assert_eq!(
+                errors,
+                vec![
+                    MapError::UnknownTerrain {
+                        line: 9,
+                        column: 19,
+                        found: '?',
+                    },
+                ]
+            ),
+            result => panic!("unexpected result {:?}", result),
+        }
+        // Rows are looked for after the tiles field, not after the first
+        // mention of tiles
+        let chapter = CHAPTER
+            .replace("\"0t0\"", "\"0?0\"")
+            .replace("\"Test\",", "\"tiles\", // tiles: [\"00w\", \"0?0\"]");
+        let mut cursor = Cursor::new(chapter);
+        match parse_chapter_from_bufread(&mut cursor, "") {
+            Err(DataParserErr::InvalidWallData(_, errors)) => assert_eq!(
+                errors,
+                vec![
+                    MapError::UnknownTerrain {
+                        line: 9,
+                        column: 19,
+                        found: '?',
+                    },
+                ]
+            ),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_cost_table_ok() {
+        let costs = indoc!(
+            "
+            # comment
+            terrain  infantry  flier
+            none     1         1
+            sand     2.5       1
+            wall     -         -
+        "
+        );
+        let mut cursor = Cursor::new(costs);
+        let cost_table = parse_cost_table_from_bufread(&mut cursor, "").unwrap();
+        assert_eq!(
+            cost_table.cost(&Terrain::None, MovementClass::Infantry),
+            Some(Ratio::from_integer(1))
+        );
+        assert_eq!(
+            cost_table.cost(&Terrain::Sand, MovementClass::Infantry),
+            Some(Ratio::new(5, 2))
+        );
+        assert_eq!(
+            cost_table.cost(&Terrain::Sand, MovementClass::Flier),
+            Some(Ratio::from_integer(1))
+        );
+        assert_eq!(cost_table.cost(&Terrain::Wall, MovementClass::Flier), None);
+        // Classes missing from the table can't move anywhere
+        assert_eq!(cost_table.cost(&Terrain::None, MovementClass::Armor), None);
+    }
+
+    #[test]
+    fn test_cost_table_wrong_column_count() {
+        let costs = indoc!(
+            "
+            terrain  infantry  flier
+            none     1
+        "
+        );
+        let mut cursor = Cursor::new(costs);
+        assert!(parse_cost_table_from_bufread(&mut cursor, "").is_err());
+    }
+
+    #[test]
+    fn test_cost_table_unknown_names() {
+        let mut cursor = Cursor::new("terrain  swimmer\nnone 1\n");
+        assert!(parse_cost_table_from_bufread(&mut cursor, "").is_err());
+        let mut cursor = Cursor::new("terrain  infantry\nlava 1\n");
+        assert!(parse_cost_table_from_bufread(&mut cursor, "").is_err());
+    }
+
+    #[test]
+    fn test_cost_table_costs_below_one() {
+        for cost in ["0", "-1", "0.5", "1/2"].iter() {
+            let mut cursor = Cursor::new(format!("terrain  infantry\nnone {}\n", cost));
+            assert!(parse_cost_table_from_bufread(&mut cursor, "").is_err());
+        }
+    }
+
+    #[test]
+    fn test_all_terrain_chars() {
+        let mut cursor = Cursor::new("0wsfm~Fgvtbdc\n");
+        let parsed_walls = parse_walls_from_bufread(&mut cursor, "").unwrap();
+        let parsed: Vec<Terrain> = parsed_walls.iter().map(|col| col[0]).collect();
+        assert_eq!(
+            parsed,
+            vec![
+                Terrain::None,
+                Terrain::Wall,
+                Terrain::Sand,
+                Terrain::Forest,
+                Terrain::Mountain,
+                Terrain::Water,
+                Terrain::Fort,
+                Terrain::Gate,
+                Terrain::Village,
+                Terrain::Throne,
+                Terrain::Bridge,
+                Terrain::Door,
+                Terrain::Chest,
+            ]
+        );
+        for terrain in parsed.iter() {
+            assert_eq!(Terrain::from_char(terrain.to_char()), Some(*terrain));
+            assert_eq!(Terrain::from_name(terrain.name()), Some(*terrain));
+        }
+    }
+
+    const CHAPTER: &str = r#"
+        (
+            name: "Test",
+            width: 3,
+            height: 2,
+            zone_of_control: true,
+            tiles: [
+                "00w",
+                "0t0",
+            ],
+            units: [
+                (id: 1, faction: Player, class: "lord", level: 1, location: (0, 0),
+                 items: ["Iron Sword", "Vulnerary"]),
+                (id: 2, faction: Enemy, class: "knight", level: 3, location: (1, 1),
+                 behavior: Guard((1, 1))),
+            ],
+            victory: Seize,
+            defeat: [UnitDies(1), TurnLimit(20)],
+            reinforcements: [
+                (turn: 3, unit: (id: 3, faction: Enemy, class: "archer", level: 2,
+                                 location: (2, 1), items: ["Iron Bow"])),
+            ],
+        )
+    "#;
+
+    #[test]
+    fn test_chapter() {
+        let mut cursor = Cursor::new(CHAPTER);
+        let chapter = parse_chapter_from_bufread(&mut cursor, "").unwrap();
+        assert_eq!(chapter.name, "Test");
+        assert_eq!((chapter.width, chapter.height), (3, 2));
+        assert!(chapter.zone_of_control);
+        assert_eq!(chapter.terrain[2][0], Terrain::Wall);
+        assert_eq!(chapter.terrain[1][1], Terrain::Throne);
+        assert_eq!(chapter.units.len(), 2);
+        assert_eq!(chapter.units[0].inventory.len(), 2);
+        assert_eq!(chapter.units[0].behavior, Behavior::Aggressive);
+        assert_eq!(chapter.units[1].class, Class::Knight);
+        assert_eq!(chapter.units[1].faction, Faction::Enemy);
+        assert_eq!(chapter.units[1].behavior, Behavior::Guard((1, 1)));
+        assert_eq!(chapter.victory, VictoryCondition::Seize);
+        assert_eq!(
+            chapter.defeat,
+            vec![DefeatCondition::UnitDies(1), DefeatCondition::TurnLimit(20)]
+        );
+        assert!(chapter.reinforcements_on(2).is_empty());
+        assert_eq!(chapter.reinforcements_on(3)[0].id, 3);
+    }
+
+    #[test]
+    fn test_chapter_invalid() {
+        let invalid = vec![
+            CHAPTER.replace("width: 3", "width: 4"),
+            CHAPTER.replace("\"0t0\"", "\"0?0\""),
+            CHAPTER.replace("\"knight\"", "\"dragon\""),
+            CHAPTER.replace("\"Iron Bow\"", "\"Bow of Legend\""),
+            CHAPTER.replace("location: (1, 1)", "location: (0, 0)"),
+            CHAPTER.replace("location: (2, 1)", "location: (3, 1)"),
+            CHAPTER.replace("location: (0, 0)", "location: (2, 0)"),
+            CHAPTER.replace("location: (2, 1)", "location: (2, 0)"),
+            CHAPTER.replace("id: 3", "id: 2"),
+            CHAPTER.replace("victory: Seize", "victory: Escape"),
+            CHAPTER.replace("UnitDies(1)", "UnitDies(4)"),
+        ];
+        for chapter in invalid.iter() {
+            let mut cursor = Cursor::new(chapter);
+            assert!(parse_chapter_from_bufread(&mut cursor, "").is_err());
+        }
+    }
+
+    #[test]
+    fn test_script() {
+        let script = indoc!(
+            "
+            # turn unit destination action target
+            1 1 3,4 attack 4,4
+            2 1 5,5  seize
+        "
+        );
+        let mut cursor = Cursor::new(script);
+        let script = parse_script_from_bufread(&mut cursor, "").unwrap();
+        assert_eq!(script.orders.len(), 2);
+        let order = script.order_for(1, 1).unwrap();
+        assert_eq!(order.destination, (3, 4));
+        assert_eq!(order.action, Action::Attack);
+        assert_eq!(order.target, Some((4, 4)));
+        let order = script.order_for(2, 1).unwrap();
+        assert_eq!(order.action, Action::Seize);
+        assert_eq!(order.target, None);
+    }
+
+    #[test]
+    fn test_script_invalid() {
+        let bad_lines = [
+            "1 1 3,4",
+            "1 1 3,4 attack",
+            "1 1 3,4 wait 4,4",
+            "1 1 3,4 trade 4,4",
+            "1 1 3;4 wait",
+            "1 1 3,4,5 wait",
+            "one 1 3,4 wait",
+            "1 1 3,4 dance",
+        ];
+        for line in bad_lines.iter() {
+            let mut cursor = Cursor::new(format!("1 1 0,0 wait\n{}\n", line));
+            match parse_script_from_bufread(&mut cursor, "script.txt") {
+                Err(DataParserErr::InvalidScriptData(path, line)) => {
+                    assert_eq!((path.as_str(), line), ("script.txt", 2))
+                }
+                other => panic!("{:?} parsed as {:?}", line, other),
+            }
+        }
+    }
 }
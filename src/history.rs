@@ -0,0 +1,257 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use battle::Battle;
+use save;
+use unit::Unit;
+
+// How many times a chapter can be rewound.
+pub const DEFAULT_REWINDS: u32 = 3;
+
+// A player unit walking from origin to destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Move {
+    pub origin: (u32, u32),
+    pub destination: (u32, u32),
+}
+
+// What the player did during the current player phase. Moves that haven't
+// been acted on can be undone and redone freely. Once a unit acts its moves
+// are committed, but the whole battle can still be rewound to before one of
+// the phase's actions a limited number of times.
+//
+// Battles are saved with save::save_to_string() to be restored later.
+// Restoring one replaces every unit, so frontends must not hold on to units
+// across undo() and rewind().
+#[derive(Debug, Clone)]
+pub struct History {
+    // Moves that haven't been acted on yet with the battle before each,
+    // latest last
+    moves: Vec<(Move, String)>,
+    // Moves that were undone, latest undo last. Cleared by any new move.
+    undone: Vec<Move>,
+    // The battle before each action committed this phase, oldest first
+    snapshots: Vec<String>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        History {
+            moves: vec![],
+            undone: vec![],
+            snapshots: vec![],
+        }
+    }
+
+    // The move that undo() would take back.
+    pub fn last_move(&self) -> Option<Move> {
+        self.moves.last().map(|&(step, _)| step)
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    // Number of actions that can be rewound.
+    pub fn actions(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    // Moves the player unit on origin and records the move.
+    pub fn move_unit(
+        &mut self,
+        battle: &mut Battle,
+        origin: (u32, u32),
+        destination: (u32, u32),
+    ) -> Rc<RefCell<Unit>> {
+        self.undone.clear();
+        self.record(
+            battle,
+            Move {
+                origin,
+                destination,
+            },
+        )
+    }
+
+    // A battle that can't be saved can't be restored either, so the move is
+    // made without being recorded and the pending moves are forgotten.
+    fn record(&mut self, battle: &mut Battle, step: Move) -> Rc<RefCell<Unit>> {
+        match save::save_to_string(battle) {
+            Ok(before) => self.moves.push((step, before)),
+            Err(_) => {
+                self.moves.clear();
+                self.undone.clear();
+            }
+        }
+        battle.move_unit(step.origin, step.destination)
+    }
+
+    // Puts the battle back to how it was before the last move, undoing
+    // anything the unit did since that doesn't end its turn, like trading.
+    pub fn undo(&mut self, battle: &mut Battle) -> Option<Move> {
+        let (step, before) = self.moves.pop()?;
+        let cost_table = battle.cost_table.clone();
+        match save::load_from_str(&before, cost_table) {
+            Ok(restored) => *battle = restored,
+            Err(_) => battle.undo_move(step.destination, step.origin),
+        }
+        self.undone.push(step);
+        Some(step)
+    }
+
+    // Makes the last undone move again. Returns None if there is nothing to
+    // redo or the move can't be made any more.
+    pub fn redo(&mut self, battle: &mut Battle) -> Option<Move> {
+        let step = self.undone.pop()?;
+        let possible = battle.units.get(&step.origin).map_or(false, |unit| {
+            let unit = unit.borrow();
+            battle.can_move(&unit)
+                && (step.destination == step.origin
+                    || unit.reachable_coords.contains(&step.destination))
+        });
+        if !possible {
+            self.undone.clear();
+            return None;
+        }
+        self.record(battle, step);
+        Some(step)
+    }
+
+    // The unit that made the pending moves has acted, so they can't be
+    // undone any more.
+    pub fn commit(&mut self) {
+        if !self.moves.is_empty() {
+            let (_, before) = self.moves.remove(0);
+            self.snapshots.push(before);
+        }
+        self.moves.clear();
+        self.undone.clear();
+    }
+
+    // Puts the battle back to how it was before the phase's latest actions,
+    // RNG included, using up one of the battle's rewinds.
+    pub fn rewind(&mut self, battle: &mut Battle, actions: usize) -> bool {
+        if battle.rewinds == 0 || actions == 0 || actions > self.snapshots.len() {
+            return false;
+        }
+        let index = self.snapshots.len() - actions;
+        let cost_table = battle.cost_table.clone();
+        let rewinds = battle.rewinds;
+        *battle = match save::load_from_str(&self.snapshots[index], cost_table) {
+            Ok(restored) => restored,
+            Err(_) => return false,
+        };
+        battle.rewinds = rewinds - 1;
+        self.snapshots.truncate(index);
+        self.moves.clear();
+        self.undone.clear();
+        true
+    }
+
+    // Forgets the phase, at the end of it or when another battle is loaded.
+    pub fn clear(&mut self) {
+        *self = History::new();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use action::TradeEntry;
+    use testing::{self, placement};
+    use unit::Faction;
+
+    fn battle() -> Battle {
+        let sword = &["Iron Sword"];
+        let chapter = testing::chapter(
+            6,
+            6,
+            vec![
+                placement(1, Faction::Player, (0, 0), sword),
+                placement(2, Faction::Player, (0, 1), sword),
+                placement(3, Faction::Enemy, (5, 5), sword),
+                placement(4, Faction::Enemy, (3, 0), sword),
+            ],
+        );
+        Battle::new(chapter, testing::cost_table(), 1)
+    }
+
+    #[test]
+    fn test_undo_and_redo() {
+        let mut battle = battle();
+        let mut history = History::new();
+        history.move_unit(&mut battle, (0, 0), (1, 0));
+        assert_eq!(
+            history.undo(&mut battle),
+            Some(Move {
+                origin: (0, 0),
+                destination: (1, 0),
+            })
+        );
+        assert!(battle.can_move(&battle.units[&(0, 0)].borrow()));
+        assert!(history.undo(&mut battle).is_none());
+
+        assert!(history.redo(&mut battle).is_some());
+        assert!(battle.units[&(1, 0)].borrow().has_moved);
+        assert!(!history.can_redo());
+
+        // A new move forgets what was undone
+        history.undo(&mut battle);
+        history.move_unit(&mut battle, (0, 0), (0, 0));
+        assert!(!history.can_redo());
+
+        // Acted on moves can't be undone
+        battle.wait((0, 0));
+        history.commit();
+        assert!(history.undo(&mut battle).is_none());
+        assert_eq!(history.actions(), 1);
+        // Nor can undone moves be redone once they're impossible
+        history.move_unit(&mut battle, (0, 1), (1, 1));
+        history.undo(&mut battle);
+        battle.move_unit((0, 1), (0, 2));
+        assert!(history.redo(&mut battle).is_none());
+    }
+
+    #[test]
+    fn test_undo_reverts_trades() {
+        let mut battle = battle();
+        let mut history = History::new();
+        history.move_unit(&mut battle, (0, 0), (1, 1));
+        assert!(battle.trade((1, 1), (0, 1), TradeEntry::Give(0)));
+        history.undo(&mut battle);
+        assert_eq!(battle.units[&(0, 0)].borrow().inventory.len(), 1);
+        assert_eq!(battle.units[&(0, 1)].borrow().inventory.len(), 1);
+        assert!(battle.can_move(&battle.units[&(0, 0)].borrow()));
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut battle = battle();
+        let mut history = History::new();
+        battle.rewinds = 1;
+        history.move_unit(&mut battle, (0, 0), (2, 0));
+        let first = battle.resolve_attack((2, 0), (3, 0)).unwrap();
+        history.commit();
+        battle.apply_combat((2, 0), (3, 0), &first);
+        history.move_unit(&mut battle, (0, 1), (1, 1));
+        battle.wait((1, 1));
+        history.commit();
+        assert_eq!(history.actions(), 2);
+        assert!(!history.rewind(&mut battle, 3));
+
+        assert!(history.rewind(&mut battle, 2));
+        assert_eq!(battle.rewinds, 0);
+        assert_eq!(history.actions(), 0);
+        assert!(battle.units.contains_key(&(0, 0)));
+        assert!(battle.units.contains_key(&(0, 1)));
+        assert_eq!(
+            battle.units[&(3, 0)].borrow().hp,
+            battle.units[&(3, 0)].borrow().stats.hp
+        );
+        // The same attack has the same result, since the RNG was rewound too
+        history.move_unit(&mut battle, (0, 0), (2, 0));
+        assert_eq!(battle.resolve_attack((2, 0), (3, 0)), Some(first));
+        history.commit();
+        assert!(!history.rewind(&mut battle, 1));
+    }
+}
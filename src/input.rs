@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use ggez::event::{Button, Keycode};
+use feg::dataparser::DataParserErr::InvalidBindingData;
+use feg::dataparser::Result;
+
+// Everything the player can do with keys or controller buttons.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Command {
+    Up,
+    Down,
+    Left,
+    Right,
+    Confirm,
+    Cancel,
+    // Move the cursor to the next unit that hasn't moved yet
+    NextUnit,
+    EndPhase,
+    ToggleDangerZone,
+    ToggleThreatCounts,
+    // Choose a slot to save to or load from
+    Save,
+    Load,
+    // Take back the selected unit's move, or make it again
+    Undo,
+    Redo,
+    Rewind,
+}
+
+impl Command {
+    // Name used for the command in the bindings file.
+    pub fn from_name(name: &str) -> Option<Command> {
+        match name {
+            "up" => Some(Command::Up),
+            "down" => Some(Command::Down),
+            "left" => Some(Command::Left),
+            "right" => Some(Command::Right),
+            "confirm" => Some(Command::Confirm),
+            "cancel" => Some(Command::Cancel),
+            "next_unit" => Some(Command::NextUnit),
+            "end_phase" => Some(Command::EndPhase),
+            "toggle_danger_zone" => Some(Command::ToggleDangerZone),
+            "toggle_threat_counts" => Some(Command::ToggleThreatCounts),
+            "save" => Some(Command::Save),
+            "load" => Some(Command::Load),
+            "undo" => Some(Command::Undo),
+            "redo" => Some(Command::Redo),
+            "rewind" => Some(Command::Rewind),
+            _ => None,
+        }
+    }
+
+    // (dx, dy) for the commands that move the cursor.
+    pub fn direction(&self) -> Option<(i32, i32)> {
+        match self {
+            &Command::Up => Some((0, -1)),
+            &Command::Down => Some((0, 1)),
+            &Command::Left => Some((-1, 0)),
+            &Command::Right => Some((1, 0)),
+            _ => None,
+        }
+    }
+}
+
+// Which keys and controller buttons trigger which commands.
+#[derive(Debug, Clone, Default)]
+pub struct Bindings {
+    keys: HashMap<Keycode, Command>,
+    buttons: HashMap<Button, Command>,
+}
+
+impl Bindings {
+    pub fn new() -> Self {
+        Bindings::default()
+    }
+
+    pub fn bind_key(&mut self, keycode: Keycode, command: Command) {
+        self.keys.insert(keycode, command);
+    }
+
+    pub fn bind_button(&mut self, button: Button, command: Command) {
+        self.buttons.insert(button, command);
+    }
+
+    pub fn key_command(&self, keycode: Keycode) -> Option<Command> {
+        self.keys.get(&keycode).cloned()
+    }
+
+    pub fn button_command(&self, button: Button) -> Option<Command> {
+        self.buttons.get(&button).cloned()
+    }
+}
+
+// Each line is a command name followed by the keys ("key:<SDL key name>")
+// and controller buttons ("button:<SDL button name>") bound to it. Lines
+// starting with '#' are comments.
+pub fn parse_bindings_from_bufread<T: BufRead + Sized, P: AsRef<Path>>(
+    buf_reader: &mut T,
+    path: P,
+) -> Result<Bindings> {
+    let err = || InvalidBindingData(path.as_ref().to_str().unwrap().to_string());
+    let mut bindings = Bindings::new();
+    for line in buf_reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let command = Command::from_name(fields.next().unwrap()).ok_or_else(&err)?;
+        for field in fields {
+            if field.starts_with("key:") {
+                let keycode = Keycode::from_name(&field[4..]).ok_or_else(&err)?;
+                bindings.bind_key(keycode, command);
+            } else if field.starts_with("button:") {
+                let button = Button::from_string(&field[7..]).ok_or_else(&err)?;
+                bindings.bind_button(button, command);
+            } else {
+                return Err(err());
+            }
+        }
+    }
+    Ok(bindings)
+}
+
+pub fn parse_bindings<P: AsRef<Path>>(path: P) -> Result<Bindings> {
+    let f = File::open(Path::new("resources").join(&path))?;
+    let mut buf_reader = BufReader::new(f);
+    parse_bindings_from_bufread(&mut buf_reader, &path)
+}
+
+// Turns held directions into repeated cursor steps that get faster the longer
+// the direction is held. We do this ourselves instead of relying on the OS so
+// that keys and the d-pad behave the same.
+#[derive(Debug, Clone)]
+pub struct KeyRepeat {
+    // Seconds before the first repeat
+    pub initial_delay: f32,
+    // Seconds between the first two repeats
+    pub initial_interval: f32,
+    pub min_interval: f32,
+    // The interval is multiplied by this after every repeat
+    pub acceleration: f32,
+    // Held directions, most recently pressed last
+    held: Vec<(i32, i32)>,
+    timer: f32,
+    interval: f32,
+}
+
+impl KeyRepeat {
+    pub fn new(
+        initial_delay: f32,
+        initial_interval: f32,
+        min_interval: f32,
+        acceleration: f32,
+    ) -> Self {
+        KeyRepeat {
+            initial_delay,
+            initial_interval,
+            min_interval,
+            acceleration,
+            held: vec![],
+            timer: 0.0,
+            interval: initial_interval,
+        }
+    }
+
+    // The most recently pressed direction is the one that repeats.
+    pub fn press(&mut self, direction: (i32, i32)) {
+        self.held.retain(|&held| held != direction);
+        self.held.push(direction);
+        self.restart();
+    }
+
+    pub fn release(&mut self, direction: (i32, i32)) {
+        let was_repeating = self.held.last() == Some(&direction);
+        self.held.retain(|&held| held != direction);
+        if was_repeating {
+            self.restart();
+        }
+    }
+
+    // Advances time by dt seconds. Returns the direction to step in if a
+    // repeat fired.
+    pub fn update(&mut self, dt: f32) -> Option<(i32, i32)> {
+        let direction = *self.held.last()?;
+        self.timer -= dt;
+        if self.timer > 0.0 {
+            return None;
+        }
+        self.timer += self.interval;
+        self.interval = self.interval * self.acceleration;
+        if self.interval < self.min_interval {
+            self.interval = self.min_interval;
+        }
+        Some(direction)
+    }
+
+    fn restart(&mut self) {
+        self.timer = self.initial_delay;
+        self.interval = self.initial_interval;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_key_repeat_accelerates() {
+        let mut repeat = KeyRepeat::new(0.5, 0.25, 0.0625, 0.5);
+        repeat.press((1, 0));
+        assert_eq!(repeat.update(0.25), None);
+        assert_eq!(repeat.update(0.25), Some((1, 0)));
+        assert_eq!(repeat.update(0.125), None);
+        assert_eq!(repeat.update(0.125), Some((1, 0)));
+        assert_eq!(repeat.update(0.125), Some((1, 0)));
+        // The interval bottoms out at min_interval
+        assert_eq!(repeat.update(0.0625), Some((1, 0)));
+        assert_eq!(repeat.update(0.0625), Some((1, 0)));
+    }
+
+    #[test]
+    fn test_key_repeat_latest_direction_wins() {
+        let mut repeat = KeyRepeat::new(0.5, 0.25, 0.0625, 0.5);
+        repeat.press((1, 0));
+        repeat.press((0, 1));
+        assert_eq!(repeat.update(0.5), Some((0, 1)));
+        repeat.release((0, 1));
+        assert_eq!(repeat.update(0.25), None);
+        assert_eq!(repeat.update(0.25), Some((1, 0)));
+        repeat.release((1, 0));
+        assert_eq!(repeat.update(1.0), None);
+    }
+
+    #[test]
+    fn test_bindings() {
+        let bindings = indoc!(
+            "
+            # comment
+            up       key:Up key:W button:dpup
+            confirm  key:Return
+        "
+        );
+        let mut cursor = Cursor::new(bindings);
+        let bindings = parse_bindings_from_bufread(&mut cursor, "").unwrap();
+        assert_eq!(bindings.key_command(Keycode::W), Some(Command::Up));
+        assert_eq!(
+            bindings.key_command(Keycode::Return),
+            Some(Command::Confirm)
+        );
+        assert_eq!(bindings.button_command(Button::DPadUp), Some(Command::Up));
+        assert_eq!(bindings.key_command(Keycode::S), None);
+    }
+
+    #[test]
+    fn test_bindings_invalid() {
+        for bindings in &["jump key:Up", "up key:NotAKey", "up Up"] {
+            let mut cursor = Cursor::new(bindings);
+            assert!(parse_bindings_from_bufread(&mut cursor, "").is_err());
+        }
+    }
+}
@@ -0,0 +1,437 @@
+use class::Class;
+
+pub const INVENTORY_SIZE: usize = 5;
+// Bonus to damage and hit for the advantaged side of the weapon triangle (and
+// penalty for the disadvantaged side)
+pub const TRIANGLE_DAMAGE_BONUS: i32 = 1;
+pub const TRIANGLE_HIT_BONUS: i32 = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WeaponType {
+    Sword,
+    Lance,
+    Axe,
+    Bow,
+    Anima,
+    Staff,
+}
+
+impl WeaponType {
+    // Magic weapons are targeted at resistance instead of defense and use the
+    // wielder's magic instead of strength.
+    pub fn is_magic(&self) -> bool {
+        match self {
+            &WeaponType::Anima | &WeaponType::Staff => true,
+            _ => false,
+        }
+    }
+
+    // Swords beat axes, axes beat lances and lances beat swords. Returns 1 if
+    // self has the advantage over other, -1 if it's at a disadvantage and 0
+    // otherwise.
+    pub fn triangle_advantage(&self, other: &WeaponType) -> i32 {
+        match (self, other) {
+            (&WeaponType::Sword, &WeaponType::Axe)
+            | (&WeaponType::Axe, &WeaponType::Lance)
+            | (&WeaponType::Lance, &WeaponType::Sword) => 1,
+            (&WeaponType::Axe, &WeaponType::Sword)
+            | (&WeaponType::Lance, &WeaponType::Axe)
+            | (&WeaponType::Sword, &WeaponType::Lance) => -1,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum WeaponRank {
+    E,
+    D,
+    C,
+    B,
+    A,
+    S,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Weapon {
+    pub name: String,
+    pub weapon_type: WeaponType,
+    // Minimum rank needed to wield the weapon
+    pub rank: WeaponRank,
+    pub might: u32,
+    pub hit: u32,
+    pub crit: u32,
+    pub weight: u32,
+    pub min_range: u32,
+    pub max_range: u32,
+    // Remaining durability. The weapon breaks when this reaches 0.
+    pub uses: u32,
+    pub max_uses: u32,
+}
+
+impl Weapon {
+    pub fn new(
+        name: &str,
+        weapon_type: WeaponType,
+        rank: WeaponRank,
+        might: u32,
+        hit: u32,
+        crit: u32,
+        weight: u32,
+        (min_range, max_range): (u32, u32),
+        max_uses: u32,
+    ) -> Self {
+        Weapon {
+            name: name.to_string(),
+            weapon_type,
+            rank,
+            might,
+            hit,
+            crit,
+            weight,
+            min_range,
+            max_range,
+            uses: max_uses,
+            max_uses,
+        }
+    }
+
+    pub fn in_range(&self, distance: u32) -> bool {
+        distance >= self.min_range && distance <= self.max_range
+    }
+}
+
+// Heals an ally in range. The amount healed is heal plus the wielder's magic.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Staff {
+    pub name: String,
+    pub rank: WeaponRank,
+    pub heal: u32,
+    pub min_range: u32,
+    pub max_range: u32,
+    pub uses: u32,
+    pub max_uses: u32,
+}
+
+impl Staff {
+    pub fn in_range(&self, distance: u32) -> bool {
+        distance >= self.min_range && distance <= self.max_range
+    }
+}
+
+// Heals the user.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Consumable {
+    pub name: String,
+    pub heal: u32,
+    pub uses: u32,
+    pub max_uses: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Item {
+    Weapon(Weapon),
+    Staff(Staff),
+    Consumable(Consumable),
+}
+
+impl Item {
+    // Looks up one of the standard items.
+    pub fn by_name(name: &str) -> Option<Item> {
+        use self::WeaponRank::*;
+        use self::WeaponType::*;
+        let weapon = |weapon_type, rank, might, hit, crit, weight, range, uses| {
+            Some(Item::Weapon(Weapon::new(
+                name,
+                weapon_type,
+                rank,
+                might,
+                hit,
+                crit,
+                weight,
+                range,
+                uses,
+            )))
+        };
+        match name {
+            "Iron Sword" => weapon(Sword, E, 5, 90, 0, 5, (1, 1), 46),
+            "Slim Sword" => weapon(Sword, E, 3, 100, 5, 2, (1, 1), 30),
+            "Steel Sword" => weapon(Sword, D, 8, 75, 0, 10, (1, 1), 30),
+            "Iron Lance" => weapon(Lance, E, 7, 80, 0, 8, (1, 1), 45),
+            "Javelin" => weapon(Lance, E, 6, 65, 0, 11, (1, 2), 20),
+            "Iron Axe" => weapon(Axe, E, 8, 75, 0, 10, (1, 1), 45),
+            "Hand Axe" => weapon(Axe, E, 7, 60, 0, 12, (1, 2), 20),
+            "Iron Bow" => weapon(Bow, E, 6, 85, 0, 5, (2, 2), 45),
+            "Longbow" => weapon(Bow, D, 5, 65, 0, 10, (2, 3), 20),
+            "Fire" => weapon(Anima, E, 5, 90, 0, 4, (1, 2), 40),
+            "Heal" => Some(Item::Staff(self::Staff {
+                name: name.to_string(),
+                rank: E,
+                heal: 10,
+                min_range: 1,
+                max_range: 1,
+                uses: 30,
+                max_uses: 30,
+            })),
+            "Vulnerary" => Some(Item::Consumable(Consumable {
+                name: name.to_string(),
+                heal: 10,
+                uses: 3,
+                max_uses: 3,
+            })),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            &Item::Weapon(ref weapon) => &weapon.name,
+            &Item::Staff(ref staff) => &staff.name,
+            &Item::Consumable(ref consumable) => &consumable.name,
+        }
+    }
+
+    pub fn uses(&self) -> u32 {
+        match self {
+            &Item::Weapon(ref weapon) => weapon.uses,
+            &Item::Staff(ref staff) => staff.uses,
+            &Item::Consumable(ref consumable) => consumable.uses,
+        }
+    }
+
+    fn uses_mut(&mut self) -> &mut u32 {
+        match self {
+            &mut Item::Weapon(ref mut weapon) => &mut weapon.uses,
+            &mut Item::Staff(ref mut staff) => &mut staff.uses,
+            &mut Item::Consumable(ref mut consumable) => &mut consumable.uses,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Inventory {
+    items: Vec<Item>,
+}
+
+impl Inventory {
+    pub fn new() -> Self {
+        Inventory::default()
+    }
+
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.items.len() >= INVENTORY_SIZE
+    }
+
+    // Gives the item back if the inventory is full.
+    pub fn add(&mut self, item: Item) -> Result<(), Item> {
+        if self.is_full() {
+            return Err(item);
+        }
+        self.items.push(item);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Item> {
+        if index < self.items.len() {
+            Some(self.items.remove(index))
+        } else {
+            None
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Item> {
+        self.items.get(index)
+    }
+
+    // Index of the equipped weapon. Like in Fire Emblem, the equipped weapon is
+    // the first weapon in the inventory that the unit can wield.
+    pub fn equipped_weapon_index(&self, class: &Class) -> Option<usize> {
+        self.items.iter().position(|item| match item {
+            &Item::Weapon(ref weapon) => class.can_wield(weapon.weapon_type, weapon.rank),
+            _ => false,
+        })
+    }
+
+    pub fn equipped_weapon(&self, class: &Class) -> Option<&Weapon> {
+        self.equipped_weapon_index(class)
+            .and_then(|index| match self.items[index] {
+                Item::Weapon(ref weapon) => Some(weapon),
+                _ => None,
+            })
+    }
+
+    // All weapons the unit can wield.
+    pub fn usable_weapons(&self, class: &Class) -> Vec<&Weapon> {
+        self.items
+            .iter()
+            .filter_map(|item| match item {
+                &Item::Weapon(ref weapon) if class.can_wield(weapon.weapon_type, weapon.rank) => {
+                    Some(weapon)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    // All staves the unit can wield.
+    pub fn usable_staves(&self, class: &Class) -> Vec<&Staff> {
+        self.items
+            .iter()
+            .filter_map(|item| match item {
+                &Item::Staff(ref staff) if class.can_wield(WeaponType::Staff, staff.rank) => {
+                    Some(staff)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Index of the first weapon the unit can wield that reaches distance.
+    pub fn weapon_index_in_range(&self, class: &Class, distance: u32) -> Option<usize> {
+        self.items.iter().position(|item| match item {
+            &Item::Weapon(ref weapon) => {
+                class.can_wield(weapon.weapon_type, weapon.rank) && weapon.in_range(distance)
+            }
+            _ => false,
+        })
+    }
+
+    // Index of the first staff the unit can wield that reaches distance.
+    pub fn staff_index_in_range(&self, class: &Class, distance: u32) -> Option<usize> {
+        self.items.iter().position(|item| match item {
+            &Item::Staff(ref staff) => {
+                class.can_wield(WeaponType::Staff, staff.rank) && staff.in_range(distance)
+            }
+            _ => false,
+        })
+    }
+
+    // Moves the weapon at index to the front of the inventory so that it
+    // becomes the equipped weapon. Returns false if the item at index isn't a
+    // weapon the unit can wield.
+    pub fn equip(&mut self, index: usize, class: &Class) -> bool {
+        let can_equip = match self.items.get(index) {
+            Some(&Item::Weapon(ref weapon)) => class.can_wield(weapon.weapon_type, weapon.rank),
+            _ => false,
+        };
+        if !can_equip {
+            return false;
+        }
+        let item = self.items.remove(index);
+        self.items.insert(0, item);
+        true
+    }
+
+    // Uses up count uses of the item at index, removing it if it breaks.
+    // Returns true if the item broke.
+    pub fn use_item(&mut self, index: usize, count: u32) -> bool {
+        let broke = match self.items.get_mut(index) {
+            Some(item) => {
+                let uses = item.uses_mut();
+                *uses = uses.saturating_sub(count);
+                *uses == 0
+            }
+            None => return false,
+        };
+        if broke {
+            self.items.remove(index);
+        }
+        broke
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn item(name: &str) -> Item {
+        Item::by_name(name).unwrap()
+    }
+
+    #[test]
+    fn test_triangle() {
+        assert_eq!(WeaponType::Sword.triangle_advantage(&WeaponType::Axe), 1);
+        assert_eq!(WeaponType::Axe.triangle_advantage(&WeaponType::Sword), -1);
+        assert_eq!(WeaponType::Lance.triangle_advantage(&WeaponType::Sword), 1);
+        assert_eq!(WeaponType::Axe.triangle_advantage(&WeaponType::Lance), 1);
+        assert_eq!(WeaponType::Bow.triangle_advantage(&WeaponType::Sword), 0);
+        assert_eq!(WeaponType::Sword.triangle_advantage(&WeaponType::Sword), 0);
+    }
+
+    #[test]
+    fn test_inventory_size() {
+        let mut inventory = Inventory::new();
+        for _ in 0..INVENTORY_SIZE {
+            assert!(inventory.add(item("Vulnerary")).is_ok());
+        }
+        assert!(inventory.is_full());
+        assert_eq!(inventory.add(item("Iron Sword")), Err(item("Iron Sword")));
+    }
+
+    #[test]
+    fn test_equipped_weapon_skips_unusable() {
+        let mut inventory = Inventory::new();
+        inventory.add(item("Vulnerary")).unwrap();
+        inventory.add(item("Iron Axe")).unwrap();
+        inventory.add(item("Iron Sword")).unwrap();
+        assert_eq!(inventory.equipped_weapon_index(&Class::Mercenary), Some(2));
+        assert_eq!(
+            inventory.equipped_weapon(&Class::Mercenary).unwrap().name,
+            "Iron Sword"
+        );
+        assert_eq!(inventory.usable_weapons(&Class::Mercenary).len(), 1);
+    }
+
+    #[test]
+    fn test_equip() {
+        let mut inventory = Inventory::new();
+        inventory.add(item("Iron Sword")).unwrap();
+        inventory.add(item("Iron Axe")).unwrap();
+        inventory.add(item("Steel Sword")).unwrap();
+        assert!(!inventory.equip(1, &Class::Mercenary));
+        assert!(inventory.equip(2, &Class::Mercenary));
+        assert_eq!(
+            inventory.equipped_weapon(&Class::Mercenary).unwrap().name,
+            "Steel Sword"
+        );
+        assert_eq!(inventory.get(1), Some(&item("Iron Sword")));
+    }
+
+    #[test]
+    fn test_rank_too_low() {
+        let silver_sword = Weapon::new(
+            "Silver Sword",
+            WeaponType::Sword,
+            WeaponRank::A,
+            13,
+            80,
+            0,
+            8,
+            (1, 1),
+            20,
+        );
+        let mut inventory = Inventory::new();
+        inventory.add(Item::Weapon(silver_sword)).unwrap();
+        inventory.add(item("Longbow")).unwrap();
+        assert_eq!(inventory.equipped_weapon(&Class::Mercenary), None);
+        assert!(!inventory.equip(0, &Class::Mercenary));
+    }
+
+    #[test]
+    fn test_use_item_breaks() {
+        let mut inventory = Inventory::new();
+        inventory.add(item("Vulnerary")).unwrap();
+        assert!(!inventory.use_item(0, 2));
+        assert_eq!(inventory.get(0).unwrap().uses(), 1);
+        assert!(inventory.use_item(0, 1));
+        assert_eq!(inventory.len(), 0);
+    }
+}
@@ -0,0 +1,42 @@
+#![feature(vec_remove_item)]
+// The game model: maps, units, and the rules for playing out a chapter.
+// Nothing in here depends on ggez, so the whole game can be run and tested
+// without a GPU. The feg binary is the graphical frontend.
+#[cfg(test)]
+#[macro_use]
+extern crate indoc;
+#[cfg(test)]
+#[macro_use]
+extern crate maplit;
+extern crate num;
+extern crate ron;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+#[macro_use]
+pub mod utils;
+pub mod action;
+pub mod ai;
+pub mod battle;
+pub mod chapter;
+pub mod class;
+pub mod combat;
+pub mod dataparser;
+pub mod history;
+pub mod item;
+pub mod menu;
+pub mod movement;
+pub mod pathfinding;
+pub mod rng;
+pub mod save;
+pub mod script;
+pub mod session;
+pub mod skill;
+pub mod stats;
+pub mod terrain;
+pub mod turn;
+pub mod unit;
+
+#[cfg(test)]
+mod testing;
@@ -1,41 +1,40 @@
-#![feature(vec_remove_item)]
+#[macro_use]
+extern crate feg;
 extern crate ggez;
 #[cfg(test)]
 #[macro_use]
 extern crate indoc;
-#[cfg(test)]
-#[macro_use]
-extern crate maplit;
-extern crate num;
 
-#[macro_use]
-mod utils;
-mod dataparser;
-mod pathfinding;
+mod input;
 mod mainstate;
-mod terrain;
-mod unit;
 
 use ggez::{event, graphics, timer, Context, ContextBuilder, GameResult};
-use ggez::event::{EventHandler, MouseButton, MouseState};
-use ggez::graphics::{Color, DrawMode, DrawParam, Drawable, Image, Point2};
+use ggez::event::{Button, EventHandler, Keycode, Mod, MouseButton, MouseState};
+use ggez::graphics::{Color, DrawParam, Drawable, Point2};
 use ggez::conf::{WindowMode, WindowSetup};
 use std::env;
 use std::path;
-use num::rational::Ratio;
-use num::Zero;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use feg::battle::{Battle, Outcome};
+use feg::save::{self, Slot};
+use feg::session::Mode;
+use feg::{dataparser, pathfinding};
 use mainstate::*;
 
 impl EventHandler for MainState {
     fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
         while timer::check_update_time(ctx, self.fps) {
-            let unit = self.units[&(3, 3)].clone();
-            let mut unit = unit.borrow_mut();
-            unit.animation_tick += 2.0 / (self.fps as f32);
+            self.update_edge_scroll();
+            self.update_key_repeat();
+            self.update_movement();
+            self.update_combat();
+            self.update_phase();
+            self.update_status();
 
-            if unit.animation_tick >= 5.0 {
-                unit.animation_tick -= 5.0;
+            self.animation_tick += 2.0 / (self.fps as f32);
+            if self.animation_tick >= self.unit_sprites.len() as f32 {
+                self.animation_tick -= self.unit_sprites.len() as f32;
             }
         }
         Ok(())
@@ -50,33 +49,58 @@ impl EventHandler for MainState {
         let fps_txt = graphics::Text::new(ctx, &fps, &self.font)?;
         fps_txt.draw(ctx, Point2::new(self.window_width as f32 - 40.0, 20.0), 0.0)?;
 
+        let turn = format!(
+            "{}  Turn {}",
+            self.session.battle.chapter.name, self.session.battle.turn_manager.turn
+        );
+        let turn_txt = graphics::Text::new(ctx, &turn, &self.font)?;
+        turn_txt.draw(ctx, Point2::new(20.0, 20.0), 0.0)?;
+
         // Draw terrain
-        self.wall_sb.draw(ctx, Point2::new(0.0, 0.0), 0.0)?;
-        self.sand_sb.draw(ctx, Point2::new(0.0, 0.0), 0.0)?;
+        for sb in self.terrain_sbs.values() {
+            sb.draw(ctx, Point2::new(0.0, 0.0), 0.0)?;
+        }
 
-        let unit = self.units[&(3, 3)].clone();
-        let unit = unit.borrow();
+        if self.show_danger_zone {
+            self.draw_cell_overlay(
+                ctx,
+                self.session.battle.danger_zone.keys(),
+                Color::from_rgba(128, 0, 200, 50),
+            )?;
+        }
 
-        for &coord in unit.reachable_coords.iter() {
-            let old_color = graphics::get_color(ctx);
-            graphics::set_color(ctx, Color::from_rgba(255, 84, 163, 60))?;
-            let (x, y) = self.grid_to_screen_coord(coord);
-            graphics::rectangle(
+        let selected_unit = match (&self.combat, &self.session.mode) {
+            // Ranges aren't interesting once the unit has moved
+            (&None, &Mode::Normal) => self
+                .session
+                .selected
+                .and_then(|location| self.session.battle.units.get(&location)),
+            _ => None,
+        };
+        if let Some(unit) = selected_unit {
+            let unit = unit.borrow();
+            self.draw_cell_overlay(
                 ctx,
-                DrawMode::Fill,
-                graphics::Rect {
-                    x: x as f32,
-                    y: y as f32,
-                    w: (self.grid_cell_dim - self.grid_line_width) as f32,
-                    h: (self.grid_cell_dim - self.grid_line_width) as f32,
-                },
+                unit.reachable_coords.iter(),
+                Color::from_rgba(255, 84, 163, 60),
             )?;
-            graphics::set_color(ctx, old_color)?;
+            self.draw_cell_overlay(
+                ctx,
+                unit.attack_coords.iter(),
+                Color::from_rgba(230, 30, 30, 70),
+            )?;
+            self.draw_border(ctx, &unit.border)?;
         }
 
-        // Draw selection
-        if let Some((grid_x, grid_y)) = self.selection {
-            if self.selection != self.screen_to_grid_coord(self.mouse_coords) {
+        // Draw selection, which is where the unit is going while it walks
+        let selection = match self.movement {
+            Some(ref movement) => Some(movement.destination()),
+            None => self.session.selected,
+        };
+        if let Some((grid_x, grid_y)) = selection {
+            if selection != Some(self.session.cursor)
+                && self.is_grid_coord_visible((grid_x, grid_y))
+            {
                 let (screen_x, screen_y) = self.grid_to_screen_coord((grid_x, grid_y));
                 let old_color = graphics::get_color(ctx);
                 graphics::set_color(ctx, graphics::Color::from_rgb(255, 255, 0))?;
@@ -86,25 +110,45 @@ impl EventHandler for MainState {
             }
         }
 
-        // Draw highlighted grid cell and path
-        match self.screen_to_grid_coord(self.mouse_coords) {
+        // Draw cursor and path
+        let cursor = if self.is_grid_coord_visible(self.session.cursor) {
+            Some(self.session.cursor)
+        } else {
+            None
+        };
+        match cursor {
             Some((grid_x, grid_y)) => {
-                let mut cpath_segments = self.cpath_to_segments(pathfinding::consolidate_path(
-                    pathfinding::get_path((grid_x, grid_y), &(unit.paths)),
-                ));
-                if cpath_segments.len() > 1 {
-                    // Don't draw path when cursor is on the unit itself
-                    for segment in cpath_segments.chunks(2) {
-                        let start = segment[0];
-                        let end = segment[1];
-                        graphics::line(
-                            ctx,
-                            &[
-                                Point2::new(start.0 as f32, start.1 as f32),
-                                Point2::new(end.0 as f32, end.1 as f32),
-                            ],
-                            self.path_line_width as f32,
-                        )?;
+                let selected_unit = match (&self.movement, &self.session.mode) {
+                    (&None, &Mode::Normal) => self
+                        .session
+                        .selected
+                        .and_then(|location| self.session.battle.units.get(&location)),
+                    // Don't draw the path of a unit that is already walking
+                    // or has moved
+                    _ => None,
+                };
+                if let Some(unit) = selected_unit {
+                    let unit = unit.borrow();
+                    let path = pathfinding::get_path((grid_x, grid_y), &(unit.paths));
+                    let cpath_segments =
+                        self.cpath_to_segments(pathfinding::consolidate_path(path));
+                    if cpath_segments.len() > 1 {
+                        // Don't draw path when cursor is on the unit itself
+                        for segment in cpath_segments.chunks(2) {
+                            // Only the part of the path inside the viewport
+                            let (start, end) = match self.clip_segment(segment[0], segment[1]) {
+                                Some(clipped) => clipped,
+                                None => continue,
+                            };
+                            graphics::line(
+                                ctx,
+                                &[
+                                    Point2::new(start.0, start.1),
+                                    Point2::new(end.0, end.1),
+                                ],
+                                self.path_line_width as f32,
+                            )?;
+                        }
                     }
                 }
                 let (rect_x, rect_y) = self.grid_to_screen_coord((grid_x, grid_y));
@@ -117,15 +161,54 @@ impl EventHandler for MainState {
             None => (),
         }
 
-        // Draw animated sprite
-        let screen_coord = self.grid_to_screen_coord((3, 3));
-        unit.animation_sprites[unit.animation_tick as usize].draw_ex(
-            ctx,
-            DrawParam {
-                dest: Point2::new(screen_coord.0 as f32, screen_coord.1 as f32),
-                ..DrawParam::default()
-            },
-        )?;
+        // Draw animated sprites
+        for (&location, unit) in self.session.battle.units.iter() {
+            let grid_position = self.unit_grid_position(location);
+            // Walking units are drawn as long as they are mostly in view
+            let cell = (
+                (grid_position.0 + 0.5) as u32,
+                (grid_position.1 + 0.5) as u32,
+            );
+            if !self.is_grid_coord_visible(cell) {
+                continue;
+            }
+            let unit = unit.borrow();
+            let screen_coord = self.grid_position_to_screen_coord(grid_position);
+            // Grey out units that are done for this turn
+            let color = if unit.is_spent() {
+                Some(Color::from_rgb(110, 110, 110))
+            } else {
+                None
+            };
+            self.unit_sprites[self.animation_tick as usize].draw_ex(
+                ctx,
+                DrawParam {
+                    dest: Point2::new(screen_coord.0, screen_coord.1),
+                    color,
+                    ..DrawParam::default()
+                },
+            )?;
+        }
+
+        if self.show_danger_zone && self.show_threat_counts {
+            self.draw_threat_counts(ctx)?;
+        }
+
+        self.draw_targets(ctx)?;
+        self.draw_menu(ctx)?;
+        self.draw_combat(ctx)?;
+        self.draw_status(ctx)?;
+
+        match self.session.battle.outcome {
+            Some(Outcome::Victory) => {
+                self.draw_banner(ctx, "Chapter Clear", Color::from_rgba(200, 160, 30, 200))?
+            }
+            Some(Outcome::Defeat) => {
+                self.draw_banner(ctx, "Game Over", Color::from_rgba(90, 0, 0, 220))?
+            }
+            None => self.draw_phase_banner(ctx)?,
+        }
+
         graphics::present(ctx);
         timer::yield_now();
         Ok(())
@@ -141,23 +224,60 @@ impl EventHandler for MainState {
         _yrel: i32,
     ) {
         self.mouse_coords = (x as u32, y as u32);
+        self.hover((x as u32, y as u32));
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: Keycode,
+        _keymod: Mod,
+        repeat: bool,
+    ) {
+        // Held keys are repeated by key_repeat instead
+        if repeat {
+            return;
+        }
+        if let Some(command) = self.bindings.key_command(keycode) {
+            self.press_command(command);
+        }
+    }
+
+    fn key_up_event(&mut self, _ctx: &mut Context, keycode: Keycode, _keymod: Mod, _repeat: bool) {
+        if let Some(command) = self.bindings.key_command(keycode) {
+            self.release_command(command);
+        }
+    }
+
+    fn controller_button_down_event(&mut self, _ctx: &mut Context, btn: Button, _instance_id: i32) {
+        if let Some(command) = self.bindings.button_command(btn) {
+            self.press_command(command);
+        }
+    }
+
+    fn controller_button_up_event(&mut self, _ctx: &mut Context, btn: Button, _instance_id: i32) {
+        if let Some(command) = self.bindings.button_command(btn) {
+            self.release_command(command);
+        }
     }
 
     fn mouse_button_up_event(&mut self, _ctx: &mut Context, button: MouseButton, x: i32, y: i32) {
         match button {
-            MouseButton::Left => {
-                self.selection = self.screen_to_grid_coord((x as u32, y as u32));
-                match self.selection {
-                    Some(grid_coord) => match self.units.get(&grid_coord) {
-                        Some(unit) => self.selected_unit = Some(unit.clone()),
-                        None => self.selected_unit = None,
-                    },
-                    None => self.selected_unit = None,
-                }
-            }
+            MouseButton::Left => self.click((x as u32, y as u32)),
+            MouseButton::Right => self.cancel(),
             _ => (),
         }
     }
+
+    // Quitting is cancelled the first time suspending fails, so that the
+    // player can see why
+    fn quit_event(&mut self, _ctx: &mut Context) -> bool {
+        if self.suspend_failed {
+            return false;
+        }
+        self.suspend_failed = !self.suspend();
+        self.suspend_failed
+    }
 }
 
 fn main() {
@@ -177,51 +297,36 @@ fn main() {
         std::process::exit(1);
     }
 
+    let chapter_path = env::args().nth(1).unwrap_or("chapter1.ron".to_string());
+    let loaded = dataparser::parse_chapter(&chapter_path).and_then(|chapter| {
+        let cost_table = dataparser::parse_cost_table("movement_costs.txt")?;
+        Ok((chapter, cost_table))
+    });
+    let (chapter, cost_table) = match loaded {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            println!("Couldn't load chapter: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    // Carry on with the chapter that was suspended last time, if there is one
+    let resumed = if save::exists(save::SAVE_DIR, Slot::Suspend) {
+        save::load(save::SAVE_DIR, Slot::Suspend, cost_table.clone())
+            .map_err(|err| println!("Couldn't resume the suspended chapter: {}", err))
+            .ok()
+    } else {
+        None
+    };
+    let battle = resumed.unwrap_or_else(|| {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        Battle::new(chapter, cost_table, seed)
+    });
+
     let ctx = &mut cb.build().unwrap();
-    let state = &mut MainState::new(ctx, window_width, window_height).unwrap();
-    state.add_unit(
-        1,
-        5,
-        (3, 3),
-        vec![
-            Image::new(ctx, "/konrad-commander.png"),
-            Image::new(ctx, "/konrad-commander-attack-1.png"),
-            Image::new(ctx, "/konrad-commander-attack-2.png"),
-            Image::new(ctx, "/konrad-commander-attack-3.png"),
-            Image::new(ctx, "/konrad-commander-attack-4.png"),
-        ].into_iter()
-            .map(|x| x.unwrap())
-            .collect(),
-    );
-
-    {
-        let unit = state.units[&(3, 3)].clone();
-        let unit = unit.borrow();
-        for y in 0..10 {
-            for x in 0..10 {
-                match unit.costs.get(&(x, y)) {
-                    Some(dist) => {
-                        if dist == &Ratio::zero() {
-                            print!("  S  ")
-                        } else {
-                            print!("{:.2} ", utils::rat_to_f32(dist))
-                        }
-                    }
-                    None => print!("---- "),
-                }
-            }
-            println!("");
-        }
-        println!("{:?}", unit.boundary);
-        println!(
-            "{:?}",
-            pathfinding::find_boundary_neighbor_directions(
-                &(unit.boundary),
-                &(unit.reachable_coords),
-                state.grid_n_cell_width,
-                state.grid_n_cell_height
-            )
-        );
-    }
+    let state = &mut MainState::new(ctx, window_width, window_height, battle).unwrap();
     event::run(ctx, state).unwrap();
 }
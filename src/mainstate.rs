@@ -1,21 +1,53 @@
-use ggez::{graphics, Context, GameResult};
-use ggez::graphics::{DrawParam, Drawable, Font, Image, Point2, Text};
+use ggez::{graphics, Context, GameError, GameResult};
+use ggez::graphics::{Color, DrawMode, DrawParam, Drawable, Font, Image, Point2, Text};
 use ggez::graphics::spritebatch::*;
+use std::cmp;
 use std::collections::HashMap;
-use dataparser;
-use pathfinding;
-use terrain::Terrain;
-use unit::Unit;
-use std::cell::RefCell;
-use std::rc::Rc;
+use feg::action::Action;
+use feg::ai::{self, AiAction};
+use feg::battle::Battle;
+use feg::combat::{self, CombatResult, Side};
+use feg::dataparser::DataParserErr;
+use feg::menu::Menu;
+use feg::pathfinding::Direction;
+use feg::movement::Movement;
+use feg::save::{self, Slot};
+use feg::session::{Event, Mode, Session};
+use feg::terrain::Terrain;
+use feg::turn::Phase;
+use feg::unit::Faction;
+use input::{self, Bindings, Command, KeyRepeat};
+
+// A fight being played back one strike at a time.
+#[derive(Debug)]
+pub struct CombatPlayback {
+    pub attacker: (u32, u32),
+    pub defender: (u32, u32),
+    pub result: CombatResult,
+    // Index of the strike being shown
+    pub strike: usize,
+    // Seconds the current strike has been shown for
+    pub timer: f32,
+}
+
+// How many numbered save slots the save menu offers.
+pub const SAVE_SLOTS: u32 = 5;
+
+// What choosing a slot in the slot menu does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlotAction {
+    Save,
+    Load,
+}
 
 #[derive(Debug)]
 pub struct MainState {
     pub mouse_coords: (u32, u32),
     pub font: Font,
-    pub terrain: Vec<Vec<Terrain>>,
-    pub wall_sb: SpriteBatch,
-    pub sand_sb: SpriteBatch,
+    // The player's cursor, selection and menus, and the battle they act on
+    pub session: Session,
+    // One sprite batch for each type of terrain on the map that has a sprite
+    pub terrain_sbs: HashMap<Terrain, SpriteBatch>,
     pub fps: u32,
     pub window_width: u32,
     pub window_height: u32,
@@ -25,115 +57,1066 @@ pub struct MainState {
     pub grid_n_cell_width: u32,
     pub grid_n_cell_height: u32,
     pub grid_cell_dim: u32,
+    // Grid coord of the top left visible cell.
+    pub camera: (u32, u32),
+    // Number of cells visible at once.
+    pub viewport_n_cell_width: u32,
+    pub viewport_n_cell_height: u32,
+    // Distance in pixels from the edge of the viewport at which the camera
+    // starts scrolling towards the mouse.
+    pub edge_scroll_margin: u32,
+    // Seconds between camera steps while edge scrolling.
+    pub edge_scroll_interval: f32,
+    pub edge_scroll_timer: f32,
     pub path_line_width: u32,
+    // Outline of the selected unit's movement range
+    pub border_color: Color,
+    pub border_width: u32,
     pub cursor_img: Image,
-    pub selection: Option<(u32, u32)>,
     pub grid_coord_to_unit_map: HashMap<(u32, u32), ()>,
     pub number_texts: Vec<Text>,
-    pub units: HashMap<(u32, u32), Rc<RefCell<Unit>>>,
-    pub selected_unit: Option<Rc<RefCell<Unit>>>,
+    pub show_danger_zone: bool,
+    pub show_threat_counts: bool,
+    pub banner_font: Font,
+    // Seconds the phase change banner is shown for, and how long the current
+    // one has left.
+    pub phase_banner_duration: f32,
+    pub phase_banner_timer: f32,
+    // Unit currently walking to a new cell, if any
+    pub movement: Option<Movement>,
+    // Cells per second
+    pub move_speed: f32,
+    // Fight being played back, if any
+    pub combat: Option<CombatPlayback>,
+    // Menu of save slots, which takes over the input while it's open
+    pub slot_menu: Option<(SlotAction, Menu<u32>)>,
+    // Result of the last save or load, shown at the bottom of the window
+    // for status_duration seconds
+    pub status: String,
+    pub status_duration: f32,
+    pub status_timer: f32,
+    // Set once quitting was cancelled because the chapter couldn't be
+    // suspended
+    pub suspend_failed: bool,
+    // Seconds each strike is shown for when playing back combat
+    pub strike_duration: f32,
+    pub menu_width: u32,
+    pub menu_entry_height: u32,
+    pub bindings: Bindings,
+    pub key_repeat: KeyRepeat,
+    // What the AI controlled unit that is walking will do when it arrives
+    pub ai_decision: Option<ai::Decision>,
+    // Every unit is drawn with these for now
+    pub unit_sprites: Vec<Image>,
+    // Index into unit_sprites. All units are animated in step.
+    pub animation_tick: f32,
+}
+
+// Data files are parsed by the library, which doesn't know about ggez.
+fn resource_error(err: DataParserErr) -> GameError {
+    GameError::ResourceLoadError(err.to_string())
 }
 
 impl MainState {
-    pub fn new(ctx: &mut Context, window_width: u32, window_height: u32) -> GameResult<Self> {
-        let wall_sb = SpriteBatch::new(Image::new(ctx, "/wall.png")?);
-        let sand_sb = SpriteBatch::new(Image::new(ctx, "/sand.png")?);
-        let terrain = dataparser::parse_walls("terrain.txt", 10, 10)?;
+    pub fn new(
+        ctx: &mut Context,
+        window_width: u32,
+        window_height: u32,
+        battle: Battle,
+    ) -> GameResult<Self> {
+        let mut terrain_sbs = HashMap::new();
+        for &terrain_type in battle.terrain.iter().flat_map(|col| col.iter()) {
+            if terrain_type == Terrain::None || terrain_sbs.contains_key(&terrain_type) {
+                continue;
+            }
+            let image = Image::new(ctx, format!("/{}.png", terrain_type.name()))?;
+            terrain_sbs.insert(terrain_type, SpriteBatch::new(image));
+        }
+        let bindings = input::parse_bindings("bindings.txt").map_err(resource_error)?;
 
-        let vertical_padding = 30;
-        let grid_n_cell_width = 10; // number of horizontal grid cells
-        let grid_n_cell_height = 10; // number of verical grid cells
+        // This includes the line width.
+        let grid_cell_dim = 74;
+        let min_padding = 30;
+        let grid_n_cell_width = battle.width; // number of horizontal grid cells
+        let grid_n_cell_height = battle.height; // number of verical grid cells
+        let viewport_n_cell_width = cmp::min(
+            grid_n_cell_width,
+            (window_width - 2 * min_padding) / grid_cell_dim,
+        );
+        let viewport_n_cell_height = cmp::min(
+            grid_n_cell_height,
+            (window_height - 2 * min_padding) / grid_cell_dim,
+        );
+        // Center the viewport in the window.
+        let horizontal_padding = (window_width - viewport_n_cell_width * grid_cell_dim) / 2;
+        let vertical_padding = (window_height - viewport_n_cell_height * grid_cell_dim) / 2;
 
         let font = Font::new(ctx, "/DejaVuSerif.ttf", 10)?;
+        let banner_font = Font::new(ctx, "/DejaVuSerif.ttf", 32)?;
         // Precompile Texts because Text::new() is expensive.
-        let number_texts = (0..10)
+        let number_texts = (0..cmp::max(grid_n_cell_width, grid_n_cell_height))
             .into_iter()
             .map(|i| Text::new(ctx, &i.to_string(), &font).unwrap())
             .collect();
 
+        let unit_sprites = vec![
+            Image::new(ctx, "/konrad-commander.png")?,
+            Image::new(ctx, "/konrad-commander-attack-1.png")?,
+            Image::new(ctx, "/konrad-commander-attack-2.png")?,
+            Image::new(ctx, "/konrad-commander-attack-3.png")?,
+            Image::new(ctx, "/konrad-commander-attack-4.png")?,
+        ];
+
         let mut main_state = MainState {
             mouse_coords: (0, 0),
             font,
-            terrain: terrain.clone(),
-            wall_sb,
-            sand_sb,
+            session: Session::new(battle),
+            terrain_sbs,
 
             fps: 60,
             window_width: window_width,
             window_height: window_height,
-            horizontal_padding: 270,
+            horizontal_padding,
             vertical_padding,
             grid_line_width: 2, // should be even
             grid_n_cell_width,
             grid_n_cell_height,
 
-            // 74. This includes the line width.
-            grid_cell_dim: (window_height - 2 * vertical_padding) / grid_n_cell_height,
+            grid_cell_dim,
+            camera: (0, 0),
+            viewport_n_cell_width,
+            viewport_n_cell_height,
+            edge_scroll_margin: 20,
+            edge_scroll_interval: 0.15,
+            edge_scroll_timer: 0.0,
 
             // Width of the line used to draw the path indicator.
             path_line_width: 10,
+            border_color: Color::from_rgb(255, 84, 163),
+            border_width: 3,
             cursor_img: Image::new(ctx, "/cursor.png")?,
-            selection: None,
             grid_coord_to_unit_map: HashMap::new(),
             number_texts,
 
-            units: HashMap::new(),
-            selected_unit: None,
+            show_danger_zone: false,
+            show_threat_counts: false,
+            banner_font,
+            phase_banner_duration: 1.5,
+            // Announce the first player phase
+            phase_banner_timer: 1.5,
+            movement: None,
+            move_speed: 6.0,
+            combat: None,
+            slot_menu: None,
+            status: String::new(),
+            status_duration: 3.0,
+            status_timer: 0.0,
+            suspend_failed: false,
+            strike_duration: 0.6,
+            menu_width: 110,
+            menu_entry_height: 22,
+            bindings,
+            key_repeat: KeyRepeat::new(0.35, 0.12, 0.04, 0.8),
+            ai_decision: None,
+            unit_sprites,
+            animation_tick: 0.0,
         };
 
-        for (x, col) in terrain.iter().enumerate() {
-            for (y, terrain_type) in col.iter().enumerate() {
-                let (rect_x, rect_y) = main_state.grid_to_screen_coord((x as u32, y as u32));
-                match terrain_type {
-                    &Terrain::Wall => {
-                        main_state.wall_sb.add(DrawParam {
-                            dest: Point2::new(rect_x as f32, rect_y as f32),
-                            ..DrawParam::default()
-                        });
-                    }
-                    &Terrain::Sand => {
-                        main_state.sand_sb.add(DrawParam {
-                            dest: Point2::new(rect_x as f32, rect_y as f32),
-                            ..DrawParam::default()
-                        });
+        main_state.rebuild_terrain_batches();
+        main_state.follow_cursor();
+
+        Ok(main_state)
+    }
+
+    // Sprite batches only hold the terrain inside the viewport, so they need
+    // to be rebuilt whenever the camera moves.
+    pub fn rebuild_terrain_batches(&mut self) {
+        for sb in self.terrain_sbs.values_mut() {
+            sb.clear();
+        }
+        for x in self.camera.0..self.camera.0 + self.viewport_n_cell_width {
+            for y in self.camera.1..self.camera.1 + self.viewport_n_cell_height {
+                let (rect_x, rect_y) = self.grid_to_screen_coord((x, y));
+                let param = DrawParam {
+                    dest: Point2::new(rect_x as f32, rect_y as f32),
+                    ..DrawParam::default()
+                };
+                let terrain_type = self.session.battle.terrain_at((x, y));
+                if let Some(sb) = self.terrain_sbs.get_mut(&terrain_type) {
+                    sb.add(param);
+                }
+            }
+        }
+    }
+
+    // Moves the camera by (dx, dy) cells, clamped so that the viewport stays
+    // inside the map. Returns true if the camera moved.
+    pub fn scroll_camera(&mut self, dx: i32, dy: i32) -> bool {
+        let max_x = (self.grid_n_cell_width - self.viewport_n_cell_width) as i32;
+        let max_y = (self.grid_n_cell_height - self.viewport_n_cell_height) as i32;
+        let new_camera = (
+            cmp::max(0, cmp::min(max_x, self.camera.0 as i32 + dx)) as u32,
+            cmp::max(0, cmp::min(max_y, self.camera.1 as i32 + dy)) as u32,
+        );
+        if new_camera == self.camera {
+            return false;
+        }
+        self.camera = new_camera;
+        self.rebuild_terrain_batches();
+        true
+    }
+
+    // Returns the direction to scroll in when the mouse is near the edge of
+    // the viewport.
+    pub fn edge_scroll_direction(&self) -> (i32, i32) {
+        let (mouse_x, mouse_y) = self.mouse_coords;
+        let left = self.horizontal_padding + self.edge_scroll_margin;
+        let right = self.window_width - self.horizontal_padding - self.edge_scroll_margin;
+        let top = self.vertical_padding + self.edge_scroll_margin;
+        let bottom = self.window_height - self.vertical_padding - self.edge_scroll_margin;
+        let dx = if mouse_x < left {
+            -1
+        } else if mouse_x > right {
+            1
+        } else {
+            0
+        };
+        let dy = if mouse_y < top {
+            -1
+        } else if mouse_y > bottom {
+            1
+        } else {
+            0
+        };
+        (dx, dy)
+    }
+
+    // Called once per update tick.
+    pub fn update_edge_scroll(&mut self) {
+        self.edge_scroll_timer += 1.0 / self.fps as f32;
+        if self.edge_scroll_timer < self.edge_scroll_interval {
+            return;
+        }
+        let (dx, dy) = self.edge_scroll_direction();
+        if (dx, dy) != (0, 0) && self.scroll_camera(dx, dy) {
+            self.edge_scroll_timer = 0.0;
+        }
+    }
+
+    pub fn is_grid_coord_visible(&self, (grid_x, grid_y): (u32, u32)) -> bool {
+        grid_x >= self.camera.0 && grid_x < self.camera.0 + self.viewport_n_cell_width
+            && grid_y >= self.camera.1
+            && grid_y < self.camera.1 + self.viewport_n_cell_height
+    }
+
+    // Whether a unit is walking or a fight is being played back. Nothing
+    // else happens until it's over.
+    pub fn is_animating(&self) -> bool {
+        self.movement.is_some() || self.combat.is_some()
+    }
+
+    // Handles a click on a grid cell. If the selected unit can reach the cell
+    // it starts walking there, otherwise the unit on the cell (if any)
+    // becomes selected.
+    pub fn select_grid_coord(&mut self, grid_coord: Option<(u32, u32)>) {
+        if self.is_animating() || self.is_phase_banner_visible() {
+            return;
+        }
+        let event = match grid_coord {
+            Some(coord) => self.session.select(coord),
+            None => {
+                self.session.cancel();
+                None
+            }
+        };
+        self.play(event);
+    }
+
+    // Starts animating what the session is waiting on, if anything.
+    fn play(&mut self, event: Option<Event>) {
+        match event {
+            Some(Event::Move(path)) => self.movement = Some(Movement::new(path)),
+            Some(Event::Attack(attacker, defender, result)) => {
+                self.start_combat(attacker, defender, result)
+            }
+            None => (),
+        }
+    }
+
+    // Called once per update tick.
+    pub fn update_movement(&mut self) {
+        let done = match self.movement {
+            Some(ref mut movement) => {
+                movement.advance(self.move_speed / self.fps as f32);
+                movement.is_done()
+            }
+            None => false,
+        };
+        if done {
+            self.finish_movement();
+        }
+    }
+
+    // Rekeys the unit that just finished walking by its new location.
+    pub fn finish_movement(&mut self) {
+        let movement = match self.movement.take() {
+            Some(movement) => movement,
+            None => return,
+        };
+        let (origin, destination) = (movement.origin(), movement.destination());
+        let faction = self.session.battle.units[&origin].borrow().faction;
+        // Only the player's moves can be undone
+        if faction == Faction::Player {
+            self.session.finish_move(destination);
+            return;
+        }
+        self.session.battle.move_unit(origin, destination);
+        self.session.selected = Some(destination);
+        if let Some(decision) = self.ai_decision.take() {
+            self.execute_ai_action(destination, decision.action);
+        }
+    }
+
+    // Decides what the unit on location does and starts walking it to its
+    // destination.
+    fn start_ai_turn(&mut self, location: (u32, u32)) {
+        let (decision, path) = self.session.battle.plan_ai_turn(location);
+        self.set_cursor(path[0]);
+        self.session.selected = Some(location);
+        self.ai_decision = Some(decision);
+        self.movement = Some(Movement::new(path));
+    }
+
+    fn execute_ai_action(&mut self, location: (u32, u32), ai_action: AiAction) {
+        let battle = &mut self.session.battle;
+        match ai_action {
+            AiAction::Attack(target) => match battle.resolve_ai_attack(location, target) {
+                Some(result) => self.start_combat(location, target, result),
+                None => self.session.finish_action(),
+            },
+            AiAction::Heal(target) => {
+                battle.use_staff(location, target);
+                self.session.finish_action();
+            }
+            AiAction::Wait => self.session.finish_action(),
+        }
+    }
+
+    // Labels and cursor of the open menu, if any. Targets are chosen on the
+    // map instead.
+    pub fn menu_labels(&self) -> Option<(Vec<&str>, usize)> {
+        if let Some((_, ref menu)) = self.slot_menu {
+            return Some((menu.labels(), menu.cursor()));
+        }
+        match self.session.mode {
+            Mode::SelectTarget(..) => None,
+            _ => self.session.menu_labels(),
+        }
+    }
+
+    // Backs out of the open menu. Cancelling the action menu undoes the move.
+    pub fn cancel(&mut self) {
+        if !self.is_animating() {
+            self.session.cancel();
+        }
+    }
+
+    // Undo button: takes back the selected unit's move from any of its menus.
+    pub fn undo(&mut self) {
+        if !self.is_animating() {
+            self.session.undo();
+        }
+    }
+
+    // Redo button: makes the move that was undone again, without walking.
+    pub fn redo(&mut self) {
+        if !self.is_animating() && self.session.redo() {
+            self.follow_cursor();
+        }
+    }
+
+    // Offers to take back any number of the actions made this phase, if
+    // there are rewinds left.
+    pub fn open_rewind_menu(&mut self) {
+        if !self.is_animating() {
+            self.session.open_rewind_menu();
+        }
+    }
+
+    // Plays back a fight that has already been decided.
+    fn start_combat(&mut self, attacker: (u32, u32), defender: (u32, u32), result: CombatResult) {
+        self.combat = Some(CombatPlayback {
+            attacker,
+            defender,
+            result,
+            strike: 0,
+            timer: 0.0,
+        });
+    }
+
+    // Called once per update tick.
+    pub fn update_combat(&mut self) {
+        let done = match self.combat {
+            Some(ref mut playback) => {
+                if playback.timer == 0.0 {
+                    // Show the damage as the strike lands
+                    if let Some(strike) = playback.result.strikes.get(playback.strike) {
+                        let target = match strike.striker {
+                            Side::Attacker => playback.defender,
+                            Side::Defender => playback.attacker,
+                        };
+                        self.session.battle.units[&target].borrow_mut().hp = strike.target_hp;
                     }
-                    _ => (),
                 }
+                playback.timer += 1.0 / self.fps as f32;
+                if playback.timer >= self.strike_duration {
+                    playback.strike += 1;
+                    playback.timer = 0.0;
+                }
+                playback.strike >= playback.result.strikes.len()
             }
+            None => false,
+        };
+        if done {
+            self.finish_combat();
         }
+    }
 
-        Ok(main_state)
+    fn finish_combat(&mut self) {
+        if let Some(playback) = self.combat.take() {
+            self.session
+                .finish_attack(playback.attacker, playback.defender, &playback.result);
+        }
     }
 
-    pub fn add_unit(
-        &mut self,
-        id: u32,
-        movement_range: u32,
-        location: (u32, u32),
-        animation_sprites: Vec<Image>,
-    ) {
-        let (paths, costs, boundary, reachable_coords) = pathfinding::compute_path_costs(
-            location,
-            &self.terrain,
-            self.grid_n_cell_width,
-            self.grid_n_cell_height,
-            movement_range,
-        );
-        let unit = Unit::new(
-            id,
-            movement_range,
-            location,
-            animation_sprites,
-            paths,
-            costs,
-            boundary,
-            reachable_coords,
-        );
-        self.units.insert(location, Rc::new(RefCell::new(unit)));
+    // Handles a left click.
+    pub fn click(&mut self, screen_coord: (u32, u32)) {
+        if self.slot_menu.is_some() {
+            if let Some(index) = self.menu_entry_at(screen_coord) {
+                self.set_slot_menu_cursor(index);
+                self.confirm_slot_menu();
+            }
+            return;
+        }
+        if self.is_animating() || !self.session.is_player_phase() {
+            return;
+        }
+        let grid_coord = self.screen_to_grid_coord(screen_coord);
+        match self.session.mode {
+            Mode::Normal => self.select_grid_coord(grid_coord),
+            Mode::SelectTarget(..) => {
+                if grid_coord.map_or(false, |coord| self.session.set_target_cursor(coord)) {
+                    let event = self.session.confirm_menu();
+                    self.play(event);
+                }
+            }
+            _ => {
+                if let Some(index) = self.menu_entry_at(screen_coord) {
+                    self.session.set_menu_cursor(index);
+                    let event = self.session.confirm_menu();
+                    self.play(event);
+                }
+            }
+        }
+    }
+
+    // Moves the grid cursor or menu cursors to whatever the mouse is over.
+    pub fn hover(&mut self, screen_coord: (u32, u32)) {
+        if self.slot_menu.is_some() {
+            if let Some(index) = self.menu_entry_at(screen_coord) {
+                self.set_slot_menu_cursor(index);
+            }
+            return;
+        }
+        match self.session.mode {
+            Mode::Normal => {
+                if let Some(coord) = self.screen_to_grid_coord(screen_coord) {
+                    self.session.cursor = coord;
+                }
+            }
+            Mode::SelectTarget(..) => {
+                if let Some(coord) = self.screen_to_grid_coord(screen_coord) {
+                    self.session.set_target_cursor(coord);
+                }
+            }
+            _ => {
+                if let Some(index) = self.menu_entry_at(screen_coord) {
+                    self.session.set_menu_cursor(index);
+                }
+            }
+        }
+    }
+
+    pub fn press_command(&mut self, command: Command) {
+        if let Some(direction) = command.direction() {
+            self.key_repeat.press(direction);
+            self.step_cursor(direction);
+            return;
+        }
+        if self.slot_menu.is_some() {
+            match command {
+                Command::Confirm => self.confirm_slot_menu(),
+                Command::Cancel => self.slot_menu = None,
+                _ => (),
+            }
+            return;
+        }
+        match command {
+            Command::Confirm => self.confirm(),
+            Command::Cancel => self.cancel(),
+            Command::NextUnit => self.select_next_unit(),
+            Command::EndPhase => {
+                if self.is_idle_player_phase() && !self.is_phase_banner_visible() {
+                    self.end_phase();
+                }
+            }
+            Command::ToggleDangerZone => self.show_danger_zone = !self.show_danger_zone,
+            // Debug view of how many enemies threaten each cell
+            Command::ToggleThreatCounts => self.show_threat_counts = !self.show_threat_counts,
+            Command::Save => self.open_slot_menu(SlotAction::Save),
+            Command::Load => self.open_slot_menu(SlotAction::Load),
+            Command::Undo => self.undo(),
+            Command::Redo => self.redo(),
+            Command::Rewind => self.open_rewind_menu(),
+            _ => (),
+        }
+    }
+
+    // Offers every slot to save to, or the slots that hold a save to load
+    // from. Only allowed while the player is choosing which unit to move
+    // next.
+    pub fn open_slot_menu(&mut self, action: SlotAction) {
+        if !self.is_idle_player_phase() {
+            return;
+        }
+        let used = save::numbered_slots(save::SAVE_DIR);
+        let entries: Vec<(u32, String)> = match action {
+            SlotAction::Save => (1..SAVE_SLOTS + 1)
+                .map(|slot| {
+                    let label = if used.contains(&slot) {
+                        format!("Slot {} (used)", slot)
+                    } else {
+                        format!("Slot {}", slot)
+                    };
+                    (slot, label)
+                })
+                .collect(),
+            SlotAction::Load => used
+                .into_iter()
+                .map(|slot| (slot, format!("Slot {}", slot)))
+                .collect(),
+        };
+        if entries.is_empty() {
+            self.show_status("No saves to load".to_string());
+            return;
+        }
+        self.slot_menu = Some((action, Menu::new(entries)));
+    }
+
+    fn set_slot_menu_cursor(&mut self, index: usize) {
+        if let Some((_, ref mut menu)) = self.slot_menu {
+            menu.set_cursor(index);
+        }
+    }
+
+    // Saves to or loads from the slot under the cursor.
+    fn confirm_slot_menu(&mut self) {
+        let (action, slot) = match self.slot_menu.take() {
+            Some((action, menu)) => match menu.selected() {
+                Some(&slot) => (action, slot),
+                None => return,
+            },
+            None => return,
+        };
+        match action {
+            SlotAction::Save => self.save(slot),
+            SlotAction::Load => self.load(slot),
+        }
+    }
+
+    fn save(&mut self, slot: u32) {
+        let status = match save::save(&self.session.battle, save::SAVE_DIR, Slot::Numbered(slot)) {
+            Ok(()) => format!("Saved to slot {}", slot),
+            Err(err) => format!("Couldn't save to slot {}: {}", slot, err),
+        };
+        self.show_status(status);
+    }
+
+    // Loads the slot if it's a save of the chapter being played.
+    fn load(&mut self, slot: u32) {
+        let cost_table = self.session.battle.cost_table.clone();
+        let battle = match save::load(save::SAVE_DIR, Slot::Numbered(slot), cost_table) {
+            Ok(battle) => battle,
+            Err(err) => {
+                self.show_status(format!("Couldn't load slot {}: {}", slot, err));
+                return;
+            }
+        };
+        // Terrain sprites are only loaded for the chapter being played
+        if battle.chapter != self.session.battle.chapter {
+            self.show_status(format!(
+                "Couldn't load slot {}: the save is of another chapter",
+                slot
+            ));
+            return;
+        }
+        self.session = Session::new(battle);
+        self.follow_cursor();
+        self.phase_banner_timer = self.phase_banner_duration;
+        self.show_status(format!("Loaded slot {}", slot));
+    }
+
+    pub fn show_status(&mut self, status: String) {
+        self.status = status;
+        self.status_timer = self.status_duration;
+    }
+
+    // Called once per update tick.
+    pub fn update_status(&mut self) {
+        if self.status_timer > 0.0 {
+            self.status_timer -= 1.0 / self.fps as f32;
+        }
+    }
+
+    pub fn draw_status(&self, ctx: &mut Context) -> GameResult<()> {
+        if self.status_timer <= 0.0 {
+            return Ok(());
+        }
+        let text = Text::new(ctx, &self.status, &self.font)?;
+        let y = self.window_height - self.vertical_padding / 2 - text.height() / 2;
+        text.draw(ctx, Point2::new(self.horizontal_padding as f32, y as f32), 0.0)
+    }
+
+    fn is_idle_player_phase(&self) -> bool {
+        !self.is_animating() && self.session.is_idle()
+    }
+
+    // Saves the chapter in progress to the suspend slot, to be resumed on the
+    // next launch. Anything being animated is finished first and a move that
+    // hasn't been acted on is undone. Returns false if the chapter couldn't
+    // be saved.
+    pub fn suspend(&mut self) -> bool {
+        while self.is_animating() {
+            if self.movement.is_some() {
+                self.finish_movement();
+            } else {
+                self.finish_combat();
+            }
+        }
+        self.session.abandon_move();
+        if self.session.battle.outcome.is_some() {
+            return true;
+        }
+        match save::save(&self.session.battle, save::SAVE_DIR, Slot::Suspend) {
+            Ok(()) => true,
+            Err(err) => {
+                self.show_status(format!(
+                    "Couldn't suspend: {}. Quitting again loses the chapter.",
+                    err
+                ));
+                false
+            }
+        }
+    }
+
+    pub fn release_command(&mut self, command: Command) {
+        if let Some(direction) = command.direction() {
+            self.key_repeat.release(direction);
+        }
+    }
+
+    // Called once per update tick.
+    pub fn update_key_repeat(&mut self) {
+        if let Some(direction) = self.key_repeat.update(1.0 / self.fps as f32) {
+            self.step_cursor(direction);
+        }
+    }
+
+    // Moves the grid cursor, or the cursor of the open menu, keeping the grid
+    // cursor in view.
+    pub fn step_cursor(&mut self, direction: (i32, i32)) {
+        if let Some((_, ref mut menu)) = self.slot_menu {
+            menu.move_cursor(direction.1);
+            return;
+        }
+        self.session.step_cursor(direction);
+        self.follow_cursor();
+    }
+
+    // Moves the grid cursor to coord and scrolls the camera to keep it in
+    // view.
+    pub fn set_cursor(&mut self, coord: (u32, u32)) {
+        self.session.cursor = coord;
+        self.follow_cursor();
+    }
+
+    // Scrolls the camera so that the grid cursor is in view.
+    pub fn follow_cursor(&mut self) {
+        let (cursor_x, cursor_y) = self.session.cursor;
+        let (camera_x, camera_y) = self.camera;
+        let scroll = |cursor: u32, camera: u32, viewport: u32| {
+            if cursor < camera {
+                cursor as i32 - camera as i32
+            } else if cursor >= camera + viewport {
+                (cursor - (camera + viewport) + 1) as i32
+            } else {
+                0
+            }
+        };
+        let dx = scroll(cursor_x, camera_x, self.viewport_n_cell_width);
+        let dy = scroll(cursor_y, camera_y, self.viewport_n_cell_height);
+        if (dx, dy) != (0, 0) {
+            self.scroll_camera(dx, dy);
+        }
+    }
+
+    // Confirm button: acts on the cell under the grid cursor, or on the open
+    // menu.
+    pub fn confirm(&mut self) {
+        if self.is_animating() || self.is_phase_banner_visible() {
+            return;
+        }
+        let event = self.session.confirm();
+        self.follow_cursor();
+        self.play(event);
+    }
+
+    // Jumps to and selects the next player unit (by id) that can still move.
+    pub fn select_next_unit(&mut self) {
+        if !self.is_animating() {
+            self.session.select_next_unit();
+            self.follow_cursor();
+        }
+    }
+
+    // Top left corner of the open menu. Menus open to the right of the
+    // selected unit, or to its left if there is no room.
+    pub fn menu_screen_coord(&self) -> (u32, u32) {
+        let location = self.session.selected.unwrap_or(self.camera);
+        if !self.is_grid_coord_visible(location) {
+            return (self.horizontal_padding, self.vertical_padding);
+        }
+        let (x, y) = self.grid_to_screen_coord(location);
+        if x + self.grid_cell_dim + self.menu_width < self.window_width {
+            (x + self.grid_cell_dim, y)
+        } else {
+            (x.saturating_sub(self.menu_width + self.grid_line_width), y)
+        }
+    }
+
+    pub fn menu_entry_at(&self, (screen_x, screen_y): (u32, u32)) -> Option<usize> {
+        let n_entries = match self.menu_labels() {
+            Some((labels, _)) => labels.len(),
+            None => return None,
+        };
+        let (x, y) = self.menu_screen_coord();
+        if screen_x < x || screen_x >= x + self.menu_width || screen_y < y {
+            return None;
+        }
+        let index = ((screen_y - y) / self.menu_entry_height) as usize;
+        if index < n_entries {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    pub fn draw_menu(&self, ctx: &mut Context) -> GameResult<()> {
+        let (labels, cursor) = match self.menu_labels() {
+            Some(menu) => menu,
+            None => return Ok(()),
+        };
+        let (x, y) = self.menu_screen_coord();
+        let old_color = graphics::get_color(ctx);
+        graphics::set_color(ctx, Color::from_rgba(20, 20, 40, 220))?;
+        graphics::rectangle(
+            ctx,
+            DrawMode::Fill,
+            graphics::Rect {
+                x: x as f32,
+                y: y as f32,
+                w: self.menu_width as f32,
+                h: (labels.len() as u32 * self.menu_entry_height) as f32,
+            },
+        )?;
+        graphics::set_color(ctx, Color::from_rgba(80, 80, 160, 255))?;
+        graphics::rectangle(
+            ctx,
+            DrawMode::Fill,
+            graphics::Rect {
+                x: x as f32,
+                y: (y + cursor as u32 * self.menu_entry_height) as f32,
+                w: self.menu_width as f32,
+                h: self.menu_entry_height as f32,
+            },
+        )?;
+        graphics::set_color(ctx, graphics::WHITE)?;
+        for (i, label) in labels.iter().enumerate() {
+            let text = Text::new(ctx, label, &self.font)?;
+            let entry_y = y + i as u32 * self.menu_entry_height;
+            text.draw(
+                ctx,
+                Point2::new(
+                    (x + 8) as f32,
+                    (entry_y + (self.menu_entry_height - text.height()) / 2) as f32,
+                ),
+                0.0,
+            )?;
+        }
+        graphics::set_color(ctx, old_color)
+    }
+
+    // Highlights the targets while choosing one and shows the combat
+    // forecast for the one under the cursor.
+    pub fn draw_targets(&self, ctx: &mut Context) -> GameResult<()> {
+        let (chosen, menu) = match self.session.mode {
+            Mode::SelectTarget(chosen, ref menu) => (chosen, menu),
+            _ => return Ok(()),
+        };
+        let color = match chosen {
+            Action::Attack => Color::from_rgba(230, 30, 30, 70),
+            _ => Color::from_rgba(30, 200, 60, 70),
+        };
+        self.draw_cell_overlay(ctx, menu.values(), color)?;
+
+        let target = match menu.selected() {
+            Some(&target) => target,
+            None => return Ok(()),
+        };
+        if self.is_grid_coord_visible(target) {
+            let (x, y) = self.grid_to_screen_coord(target);
+            let old_color = graphics::get_color(ctx);
+            graphics::set_color(ctx, Color::from_rgb(255, 255, 0))?;
+            self.cursor_img
+                .draw(ctx, Point2::new(x as f32, y as f32), 0.0)?;
+            graphics::set_color(ctx, old_color)?;
+        }
+        if chosen != Action::Attack {
+            return Ok(());
+        }
+        let forecast = match self.session.forecast() {
+            Some(forecast) => forecast,
+            None => return Ok(()),
+        };
+        let describe = |side: &combat::SideForecast| {
+            let attacks = if side.attacks > 1 { " x2" } else { "" };
+            format!(
+                "HP {}  Dmg {}{}  Hit {}  Crit {}",
+                side.hp, side.damage, attacks, side.hit, side.crit
+            )
+        };
+        let (x, y) = self.menu_screen_coord();
+        let lines = [describe(&forecast.attacker), describe(&forecast.defender)];
+        for (i, line) in lines.iter().enumerate() {
+            let text = Text::new(ctx, line, &self.font)?;
+            text.draw(
+                ctx,
+                Point2::new(x as f32, (y + i as u32 * self.menu_entry_height) as f32),
+                0.0,
+            )?;
+        }
+        Ok(())
+    }
+
+    // Shows the outcome of the strike being played back above its target.
+    pub fn draw_combat(&self, ctx: &mut Context) -> GameResult<()> {
+        let playback = match self.combat {
+            Some(ref playback) => playback,
+            None => return Ok(()),
+        };
+        let strike = match playback.result.strikes.get(playback.strike) {
+            Some(strike) => strike,
+            None => return Ok(()),
+        };
+        let target = match strike.striker {
+            Side::Attacker => playback.defender,
+            Side::Defender => playback.attacker,
+        };
+        if !self.is_grid_coord_visible(target) {
+            return Ok(());
+        }
+        let label = if !strike.hit {
+            "Miss".to_string()
+        } else if strike.crit {
+            format!("Crit! {}", strike.damage)
+        } else {
+            strike.damage.to_string()
+        };
+        let (x, y) = self.grid_to_screen_coord(target);
+        let text = Text::new(ctx, &label, &self.banner_font)?;
+        text.draw(
+            ctx,
+            Point2::new(x as f32, y.saturating_sub(text.height()) as f32),
+            0.0,
+        )
+    }
+
+    // Where to draw the unit keyed by location, in grid coords. Units that
+    // are walking are between cells.
+    pub fn unit_grid_position(&self, location: (u32, u32)) -> (f32, f32) {
+        match self.movement {
+            Some(ref movement) if movement.origin() == location => movement.position(),
+            _ => tuple_as!(location, (x, f32), (y, f32)),
+        }
+    }
+
+    // Ends the current phase and announces the next one.
+    pub fn end_phase(&mut self) {
+        self.session.end_phase();
+        self.phase_banner_timer = self.phase_banner_duration;
+    }
+
+    pub fn is_phase_banner_visible(&self) -> bool {
+        self.phase_banner_timer > 0.0
+    }
+
+    // Called once per update tick.
+    pub fn update_phase(&mut self) {
+        // Nothing happens once the chapter is over
+        if self.session.battle.outcome.is_some() {
+            return;
+        }
+        if self.is_phase_banner_visible() {
+            self.phase_banner_timer -= 1.0 / self.fps as f32;
+            return;
+        }
+        if self.is_animating() {
+            return;
+        }
+        if self.session.battle.turn_manager.phase == Phase::Player {
+            if self.session.battle.is_phase_over() {
+                self.end_phase();
+            }
+            return;
+        }
+        // The AI moves the other factions' units one at a time
+        match self.session.battle.next_ai_unit() {
+            Some(location) => self.start_ai_turn(location),
+            None => self.end_phase(),
+        }
+    }
+
+    // Draws the current phase's name in a band across the middle of the
+    // viewport.
+    pub fn draw_phase_banner(&self, ctx: &mut Context) -> GameResult<()> {
+        if !self.is_phase_banner_visible() {
+            return Ok(());
+        }
+        let phase = self.session.battle.turn_manager.phase;
+        let band_color = match phase {
+            Phase::Player => Color::from_rgba(30, 60, 200, 180),
+            Phase::Enemy => Color::from_rgba(200, 30, 30, 180),
+            Phase::Other => Color::from_rgba(30, 160, 60, 180),
+        };
+        self.draw_banner(ctx, phase.name(), band_color)
+    }
+
+    pub fn draw_banner(&self, ctx: &mut Context, label: &str, band_color: Color) -> GameResult<()> {
+        let band_height = 80.0;
+        let center_y = self.window_height as f32 / 2.0;
+        let old_color = graphics::get_color(ctx);
+        graphics::set_color(ctx, band_color)?;
+        graphics::rectangle(
+            ctx,
+            DrawMode::Fill,
+            graphics::Rect {
+                x: self.horizontal_padding as f32,
+                y: center_y - band_height / 2.0,
+                w: (self.viewport_n_cell_width * self.grid_cell_dim) as f32,
+                h: band_height,
+            },
+        )?;
+        graphics::set_color(ctx, graphics::WHITE)?;
+        let text = Text::new(ctx, label, &self.banner_font)?;
+        text.draw(
+            ctx,
+            Point2::new(
+                (self.window_width - text.width()) as f32 / 2.0,
+                center_y - text.height() as f32 / 2.0,
+            ),
+            0.0,
+        )?;
+        graphics::set_color(ctx, old_color)
+    }
+
+    // Debug view: draws the number of enemies threatening each cell in the
+    // danger zone.
+    pub fn draw_threat_counts(&self, ctx: &mut Context) -> GameResult<()> {
+        for (&coord, &count) in self.session.battle.danger_zone.iter() {
+            if !self.is_grid_coord_visible(coord) {
+                continue;
+            }
+            if let Some(text) = self.number_texts.get(count as usize) {
+                let (x, y) = self.grid_to_screen_coord(coord);
+                text.draw(ctx, Point2::new(x as f32 + 4.0, y as f32 + 2.0), 0.0)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Tints every visible cell in coords with color.
+    pub fn draw_cell_overlay<'a, I>(
+        &self,
+        ctx: &mut Context,
+        coords: I,
+        color: Color,
+    ) -> GameResult<()>
+    where
+        I: IntoIterator<Item = &'a (u32, u32)>,
+    {
+        let old_color = graphics::get_color(ctx);
+        graphics::set_color(ctx, color)?;
+        for &coord in coords {
+            if !self.is_grid_coord_visible(coord) {
+                continue;
+            }
+            let (x, y) = self.grid_to_screen_coord(coord);
+            graphics::rectangle(
+                ctx,
+                DrawMode::Fill,
+                graphics::Rect {
+                    x: x as f32,
+                    y: y as f32,
+                    w: (self.grid_cell_dim - self.grid_line_width) as f32,
+                    h: (self.grid_cell_dim - self.grid_line_width) as f32,
+                },
+            )?;
+        }
+        graphics::set_color(ctx, old_color)
+    }
+
+    // Draws the given sides of each cell as lines just inside the cell.
+    pub fn draw_border(
+        &self,
+        ctx: &mut Context,
+        border: &[((u32, u32), Vec<Direction>)],
+    ) -> GameResult<()> {
+        let old_color = graphics::get_color(ctx);
+        graphics::set_color(ctx, self.border_color)?;
+        let cell_dim = (self.grid_cell_dim - self.grid_line_width) as f32;
+        let width = self.border_width as f32;
+        for &(coord, ref directions) in border {
+            if !self.is_grid_coord_visible(coord) {
+                continue;
+            }
+            let (x, y) = tuple_as!(self.grid_to_screen_coord(coord), (x, f32), (y, f32));
+            for direction in directions {
+                let rect = match direction {
+                    &Direction::N => graphics::Rect::new(x, y, cell_dim, width),
+                    &Direction::S => graphics::Rect::new(x, y + cell_dim - width, cell_dim, width),
+                    &Direction::E => graphics::Rect::new(x + cell_dim - width, y, width, cell_dim),
+                    &Direction::W => graphics::Rect::new(x, y, width, cell_dim),
+                };
+                graphics::rectangle(ctx, DrawMode::Fill, rect)?;
+            }
+        }
+        graphics::set_color(ctx, old_color)
     }
 
     pub fn draw_grid(&self, ctx: &mut Context) -> GameResult<()> {
-        for i in 0..self.grid_n_cell_height + 1 {
+        let grid_pixel_width = self.viewport_n_cell_width * self.grid_cell_dim;
+        let grid_pixel_height = self.viewport_n_cell_height * self.grid_cell_dim;
+        for i in 0..self.viewport_n_cell_width + 1 {
             graphics::line(
                 ctx,
                 &[
@@ -143,13 +1126,13 @@ impl MainState {
                     ),
                     Point2::new(
                         (self.horizontal_padding + i * self.grid_cell_dim) as f32,
-                        (self.window_height - self.vertical_padding) as f32,
+                        (self.vertical_padding + grid_pixel_height) as f32,
                     ),
                 ],
                 self.grid_line_width as f32,
             )?;
         }
-        for i in 0..self.grid_n_cell_width + 1 {
+        for i in 0..self.viewport_n_cell_height + 1 {
             graphics::line(
                 ctx,
                 &[
@@ -158,7 +1141,7 @@ impl MainState {
                         (self.vertical_padding + i * self.grid_cell_dim) as f32,
                     ),
                     Point2::new(
-                        (self.window_width - self.horizontal_padding) as f32,
+                        (self.horizontal_padding + grid_pixel_width) as f32,
                         (self.vertical_padding + i * self.grid_cell_dim) as f32,
                     ),
                 ],
@@ -167,24 +1150,24 @@ impl MainState {
         }
 
         // Draw x and y labels
-        for x in 0..10 {
-            self.number_texts[x].draw(
+        for x in 0..self.viewport_n_cell_width {
+            self.number_texts[(self.camera.0 + x) as usize].draw(
                 ctx,
                 Point2::new(
-                    (self.horizontal_padding + self.grid_cell_dim / 2 - 3
-                        + x as u32 * self.grid_cell_dim) as f32,
+                    (self.horizontal_padding + self.grid_cell_dim / 2 - 3 + x * self.grid_cell_dim)
+                        as f32,
                     (self.vertical_padding - 20) as f32,
                 ),
                 0.0,
             )?;
         }
-        for y in 0..10 {
-            self.number_texts[y].draw(
+        for y in 0..self.viewport_n_cell_height {
+            self.number_texts[(self.camera.1 + y) as usize].draw(
                 ctx,
                 Point2::new(
                     (self.horizontal_padding - 20) as f32,
-                    (self.vertical_padding + self.grid_cell_dim / 2 - 2
-                        + y as u32 * self.grid_cell_dim) as f32,
+                    (self.vertical_padding + self.grid_cell_dim / 2 - 2 + y * self.grid_cell_dim)
+                        as f32,
                 ),
                 0.0,
             )?;
@@ -195,82 +1178,109 @@ impl MainState {
 
     // Includes pixels in the line of the grid
     pub fn screen_to_grid_coord(&self, (screen_x, screen_y): (u32, u32)) -> Option<(u32, u32)> {
-        if screen_x < self.horizontal_padding
-            || screen_x > self.window_width - self.horizontal_padding
-            || screen_y < self.vertical_padding
-            || screen_y > self.window_height - self.vertical_padding
-        {
+        if screen_x < self.horizontal_padding || screen_y < self.vertical_padding {
             return None;
         }
-        Some((
-            (screen_x - self.horizontal_padding) / self.grid_cell_dim,
-            (screen_y - self.vertical_padding) / self.grid_cell_dim,
-        ))
+        let viewport_x = (screen_x - self.horizontal_padding) / self.grid_cell_dim;
+        let viewport_y = (screen_y - self.vertical_padding) / self.grid_cell_dim;
+        if viewport_x >= self.viewport_n_cell_width || viewport_y >= self.viewport_n_cell_height {
+            return None;
+        }
+        Some((self.camera.0 + viewport_x, self.camera.1 + viewport_y))
     }
 
     // Screen coord is the top left hand corner of the cell, not including line
     // width (i.e. if we start drawing at the coord returned by this function,
-    // we will not overlap with the grid line)
+    // we will not overlap with the grid line). The grid coord must be visible
+    // (see is_grid_coord_visible()).
     pub fn grid_to_screen_coord(&self, (grid_x, grid_y): (u32, u32)) -> (u32, u32) {
         (
-            self.horizontal_padding + grid_x * self.grid_cell_dim + self.grid_line_width / 2,
-            self.vertical_padding + grid_y * self.grid_cell_dim + self.grid_line_width / 2,
+            self.horizontal_padding + (grid_x - self.camera.0) * self.grid_cell_dim
+                + self.grid_line_width / 2,
+            self.vertical_padding + (grid_y - self.camera.1) * self.grid_cell_dim
+                + self.grid_line_width / 2,
         )
     }
 
-    // Screen coord is the center of the cell
-    pub fn grid_to_screen_coord_center(&self, (grid_x, grid_y): (u32, u32)) -> (u32, u32) {
-        let (x, y) = self.grid_to_screen_coord((grid_x, grid_y));
+    // Same as grid_to_screen_coord() but for positions between cells.
+    pub fn grid_position_to_screen_coord(&self, (grid_x, grid_y): (f32, f32)) -> (f32, f32) {
+        let cell_dim = self.grid_cell_dim as f32;
+        let half_line_width = (self.grid_line_width / 2) as f32;
         (
-            x + (self.grid_cell_dim - self.grid_line_width) / 2,
-            y + (self.grid_cell_dim - self.grid_line_width) / 2,
+            self.horizontal_padding as f32 + (grid_x - self.camera.0 as f32) * cell_dim
+                + half_line_width,
+            self.vertical_padding as f32 + (grid_y - self.camera.1 as f32) * cell_dim
+                + half_line_width,
         )
     }
 
+    // Screen coord is the center of the cell. Works for cells outside the
+    // viewport too, whose screen coords can be negative.
+    pub fn grid_to_screen_position_center(&self, (grid_x, grid_y): (u32, u32)) -> (f32, f32) {
+        let (x, y) = self.grid_position_to_screen_coord((grid_x as f32, grid_y as f32));
+        let offset = ((self.grid_cell_dim - self.grid_line_width) / 2) as f32;
+        (x + offset, y + offset)
+    }
+
     // Converts a consolidated path returned by pathfinding::consolidate_path()
     // into segments that can be drawn by iterating over pairs of elements in
     // the returned vec. We need to account for the width of the line used to
-    // draw the path indicator.
+    // draw the path indicator. Parts of the path can be outside the viewport
+    // (see clip_segment()).
     pub fn cpath_to_segments(&self, cpath: Vec<(u32, u32)>) -> Vec<(f32, f32)> {
         if cpath.len() < 2 {
             return cpath
                 .into_iter()
-                .map(|p| tuple_as!(self.grid_to_screen_coord_center(p), (x, f32), (y, f32)))
+                .map(|p| self.grid_to_screen_position_center(p))
                 .collect();
         }
-        let mut segments = vec![
-            tuple_as!(
-                self.grid_to_screen_coord_center(cpath[0]),
-                (x, f32),
-                (y, f32)
-            ),
-        ];
+        let half_line_width = (self.path_line_width / 2) as f32;
+        let mut segments = vec![self.grid_to_screen_position_center(cpath[0])];
         for window in cpath.windows(2).take(cpath.windows(2).len() - 1) {
             let prev = (*window)[0];
             let cur = (*window)[1];
-            let (x, y) = self.grid_to_screen_coord_center(cur);
+            let (x, y) = self.grid_to_screen_position_center(cur);
             // If y coords are equal, then the line is horizontal
             if cur.1 == prev.1 {
                 if cur.0 < prev.0 {
-                    segments.push(((x - self.path_line_width / 2) as f32, y as f32));
+                    segments.push((x - half_line_width, y));
                 } else {
-                    segments.push(((x + self.path_line_width / 2) as f32, y as f32));
+                    segments.push((x + half_line_width, y));
                 }
-                segments.push((x as f32, y as f32));
+                segments.push((x, y));
             } else {
                 if cur.1 < prev.1 {
-                    segments.push((x as f32, (y - self.path_line_width / 2) as f32));
+                    segments.push((x, y - half_line_width));
                 } else {
-                    segments.push((x as f32, (y + self.path_line_width / 2) as f32));
+                    segments.push((x, y + half_line_width));
                 }
-                segments.push((x as f32, y as f32));
+                segments.push((x, y));
             }
         }
-        segments.push(tuple_as!(
-            self.grid_to_screen_coord_center(cpath[cpath.len() - 1]),
-            (x, f32),
-            (y, f32)
-        ));
+        segments.push(self.grid_to_screen_position_center(cpath[cpath.len() - 1]));
         segments
     }
+
+    // Clips a horizontal or vertical segment from cpath_to_segments() to the
+    // viewport. Returns None if none of it is inside.
+    pub fn clip_segment(
+        &self,
+        start: (f32, f32),
+        end: (f32, f32),
+    ) -> Option<((f32, f32), (f32, f32))> {
+        let left = self.horizontal_padding as f32;
+        let top = self.vertical_padding as f32;
+        let right = left + (self.viewport_n_cell_width * self.grid_cell_dim) as f32;
+        let bottom = top + (self.viewport_n_cell_height * self.grid_cell_dim) as f32;
+        let clamp = |value: f32, min: f32, max: f32| value.max(min).min(max);
+        let (min_x, max_x) = (start.0.min(end.0), start.0.max(end.0));
+        let (min_y, max_y) = (start.1.min(end.1), start.1.max(end.1));
+        if max_x < left || min_x > right || max_y < top || min_y > bottom {
+            return None;
+        }
+        Some((
+            (clamp(start.0, left, right), clamp(start.1, top, bottom)),
+            (clamp(end.0, left, right), clamp(end.1, top, bottom)),
+        ))
+    }
 }
@@ -0,0 +1,113 @@
+// A vertical list of labelled entries with a cursor, used for the in-game
+// command menus.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Menu<T> {
+    entries: Vec<(T, String)>,
+    cursor: usize,
+}
+
+impl<T> Menu<T> {
+    pub fn new(entries: Vec<(T, String)>) -> Self {
+        Menu { entries, cursor: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn labels(&self) -> Vec<&str> {
+        self.entries
+            .iter()
+            .map(|&(_, ref label)| label.as_str())
+            .collect()
+    }
+
+    pub fn values(&self) -> Vec<&T> {
+        self.entries.iter().map(|&(ref value, _)| value).collect()
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    // Ignores indices past the end of the menu.
+    pub fn set_cursor(&mut self, index: usize) {
+        if index < self.entries.len() {
+            self.cursor = index;
+        }
+    }
+
+    // Moves the cursor by delta entries, wrapping around at either end.
+    pub fn move_cursor(&mut self, delta: i32) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let len = self.entries.len() as i32;
+        self.cursor = (((self.cursor as i32 + delta) % len + len) % len) as usize;
+    }
+
+    pub fn selected(&self) -> Option<&T> {
+        self.entries.get(self.cursor).map(|&(ref entry, _)| entry)
+    }
+
+    // Moves the cursor to the entry with the given value. Returns false if
+    // there is none.
+    pub fn select(&mut self, value: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        match self
+            .entries
+            .iter()
+            .position(|&(ref entry, _)| entry == value)
+        {
+            Some(index) => {
+                self.cursor = index;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn menu() -> Menu<u32> {
+        Menu::new(vec![
+            (10, "Ten".to_string()),
+            (20, "Twenty".to_string()),
+            (30, "Thirty".to_string()),
+        ])
+    }
+
+    #[test]
+    fn test_move_cursor_wraps() {
+        let mut menu = menu();
+        assert_eq!(menu.selected(), Some(&10));
+        menu.move_cursor(-1);
+        assert_eq!(menu.selected(), Some(&30));
+        menu.move_cursor(2);
+        assert_eq!(menu.selected(), Some(&20));
+        menu.set_cursor(5);
+        assert_eq!(menu.cursor(), 1);
+    }
+
+    #[test]
+    fn test_select() {
+        let mut menu = menu();
+        assert!(menu.select(&30));
+        assert_eq!(menu.cursor(), 2);
+        assert!(!menu.select(&40));
+        assert_eq!(menu.cursor(), 2);
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut menu: Menu<u32> = Menu::new(vec![]);
+        menu.move_cursor(1);
+        assert_eq!(menu.selected(), None);
+        assert_eq!(menu.labels().len(), 0);
+    }
+}
@@ -0,0 +1,92 @@
+use std::cmp;
+
+// A unit walking along a path, one cell at a time. The unit stays keyed by
+// path[0] in Battle::units until it arrives.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movement {
+    pub path: Vec<(u32, u32)>,
+    // Number of cells walked so far, including partially walked cells
+    pub progress: f32,
+}
+
+impl Movement {
+    // path must not be empty
+    pub fn new(path: Vec<(u32, u32)>) -> Self {
+        assert!(!path.is_empty());
+        Movement {
+            path,
+            progress: 0.0,
+        }
+    }
+
+    pub fn origin(&self) -> (u32, u32) {
+        self.path[0]
+    }
+
+    pub fn destination(&self) -> (u32, u32) {
+        self.path[self.path.len() - 1]
+    }
+
+    pub fn advance(&mut self, cells: f32) {
+        self.progress += cells;
+        if self.progress > self.last_index() as f32 {
+            self.progress = self.last_index() as f32;
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.progress >= self.last_index() as f32
+    }
+
+    // Grid position of the unit, interpolated between the two cells it's
+    // walking between.
+    pub fn position(&self) -> (f32, f32) {
+        let index = self.progress as usize;
+        if index >= self.last_index() {
+            return tuple_as!(self.destination(), (x, f32), (y, f32));
+        }
+        let t = self.progress - index as f32;
+        let (x0, y0) = tuple_as!(self.path[index], (x, f32), (y, f32));
+        let (x1, y1) = tuple_as!(self.path[index + 1], (x, f32), (y, f32));
+        (x0 + (x1 - x0) * t, y0 + (y1 - y0) * t)
+    }
+
+    // The cell the unit is mostly in.
+    pub fn current_cell(&self) -> (u32, u32) {
+        let index = (self.progress + 0.5) as usize;
+        self.path[cmp::min(index, self.last_index())]
+    }
+
+    fn last_index(&self) -> usize {
+        self.path.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_position() {
+        let mut movement = Movement::new(vec![(0, 0), (1, 0), (1, 1)]);
+        assert_eq!(movement.position(), (0.0, 0.0));
+        movement.advance(0.25);
+        assert_eq!(movement.position(), (0.25, 0.0));
+        assert_eq!(movement.current_cell(), (0, 0));
+        movement.advance(1.25);
+        assert_eq!(movement.position(), (1.0, 0.5));
+        assert_eq!(movement.current_cell(), (1, 1));
+        assert!(!movement.is_done());
+        movement.advance(10.0);
+        assert!(movement.is_done());
+        assert_eq!(movement.position(), (1.0, 1.0));
+    }
+
+    #[test]
+    fn test_single_cell_path() {
+        let movement = Movement::new(vec![(2, 3)]);
+        assert!(movement.is_done());
+        assert_eq!(movement.origin(), movement.destination());
+        assert_eq!(movement.position(), (2.0, 3.0));
+    }
+}
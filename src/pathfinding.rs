@@ -1,7 +1,8 @@
 use std::cmp::Ordering;
 use std::u32;
-use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet};
 use terrain::Terrain;
+use unit::Faction;
 use num::Rational;
 use num::rational::Ratio;
 
@@ -44,39 +45,23 @@ fn is_point_valid((point_x, point_y): (u32, u32), max_w: u32, max_h: u32) -> boo
     point_x < max_w && point_y < max_h
 }
 
-// Returns vec of ((coord_x, coord_y), terrain movement cost).
-fn neighbor_costs(
-    (point_x, point_y): (u32, u32),
+// Returns vec of ((coord_x, coord_y), terrain movement cost). The cost of
+// entering each neighbor is given by cost_fn, which returns None if the terrain
+// is impassable.
+fn neighbor_costs<F>(
+    point: (u32, u32),
     terrain: &Vec<Vec<Terrain>>,
     max_w: u32,
     max_h: u32,
-) -> Vec<((u32, u32), Rational)> {
-    let mut neighbors = vec![];
-    if point_x >= 1 && is_point_valid((point_x - 1, point_y), max_w, max_h)
-        && terrain[(point_x - 1) as usize][point_y as usize] != Terrain::Wall
-    {
-        let terr = &terrain[(point_x - 1) as usize][point_y as usize];
-        neighbors.push(((point_x - 1, point_y), terr.cost()));
-    }
-    if is_point_valid((point_x + 1, point_y), max_w, max_h)
-        && terrain[(point_x + 1) as usize][point_y as usize] != Terrain::Wall
-    {
-        let terr = &terrain[(point_x + 1) as usize][point_y as usize];
-        neighbors.push(((point_x + 1, point_y), terr.cost()));
-    }
-    if point_y >= 1 && is_point_valid((point_x, point_y - 1), max_w, max_h)
-        && terrain[point_x as usize][(point_y - 1) as usize] != Terrain::Wall
-    {
-        let terr = &terrain[point_x as usize][(point_y - 1) as usize];
-        neighbors.push(((point_x, point_y - 1), terr.cost()));
-    }
-    if is_point_valid((point_x, point_y + 1), max_w, max_h)
-        && terrain[point_x as usize][(point_y + 1) as usize] != Terrain::Wall
-    {
-        let terr = &terrain[point_x as usize][(point_y + 1) as usize];
-        neighbors.push(((point_x, point_y + 1), terr.cost()));
-    }
-    neighbors
+    cost_fn: &F,
+) -> Vec<((u32, u32), Rational)>
+where
+    F: Fn(&Terrain) -> Option<Rational>,
+{
+    valid_neighbor_coords(point, max_w, max_h)
+        .into_iter()
+        .filter_map(|(x, y)| cost_fn(&terrain[x as usize][y as usize]).map(|cost| ((x, y), cost)))
+        .collect()
 }
 
 // Gets all valid neighbor coordinates (doesn't look at terrain)
@@ -101,6 +86,19 @@ fn valid_neighbor_coords(
     neighbors
 }
 
+// How other units get in the way of a moving unit.
+pub struct MoveRules<'a> {
+    // Faction of the moving unit
+    pub faction: Faction,
+    // Factions of the units on the map, keyed by location. Units can move
+    // through cells occupied by friendly units but can't end their movement
+    // there, and can't enter cells occupied by hostile units at all.
+    pub occupants: &'a HashMap<(u32, u32), Faction>,
+    // If true, entering a cell next to a hostile unit ends the unit's movement
+    // there. Leaving such a cell from src is still allowed.
+    pub zone_of_control: bool,
+}
+
 // Dijkstra's algorithm
 // Params:
 // src: (x, y) coords of source and destination
@@ -108,28 +106,37 @@ fn valid_neighbor_coords(
 // max_w, max_h: grid width, height
 // max_dist: max movement of unit (algorithm stops considering neighbors when it
 // encounters a total cost > max_dist)
+// cost_fn: cost of entering a cell with the given terrain for the moving unit,
+// or None if the terrain is impassable (see CostTable::cost())
+// rules: how other units get in the way of the moving unit (see MoveRules)
 //
 // Returns:
 // 0: map of backpointers indicating best paths to each coord
 // 1: map of costs to each coord
 // 2: set of boundary coords
-// 3: set of all reachable coords
+// 3: set of all reachable coords (i.e. coords where the unit can end its
+// movement)
 //
 // At the src point, came_from points to src (i.e., to find the end of the path,
 // the condition is that the backpointer for a point points to the point
 // itself).
-pub fn compute_path_costs(
+pub fn compute_path_costs<F>(
     src: (u32, u32),
     terrain: &Vec<Vec<Terrain>>,
     max_w: u32,
     max_h: u32,
     max_dist: u32,
+    cost_fn: F,
+    rules: &MoveRules,
 ) -> (
     HashMap<(u32, u32), (u32, u32)>,
     HashMap<(u32, u32), Rational>,
     HashSet<(u32, u32)>,
     HashSet<(u32, u32)>,
-) {
+)
+where
+    F: Fn(&Terrain) -> Option<Rational>,
+{
     let mut frontier = BinaryHeap::new();
     frontier.push(DaState::new(Ratio::from_integer(0), src));
     let mut came_from: HashMap<(u32, u32), (u32, u32)> = HashMap::new();
@@ -143,10 +150,25 @@ pub fn compute_path_costs(
 
     while !frontier.is_empty() {
         let current = frontier.pop().unwrap();
-        max_boundary.remove(&came_from[&current.pos]);
-        max_boundary.insert(current.pos);
-        reachable_coords.insert(current.pos);
-        for (neighbor_coord, cost) in neighbor_costs(current.pos, terrain, max_w, max_h) {
+        // The moving unit is itself in occupants at src
+        if current.pos == src || !rules.occupants.contains_key(&current.pos) {
+            max_boundary.remove(&came_from[&current.pos]);
+            max_boundary.insert(current.pos);
+            reachable_coords.insert(current.pos);
+        }
+        // Entering a cell next to a hostile unit ends movement there
+        if rules.zone_of_control
+            && current.pos != src
+            && is_next_to_hostile(current.pos, max_w, max_h, rules)
+        {
+            continue;
+        }
+        for (neighbor_coord, cost) in neighbor_costs(current.pos, terrain, max_w, max_h, &cost_fn) {
+            if let Some(occupant) = rules.occupants.get(&neighbor_coord) {
+                if occupant.is_hostile_to(&rules.faction) {
+                    continue;
+                }
+            }
             let new_cost = cost_so_far[&current.pos] + cost;
             if new_cost <= max_dist
                 && (!cost_so_far.contains_key(&neighbor_coord)
@@ -161,6 +183,140 @@ pub fn compute_path_costs(
     (came_from, cost_so_far, max_boundary, reachable_coords)
 }
 
+fn is_next_to_hostile(point: (u32, u32), max_w: u32, max_h: u32, rules: &MoveRules) -> bool {
+    valid_neighbor_coords(point, max_w, max_h)
+        .iter()
+        .any(|neighbor| {
+            rules
+                .occupants
+                .get(neighbor)
+                .map_or(false, |occupant| occupant.is_hostile_to(&rules.faction))
+        })
+}
+
+pub fn manhattan_distance((x1, y1): (u32, u32), (x2, y2): (u32, u32)) -> u32 {
+    (if x1 > x2 { x1 - x2 } else { x2 - x1 }) + (if y1 > y2 { y1 - y2 } else { y2 - y1 })
+}
+
+// A* search from src to dest using the Manhattan distance as the heuristic.
+// Unlike compute_path_costs(), the search is not bounded by a movement range,
+// so it can be used to plan movement over several turns.
+// Params:
+// src, dest: (x, y) coords of source and destination
+// terrain: 2d vec (x, y) of terrain features
+// cost_fn: cost of entering a cell with the given terrain, or None if the
+// terrain is impassable. The heuristic is only admissible if every cost is at
+// least 1.
+// blocked: cells that can't be passed through whatever their terrain, such as
+// cells occupied by hostile units. dest can still be entered.
+//
+// Returns the path from src to dest (inclusive) and its total cost, or None if
+// dest can't be reached.
+pub fn find_path<F>(
+    src: (u32, u32),
+    dest: (u32, u32),
+    terrain: &Vec<Vec<Terrain>>,
+    cost_fn: F,
+    blocked: &HashSet<(u32, u32)>,
+) -> Option<(Vec<(u32, u32)>, Rational)>
+where
+    F: Fn(&Terrain) -> Option<Rational>,
+{
+    let max_w = terrain.len() as u32;
+    let max_h = if max_w > 0 { terrain[0].len() as u32 } else { 0 };
+    if !is_point_valid(src, max_w, max_h) || !is_point_valid(dest, max_w, max_h) {
+        return None;
+    }
+
+    let heuristic = |pos| Ratio::from_integer(manhattan_distance(pos, dest) as isize);
+    let mut frontier = BinaryHeap::new();
+    frontier.push(DaState::new(heuristic(src), src));
+    let mut came_from: HashMap<(u32, u32), (u32, u32)> = HashMap::new();
+    came_from.insert(src, src);
+    let mut cost_so_far: HashMap<(u32, u32), Rational> = HashMap::new();
+    cost_so_far.insert(src, Ratio::from_integer(0));
+
+    while let Some(current) = frontier.pop() {
+        if current.pos == dest {
+            return Some((get_path(dest, &came_from), cost_so_far[&dest]));
+        }
+        for (neighbor_coord, cost) in neighbor_costs(current.pos, terrain, max_w, max_h, &cost_fn) {
+            if neighbor_coord != dest && blocked.contains(&neighbor_coord) {
+                continue;
+            }
+            let new_cost = cost_so_far[&current.pos] + cost;
+            if !cost_so_far.contains_key(&neighbor_coord)
+                || new_cost < cost_so_far[&neighbor_coord]
+            {
+                cost_so_far.insert(neighbor_coord, new_cost);
+                frontier.push(DaState::new(new_cost + heuristic(neighbor_coord), neighbor_coord));
+                came_from.insert(neighbor_coord, current.pos);
+            }
+        }
+    }
+    None
+}
+
+// Returns every coord whose Manhattan distance from at least one of coords is
+// one of distances (see Unit::attack_distances()). Terrain is ignored since
+// attacks aren't blocked by walls.
+pub fn compute_threatened_coords(
+    coords: &HashSet<(u32, u32)>,
+    distances: &BTreeSet<u32>,
+    max_w: u32,
+    max_h: u32,
+) -> HashSet<(u32, u32)> {
+    let mut threatened = HashSet::new();
+    let max_range = match distances.iter().next_back() {
+        Some(&max_range) => max_range as i32,
+        None => return threatened,
+    };
+    for &(x, y) in coords.iter() {
+        for dx in -max_range..max_range + 1 {
+            for dy in -max_range..max_range + 1 {
+                let dist = (dx.abs() + dy.abs()) as u32;
+                if !distances.contains(&dist) {
+                    continue;
+                }
+                let (tx, ty) = (x as i32 + dx, y as i32 + dy);
+                if tx >= 0 && ty >= 0 && is_point_valid((tx as u32, ty as u32), max_w, max_h) {
+                    threatened.insert((tx as u32, ty as u32));
+                }
+            }
+        }
+    }
+    threatened
+}
+
+// Computes the attack range drawn around a unit's movement range: every coord
+// within weapon range of a reachable coord that isn't itself reachable.
+pub fn compute_attack_coords(
+    reachable_coords: &HashSet<(u32, u32)>,
+    distances: &BTreeSet<u32>,
+    max_w: u32,
+    max_h: u32,
+) -> HashSet<(u32, u32)> {
+    compute_threatened_coords(reachable_coords, distances, max_w, max_h)
+        .difference(reachable_coords)
+        .cloned()
+        .collect()
+}
+
+// Counts how many of the given areas contain each coord. Coords that aren't in
+// any area are left out.
+pub fn compute_threat_counts<'a, I>(areas: I) -> HashMap<(u32, u32), u32>
+where
+    I: IntoIterator<Item = &'a HashSet<(u32, u32)>>,
+{
+    let mut counts = HashMap::new();
+    for area in areas {
+        for &coord in area.iter() {
+            *counts.entry(coord).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
 // Reads from the map of backpointers to get the best path to dest.
 pub fn get_path(dest: (u32, u32), paths: &HashMap<(u32, u32), (u32, u32)>) -> Vec<(u32, u32)> {
     if paths.get(&dest) == Some(&dest) {
@@ -232,7 +388,7 @@ fn relative_adj_dir(from: (u32, u32), to: (u32, u32)) -> Direction {
 // For each grid coord in the boundary, look at its neighbors and remove each
 // direction to the neighbor. We need to do this because when we draw the
 // boundary, we need to determine on which sides of the grid cell to draw the
-// border.
+// border. Coords with no sides facing outside the reachable area are left out.
 pub fn find_boundary_neighbor_directions(
     boundary: &HashSet<(u32, u32)>,
     reachable_coords: &HashSet<(u32, u32)>,
@@ -251,6 +407,7 @@ pub fn find_boundary_neighbor_directions(
             }
             ((x, y), directions)
         })
+        .filter(|&(_, ref directions)| !directions.is_empty())
         .collect()
 }
 
@@ -340,4 +497,344 @@ mod test {
         let cpath = consolidate_path(path);
         assert_eq!(cpath, vec![(1, 0), (6, 0), (6, 2), (4, 2)]);
     }
+
+    fn default_cost(terr: &Terrain) -> Option<Rational> {
+        match terr {
+            &Terrain::Sand => Some(Ratio::new(5, 2)),
+            &Terrain::Wall => None,
+            _ => Some(Ratio::from_integer(1)),
+        }
+    }
+
+    fn empty_terrain(w: usize, h: usize) -> Vec<Vec<Terrain>> {
+        vec![vec![Terrain::None; h]; w]
+    }
+
+    #[test]
+    fn test_find_path_src_is_dest() {
+        let terrain = empty_terrain(3, 3);
+        assert_eq!(
+            find_path((1, 1), (1, 1), &terrain, default_cost, &HashSet::new()),
+            Some((vec![(1, 1)], Ratio::from_integer(0)))
+        );
+    }
+
+    #[test]
+    fn test_find_path_straight() {
+        let terrain = empty_terrain(5, 1);
+        assert_eq!(
+            find_path((0, 0), (4, 0), &terrain, default_cost, &HashSet::new()),
+            Some((
+                vec![(0, 0), (1, 0), (2, 0), (3, 0), (4, 0)],
+                Ratio::from_integer(4)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_find_path_around_wall() {
+        // 0w0
+        // 0w0
+        // 000
+        let mut terrain = empty_terrain(3, 3);
+        terrain[1][0] = Terrain::Wall;
+        terrain[1][1] = Terrain::Wall;
+        let (path, cost) =
+            find_path((0, 0), (2, 0), &terrain, default_cost, &HashSet::new()).unwrap();
+        assert_eq!(
+            path,
+            vec![(0, 0), (0, 1), (0, 2), (1, 2), (2, 2), (2, 1), (2, 0)]
+        );
+        assert_eq!(cost, Ratio::from_integer(6));
+    }
+
+    #[test]
+    fn test_find_path_avoids_sand() {
+        // 0ss0
+        // 0000
+        let mut terrain = empty_terrain(4, 2);
+        terrain[1][0] = Terrain::Sand;
+        terrain[2][0] = Terrain::Sand;
+        let (path, cost) =
+            find_path((0, 0), (3, 0), &terrain, default_cost, &HashSet::new()).unwrap();
+        assert_eq!(path, vec![(0, 0), (0, 1), (1, 1), (2, 1), (3, 1), (3, 0)]);
+        assert_eq!(cost, Ratio::from_integer(5));
+    }
+
+    #[test]
+    fn test_find_path_unreachable() {
+        let mut terrain = empty_terrain(3, 3);
+        for y in 0..3 {
+            terrain[1][y] = Terrain::Wall;
+        }
+        assert_eq!(find_path((0, 0), (2, 2), &terrain, default_cost, &HashSet::new()), None);
+        assert_eq!(find_path((0, 0), (5, 5), &terrain, default_cost, &HashSet::new()), None);
+    }
+
+    #[test]
+    fn test_find_path_around_blocked() {
+        // 0b00
+        // 0000
+        let terrain = empty_terrain(4, 2);
+        let blocked = hashset!{(1, 0), (3, 0)};
+        let (path, cost) = find_path((0, 0), (3, 0), &terrain, default_cost, &blocked).unwrap();
+        assert_eq!(path, vec![(0, 0), (0, 1), (1, 1), (2, 1), (3, 1), (3, 0)]);
+        assert_eq!(cost, Ratio::from_integer(5));
+    }
+
+    #[test]
+    fn test_find_path_beyond_movement_range() {
+        let terrain = empty_terrain(30, 20);
+        let (path, cost) =
+            find_path((0, 0), (29, 19), &terrain, default_cost, &HashSet::new()).unwrap();
+        assert_eq!(path.len(), 49);
+        assert_eq!(path[0], (0, 0));
+        assert_eq!(path[48], (29, 19));
+        assert_eq!(cost, Ratio::from_integer(48));
+    }
+
+    #[test]
+    fn test_compute_attack_coords_melee() {
+        let reachable = hashset!{(1, 1)};
+        let attack_coords = compute_attack_coords(&reachable, &btreeset!{1}, 3, 3);
+        assert_eq!(attack_coords, hashset!{(0, 1), (2, 1), (1, 0), (1, 2)});
+    }
+
+    #[test]
+    fn test_compute_attack_coords_excludes_reachable() {
+        let reachable = hashset!{(0, 0), (1, 0)};
+        let attack_coords = compute_attack_coords(&reachable, &btreeset!{1}, 3, 2);
+        assert_eq!(attack_coords, hashset!{(2, 0), (0, 1), (1, 1)});
+    }
+
+    #[test]
+    fn test_compute_attack_coords_bow() {
+        // 2-3 range doesn't include adjacent cells
+        let reachable = hashset!{(0, 0)};
+        let attack_coords = compute_attack_coords(&reachable, &btreeset!{2, 3}, 4, 4);
+        assert_eq!(
+            attack_coords,
+            hashset!{(2, 0), (1, 1), (0, 2), (3, 0), (2, 1), (1, 2), (0, 3)}
+        );
+    }
+
+    #[test]
+    fn test_compute_attack_coords_disjoint_ranges() {
+        // A sword and a 3 range weapon don't reach 2 cells away
+        let reachable = hashset!{(0, 0)};
+        let attack_coords = compute_attack_coords(&reachable, &btreeset!{1, 3}, 4, 1);
+        assert_eq!(attack_coords, hashset!{(1, 0), (3, 0)});
+    }
+
+    #[test]
+    fn test_compute_threatened_coords_includes_own_cell() {
+        let coords = hashset!{(1, 1), (1, 2)};
+        let threatened = compute_threatened_coords(&coords, &btreeset!{1}, 3, 4);
+        assert!(threatened.contains(&(1, 1)));
+        assert!(threatened.contains(&(1, 2)));
+        assert!(!threatened.contains(&(0, 0)));
+    }
+
+    #[test]
+    fn test_compute_threat_counts() {
+        let areas = vec![hashset!{(0, 0), (1, 0)}, hashset!{(1, 0), (2, 0)}];
+        let counts = compute_threat_counts(areas.iter());
+        assert_eq!(counts, hashmap!{(0, 0) => 1, (1, 0) => 2, (2, 0) => 1});
+    }
+
+    #[test]
+    fn test_find_boundary_neighbor_directions() {
+        // A ring of cells around an unreachable hole at (1, 1)
+        let reachable = hashset!{
+            (0, 0), (1, 0), (2, 0),
+            (0, 1), (2, 1),
+            (0, 2), (1, 2), (2, 2),
+        };
+        let mut border = find_boundary_neighbor_directions(&reachable, &reachable, 4, 3);
+        border.sort_by_key(|&(coord, _)| coord);
+        assert_eq!(border.len(), 8);
+        // The map edge counts as outside
+        assert_eq!(border[0], ((0, 0), vec![Direction::N, Direction::W]));
+        assert_eq!(border[1], ((0, 1), vec![Direction::E, Direction::W]));
+        assert_eq!(border[3], ((1, 0), vec![Direction::N, Direction::S]));
+        assert_eq!(border[7], ((2, 2), vec![Direction::S, Direction::E]));
+        let interior = find_boundary_neighbor_directions(
+            &hashset!{(1, 1)},
+            &hashset!{(1, 0), (0, 1), (1, 1), (2, 1), (1, 2)},
+            3,
+            3,
+        );
+        assert!(interior.is_empty());
+    }
+
+    #[test]
+    fn test_compute_path_costs_no_occupants() {
+        let terrain = empty_terrain(5, 1);
+        let (_, costs, _, reachable_coords) = compute_path_costs(
+            (0, 0),
+            &terrain,
+            5,
+            1,
+            2,
+            default_cost,
+            &MoveRules {
+                faction: Faction::Player,
+                occupants: &HashMap::new(),
+                zone_of_control: false,
+            },
+        );
+        assert_eq!(reachable_coords, hashset!{(0, 0), (1, 0), (2, 0)});
+        assert_eq!(costs[&(2, 0)], Ratio::from_integer(2));
+    }
+
+    #[test]
+    fn test_compute_path_costs_blocked_by_enemy() {
+        // Enemy at (1, 0) in a 1-wide corridor
+        let terrain = empty_terrain(5, 1);
+        let occupants = hashmap!{(0, 0) => Faction::Player, (1, 0) => Faction::Enemy};
+        let (paths, _, _, reachable_coords) = compute_path_costs(
+            (0, 0),
+            &terrain,
+            5,
+            1,
+            4,
+            default_cost,
+            &MoveRules {
+                faction: Faction::Player,
+                occupants: &occupants,
+                zone_of_control: false,
+            },
+        );
+        assert_eq!(reachable_coords, hashset!{(0, 0)});
+        assert!(!paths.contains_key(&(1, 0)));
+    }
+
+    #[test]
+    fn test_compute_path_costs_pass_through_ally() {
+        let terrain = empty_terrain(5, 1);
+        let occupants = hashmap!{(0, 0) => Faction::Player, (1, 0) => Faction::Other};
+        let (paths, _, _, reachable_coords) = compute_path_costs(
+            (0, 0),
+            &terrain,
+            5,
+            1,
+            3,
+            default_cost,
+            &MoveRules {
+                faction: Faction::Player,
+                occupants: &occupants,
+                zone_of_control: false,
+            },
+        );
+        // Can move through the ally but not stop on it
+        assert_eq!(reachable_coords, hashset!{(0, 0), (2, 0), (3, 0)});
+        assert_eq!(get_path((2, 0), &paths), vec![(0, 0), (1, 0), (2, 0)]);
+    }
+
+    #[test]
+    fn test_compute_path_costs_zone_of_control() {
+        // Enemy at (2, 1) next to the corridor along y = 0
+        let terrain = empty_terrain(5, 2);
+        let occupants = hashmap!{(0, 0) => Faction::Player, (2, 1) => Faction::Enemy};
+        let (_, _, boundary, reachable_coords) = compute_path_costs(
+            (0, 0),
+            &terrain,
+            5,
+            2,
+            4,
+            default_cost,
+            &MoveRules {
+                faction: Faction::Player,
+                occupants: &occupants,
+                zone_of_control: true,
+            },
+        );
+        assert_eq!(
+            reachable_coords,
+            hashset!{(0, 0), (1, 0), (2, 0), (0, 1), (1, 1)}
+        );
+        assert!(boundary.contains(&(2, 0)));
+        assert!(!boundary.contains(&(4, 0)));
+    }
+
+    #[test]
+    fn test_compute_path_costs_zone_of_control_leave_src() {
+        // Starting next to the enemy doesn't stop the unit from moving away
+        let terrain = empty_terrain(4, 1);
+        let occupants = hashmap!{(1, 0) => Faction::Player, (0, 0) => Faction::Enemy};
+        let (_, _, _, reachable_coords) = compute_path_costs(
+            (1, 0),
+            &terrain,
+            4,
+            1,
+            2,
+            default_cost,
+            &MoveRules {
+                faction: Faction::Player,
+                occupants: &occupants,
+                zone_of_control: true,
+            },
+        );
+        assert_eq!(reachable_coords, hashset!{(1, 0), (2, 0), (3, 0)});
+    }
+
+    #[test]
+    fn test_compute_path_costs_enemies_pass_through_each_other() {
+        let terrain = empty_terrain(3, 1);
+        let occupants = hashmap!{(0, 0) => Faction::Enemy, (1, 0) => Faction::Enemy};
+        let (_, _, _, reachable_coords) = compute_path_costs(
+            (0, 0),
+            &terrain,
+            3,
+            1,
+            2,
+            default_cost,
+            &MoveRules {
+                faction: Faction::Enemy,
+                occupants: &occupants,
+                zone_of_control: false,
+            },
+        );
+        assert_eq!(reachable_coords, hashset!{(0, 0), (2, 0)});
+    }
+
+    #[test]
+    fn test_compute_path_costs_uses_cost_fn() {
+        // Flier-style costs where sand is as cheap as plains
+        let mut terrain = empty_terrain(4, 1);
+        terrain[1][0] = Terrain::Sand;
+        terrain[2][0] = Terrain::Sand;
+        let (_, _, _, reachable_coords) = compute_path_costs(
+            (0, 0),
+            &terrain,
+            4,
+            1,
+            3,
+            default_cost,
+            &MoveRules {
+                faction: Faction::Player,
+                occupants: &HashMap::new(),
+                zone_of_control: false,
+            },
+        );
+        assert_eq!(reachable_coords, hashset!{(0, 0), (1, 0)});
+        let flier_cost = |terr: &Terrain| match terr {
+            &Terrain::Wall => None,
+            _ => Some(Ratio::from_integer(1)),
+        };
+        let (_, _, _, reachable_coords) = compute_path_costs(
+            (0, 0),
+            &terrain,
+            4,
+            1,
+            3,
+            flier_cost,
+            &MoveRules {
+                faction: Faction::Player,
+                occupants: &HashMap::new(),
+                zone_of_control: false,
+            },
+        );
+        assert_eq!(reachable_coords, hashset!{(0, 0), (1, 0), (2, 0), (3, 0)});
+    }
 }
@@ -0,0 +1,86 @@
+// Small deterministic random number generator (xorshift64*). We use our own
+// instead of a library so that the state is a plain u64 that can be saved and
+// restored, and so that a seed always produces the same battle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Scramble the seed (splitmix64) so that similar seeds give unrelated
+        // sequences. xorshift gets stuck on a zero state.
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z = z ^ (z >> 31);
+        Rng {
+            state: if z == 0 { 1 } else { z },
+        }
+    }
+
+    pub fn from_state(state: u64) -> Self {
+        Rng {
+            state: if state == 0 { 1 } else { state },
+        }
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 32) as u32
+    }
+
+    // Uniformly distributed in [0, 100).
+    pub fn roll_percent(&mut self) -> u32 {
+        ((self.next_u32() as u64 * 100) >> 32) as u32
+    }
+
+    // Returns true with the given percent chance.
+    pub fn check(&mut self, percent: u32) -> bool {
+        self.roll_percent() < percent
+    }
+
+    // Like check(), but uses the average of two rolls, which makes high
+    // percentages more likely and low percentages less likely than displayed.
+    pub fn check_2rn(&mut self, percent: u32) -> bool {
+        (self.roll_percent() + self.roll_percent()) / 2 < percent
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        for _ in 0..100 {
+            assert_eq!(a.next_u32(), b.next_u32());
+        }
+    }
+
+    #[test]
+    fn test_from_state() {
+        let mut a = Rng::new(7);
+        a.next_u32();
+        let mut b = Rng::from_state(a.state());
+        assert_eq!(a.next_u32(), b.next_u32());
+    }
+
+    #[test]
+    fn test_roll_percent_range() {
+        let mut rng = Rng::new(0);
+        for _ in 0..1000 {
+            assert!(rng.roll_percent() < 100);
+        }
+        assert!((0..1000).all(|_| rng.check(100)));
+        assert!((0..1000).all(|_| !rng.check_2rn(0)));
+    }
+}
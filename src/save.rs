@@ -0,0 +1,402 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::result;
+use ron;
+use ron::ser::PrettyConfig;
+use ai::Behavior;
+use battle::{Battle, Outcome};
+use chapter::Chapter;
+use class::Class;
+use item::Inventory;
+use rng::Rng;
+use skill::Skill;
+use stats::Stats;
+use terrain::CostTable;
+use turn::TurnManager;
+use unit::{Faction, Unit};
+
+// Bumped whenever the layout of SaveData changes. Saves from other versions
+// are refused rather than loaded wrong.
+pub const SAVE_VERSION: u32 = 2;
+
+// Where the frontends keep their saves.
+pub const SAVE_DIR: &str = "saves";
+
+pub type Result<T> = result::Result<T, SaveErr>;
+
+#[derive(Debug)]
+pub enum SaveErr {
+    // The save was written by a different version of the game
+    UnsupportedVersion(u32),
+    // What's wrong with the save
+    InvalidSaveData(String),
+    Io(io::Error),
+}
+
+use self::SaveErr::*;
+
+impl From<io::Error> for SaveErr {
+    fn from(err: io::Error) -> Self {
+        Io(err)
+    }
+}
+
+impl fmt::Display for SaveErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &UnsupportedVersion(version) => write!(
+                f,
+                "save is from version {}, expected version {}",
+                version, SAVE_VERSION
+            ),
+            &InvalidSaveData(ref message) => write!(f, "invalid save: {}", message),
+            &Io(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    // Saves the player makes and loads as often as they like
+    Numbered(u32),
+    // Made when quitting in the middle of a chapter. It's deleted as soon as
+    // it's loaded so that it can't be used to retry fights.
+    Suspend,
+}
+
+impl Slot {
+    pub fn file_name(&self) -> String {
+        match self {
+            &Slot::Numbered(number) => format!("slot{}.ron", number),
+            &Slot::Suspend => "suspend.ron".to_string(),
+        }
+    }
+}
+
+// Read first to check the version before reading the rest.
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+// Everything needed to restore a Battle. Path data and the danger zone are
+// recomputed on load, and the cost table is data rather than state.
+#[derive(Serialize, Deserialize)]
+struct SaveData {
+    version: u32,
+    // The chapter as it was loaded, which decides the map, the win and loss
+    // conditions and the reinforcements still to come
+    chapter: Chapter,
+    zone_of_control: bool,
+    turn_manager: TurnManager,
+    rng_state: u64,
+    outcome: Option<Outcome>,
+    rewinds: u32,
+    units: Vec<UnitData>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct UnitData {
+    id: u32,
+    faction: Faction,
+    class: Class,
+    level: u32,
+    exp: u32,
+    stats: Stats,
+    hp: u32,
+    inventory: Inventory,
+    skills: Vec<Skill>,
+    movement_range: u32,
+    location: (u32, u32),
+    has_moved: bool,
+    has_acted: bool,
+    behavior: Behavior,
+}
+
+impl UnitData {
+    fn from_unit(unit: &Unit) -> Self {
+        UnitData {
+            id: unit.id,
+            faction: unit.faction,
+            class: unit.class,
+            level: unit.level,
+            exp: unit.exp,
+            stats: unit.stats,
+            hp: unit.hp,
+            inventory: unit.inventory.clone(),
+            skills: unit.skills.clone(),
+            movement_range: unit.movement_range,
+            location: unit.location,
+            has_moved: unit.has_moved,
+            has_acted: unit.has_acted,
+            behavior: unit.behavior,
+        }
+    }
+
+    fn into_unit(self) -> Unit {
+        let mut unit = Unit::new(
+            self.id,
+            self.faction,
+            self.class,
+            self.level,
+            self.location,
+            self.inventory,
+        );
+        unit.exp = self.exp;
+        unit.stats = self.stats;
+        unit.hp = self.hp;
+        unit.skills = self.skills;
+        unit.movement_range = self.movement_range;
+        unit.has_moved = self.has_moved;
+        unit.has_acted = self.has_acted;
+        unit.behavior = self.behavior;
+        unit
+    }
+}
+
+pub fn save_to_string(battle: &Battle) -> Result<String> {
+    // Sorted so that saving the same battle twice gives the same file
+    let mut units: Vec<UnitData> = battle
+        .units
+        .values()
+        .map(|unit| UnitData::from_unit(&unit.borrow()))
+        .collect();
+    units.sort_by_key(|unit| unit.id);
+    let data = SaveData {
+        version: SAVE_VERSION,
+        chapter: battle.chapter.clone(),
+        zone_of_control: battle.zone_of_control,
+        turn_manager: battle.turn_manager.clone(),
+        rng_state: battle.rng.state(),
+        outcome: battle.outcome,
+        rewinds: battle.rewinds,
+        units,
+    };
+    ron::ser::to_string_pretty(&data, PrettyConfig::default())
+        .map_err(|err| InvalidSaveData(err.to_string()))
+}
+
+pub fn load_from_str(contents: &str, cost_table: CostTable) -> Result<Battle> {
+    let header: SaveHeader =
+        ron::de::from_str(contents).map_err(|err| InvalidSaveData(err.to_string()))?;
+    if header.version != SAVE_VERSION {
+        return Err(UnsupportedVersion(header.version));
+    }
+    let data: SaveData =
+        ron::de::from_str(contents).map_err(|err| InvalidSaveData(err.to_string()))?;
+
+    let chapter = data.chapter;
+    if chapter.terrain.len() != chapter.width as usize
+        || chapter
+            .terrain
+            .iter()
+            .any(|column| column.len() != chapter.height as usize)
+    {
+        return Err(InvalidSaveData("map doesn't match its size".to_string()));
+    }
+    let mut units = HashMap::new();
+    for unit_data in data.units {
+        let (x, y) = unit_data.location;
+        if x >= chapter.width || y >= chapter.height {
+            return Err(InvalidSaveData(format!("unit {} is off the map", unit_data.id)));
+        }
+        if units.contains_key(&unit_data.location) {
+            return Err(InvalidSaveData(format!(
+                "more than one unit at {:?}",
+                unit_data.location
+            )));
+        }
+        let unit = unit_data.into_unit();
+        units.insert(unit.location, Rc::new(RefCell::new(unit)));
+    }
+
+    let mut battle = Battle {
+        terrain: chapter.terrain.clone(),
+        width: chapter.width,
+        height: chapter.height,
+        cost_table,
+        zone_of_control: data.zone_of_control,
+        units,
+        turn_manager: data.turn_manager,
+        rng: Rng::from_state(data.rng_state),
+        danger_zone: HashMap::new(),
+        outcome: data.outcome,
+        rewinds: data.rewinds,
+        chapter,
+    };
+    battle.recompute_unit_paths();
+    Ok(battle)
+}
+
+pub fn slot_path<P: AsRef<Path>>(dir: P, slot: Slot) -> PathBuf {
+    dir.as_ref().join(slot.file_name())
+}
+
+pub fn exists<P: AsRef<Path>>(dir: P, slot: Slot) -> bool {
+    slot_path(dir, slot).is_file()
+}
+
+// Numbers of the numbered slots that hold a save.
+pub fn numbered_slots<P: AsRef<Path>>(dir: P) -> Vec<u32> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    let mut numbers: Vec<u32> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with("slot") || !name.ends_with(".ron") {
+                return None;
+            }
+            name["slot".len()..name.len() - ".ron".len()].parse().ok()
+        })
+        .collect();
+    numbers.sort();
+    numbers
+}
+
+// Creates dir if needed. The save is written next to the slot's file first
+// so that a failed write doesn't destroy the previous save.
+pub fn save<P: AsRef<Path>>(battle: &Battle, dir: P, slot: Slot) -> Result<()> {
+    let contents = save_to_string(battle)?;
+    fs::create_dir_all(&dir)?;
+    let path = slot_path(&dir, slot);
+    let temp_path = path.with_extension("ron.tmp");
+    {
+        let mut f = File::create(&temp_path)?;
+        f.write_all(contents.as_bytes())?;
+        f.sync_all()?;
+    }
+    fs::rename(&temp_path, &path)?;
+    Ok(())
+}
+
+// Loading the suspend slot deletes it.
+pub fn load<P: AsRef<Path>>(dir: P, slot: Slot, cost_table: CostTable) -> Result<Battle> {
+    let path = slot_path(&dir, slot);
+    let mut contents = String::new();
+    File::open(&path)?.read_to_string(&mut contents)?;
+    let battle = load_from_str(&contents, cost_table)?;
+    if slot == Slot::Suspend {
+        fs::remove_file(&path)?;
+    }
+    Ok(battle)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::process;
+    use terrain::Terrain;
+    use testing::{self, cost_table, placement};
+
+    fn battle() -> Battle {
+        let mut chapter = testing::chapter(
+            6,
+            4,
+            vec![
+                placement(1, Faction::Player, (0, 0), &["Iron Sword", "Vulnerary"]),
+                placement(2, Faction::Enemy, (2, 0), &["Iron Sword"]),
+                placement(3, Faction::Enemy, (5, 3), &[]),
+            ],
+        );
+        chapter.terrain[2][3] = Terrain::Forest;
+        chapter.zone_of_control = true;
+        Battle::new(chapter, cost_table(), 7)
+    }
+
+    // A directory of its own for each test, since tests run in parallel.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("feg-save-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut battle = battle();
+        battle.move_unit((0, 0), (1, 0));
+        let result = battle.resolve_attack((1, 0), (2, 0)).unwrap();
+        battle.apply_combat((1, 0), (2, 0), &result);
+        battle.units[&(1, 0)].borrow_mut().gain_exp(150);
+        battle.end_phase();
+        battle.rewinds = 1;
+
+        let contents = save_to_string(&battle).unwrap();
+        let mut loaded = load_from_str(&contents, cost_table()).unwrap();
+        assert_eq!(loaded.chapter, battle.chapter);
+        assert_eq!(loaded.turn_manager, battle.turn_manager);
+        assert_eq!(loaded.outcome, battle.outcome);
+        assert_eq!(loaded.rewinds, battle.rewinds);
+        assert_eq!(loaded.zone_of_control, battle.zone_of_control);
+        assert_eq!(loaded.danger_zone, battle.danger_zone);
+        assert_eq!(loaded.units.len(), battle.units.len());
+        for (location, unit) in battle.units.iter() {
+            let unit = unit.borrow();
+            let loaded_unit = loaded.units[location].borrow();
+            assert_eq!(loaded_unit.id, unit.id);
+            assert_eq!((loaded_unit.level, loaded_unit.exp), (unit.level, unit.exp));
+            assert_eq!(loaded_unit.stats, unit.stats);
+            assert_eq!(loaded_unit.hp, unit.hp);
+            assert_eq!(loaded_unit.inventory, unit.inventory);
+            assert_eq!(
+                (loaded_unit.has_moved, loaded_unit.has_acted),
+                (unit.has_moved, unit.has_acted)
+            );
+            assert_eq!(loaded_unit.reachable_coords, unit.reachable_coords);
+        }
+        // Saving again gives the same file
+        assert_eq!(save_to_string(&loaded).unwrap(), contents);
+        // The rng carries on where it left off
+        assert_eq!(loaded.rng.next_u32(), battle.rng.next_u32());
+    }
+
+    #[test]
+    fn test_wrong_version() {
+        let contents = save_to_string(&battle()).unwrap();
+        let old = contents.replacen(
+            &format!("version: {}", SAVE_VERSION),
+            &format!("version: {}", SAVE_VERSION + 1),
+            1,
+        );
+        match load_from_str(&old, cost_table()) {
+            Err(UnsupportedVersion(version)) => assert_eq!(version, SAVE_VERSION + 1),
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+        let incomplete = format!("(version: {}, units: [])", SAVE_VERSION);
+        match load_from_str(&incomplete, cost_table()) {
+            Err(InvalidSaveData(_)) => (),
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_slots() {
+        let dir = temp_dir("slots");
+        assert_eq!(numbered_slots(&dir), Vec::<u32>::new());
+        let mut battle = battle();
+        save(&battle, &dir, Slot::Numbered(2)).unwrap();
+        battle.end_phase();
+        save(&battle, &dir, Slot::Numbered(1)).unwrap();
+        save(&battle, &dir, Slot::Suspend).unwrap();
+        assert_eq!(numbered_slots(&dir), vec![1, 2]);
+
+        let loaded = load(&dir, Slot::Numbered(2), cost_table()).unwrap();
+        assert_eq!(loaded.turn_manager, TurnManager::new());
+        // Numbered saves can be loaded again, the suspend save can't
+        assert!(exists(&dir, Slot::Numbered(2)));
+        let loaded = load(&dir, Slot::Suspend, cost_table()).unwrap();
+        assert_eq!(loaded.turn_manager, battle.turn_manager);
+        assert!(!exists(&dir, Slot::Suspend));
+        assert!(load(&dir, Slot::Suspend, cost_table()).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
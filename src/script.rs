@@ -0,0 +1,59 @@
+use action::Action;
+
+// What a player unit does on a given turn of a scripted battle, as loaded by
+// dataparser::parse_script().
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Order {
+    pub turn: u32,
+    // Id of the unit following the order
+    pub unit: u32,
+    pub destination: (u32, u32),
+    pub action: Action,
+    // Who to attack or heal
+    pub target: Option<(u32, u32)>,
+}
+
+// Orders for some of the player's units. Units without an order for the
+// turn are left to the AI.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Script {
+    pub orders: Vec<Order>,
+}
+
+impl Script {
+    pub fn new(orders: Vec<Order>) -> Self {
+        Script { orders }
+    }
+
+    // The unit's order for the turn. If there is more than one, the first
+    // wins.
+    pub fn order_for(&self, turn: u32, unit: u32) -> Option<&Order> {
+        self.orders
+            .iter()
+            .find(|order| order.turn == turn && order.unit == unit)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_order_for() {
+        let order = |turn, unit, action| Order {
+            turn,
+            unit,
+            destination: (0, 0),
+            action,
+            target: None,
+        };
+        let script = Script::new(vec![
+            order(1, 1, Action::Wait),
+            order(2, 1, Action::Seize),
+            order(2, 1, Action::Wait),
+        ]);
+        assert_eq!(script.order_for(1, 1).unwrap().action, Action::Wait);
+        assert_eq!(script.order_for(2, 1).unwrap().action, Action::Seize);
+        assert_eq!(script.order_for(1, 2), None);
+    }
+}
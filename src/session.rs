@@ -0,0 +1,718 @@
+use std::cmp;
+use std::mem;
+use action::{self, Action, TradeEntry};
+use battle::Battle;
+use combat::{CombatResult, Forecast};
+use history::History;
+use menu::Menu;
+use pathfinding;
+use turn::Phase;
+use unit::Faction;
+
+#[derive(Debug)]
+pub enum Mode {
+    // Selecting and moving units
+    Normal,
+    // Choosing what the selected unit does after moving
+    ActionMenu(Menu<Action>),
+    // Choosing an item to use or equip. Entries are inventory indices.
+    ItemMenu(Menu<usize>),
+    // Choosing who to attack, heal or trade with
+    SelectTarget(Action, Menu<(u32, u32)>),
+    // Swapping items with the unit at the given location
+    TradeMenu((u32, u32), Menu<TradeEntry>),
+    // Choosing how many of the phase's actions to take back
+    RewindMenu(Menu<usize>),
+}
+
+// Something the frontend has to show before the session carries on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    // The selected unit walks along the path, from where it stands to its
+    // destination. Call Session::finish_move() once it arrives.
+    Move(Vec<(u32, u32)>),
+    // The unit on the first location attacked the unit on the second one.
+    // Call Session::finish_attack() once the fight has been played back.
+    Attack((u32, u32), (u32, u32), CombatResult),
+}
+
+// The player's side of a battle: the grid cursor, the selected unit, the
+// menus for choosing what it does and the history of the phase. Frontends
+// map their input onto a session and draw it, so they all play by the same
+// rules. Walking and fighting are left to them to animate (see Event).
+#[derive(Debug)]
+pub struct Session {
+    pub battle: Battle,
+    pub cursor: (u32, u32),
+    // Location of the selected unit
+    pub selected: Option<(u32, u32)>,
+    // The player phase so far, for undoing moves and rewinding actions
+    pub history: History,
+    pub mode: Mode,
+}
+
+// Moves coord by direction, staying inside a width x height grid.
+pub fn step_coord((x, y): (u32, u32), (dx, dy): (i32, i32), width: u32, height: u32) -> (u32, u32) {
+    (
+        cmp::max(0, cmp::min(width as i32 - 1, x as i32 + dx)) as u32,
+        cmp::max(0, cmp::min(height as i32 - 1, y as i32 + dy)) as u32,
+    )
+}
+
+impl Session {
+    pub fn new(battle: Battle) -> Self {
+        // Start on the first player unit
+        let cursor = battle
+            .units
+            .values()
+            .map(|unit| unit.borrow())
+            .filter(|unit| unit.faction == Faction::Player)
+            .min_by_key(|unit| unit.id)
+            .map_or((0, 0), |unit| unit.location);
+        Session {
+            battle,
+            cursor,
+            selected: None,
+            history: History::new(),
+            mode: Mode::Normal,
+        }
+    }
+
+    // Whether the player can give orders at all.
+    pub fn is_player_phase(&self) -> bool {
+        self.battle.turn_manager.phase == Phase::Player && self.battle.outcome.is_none()
+    }
+
+    // Whether the player is choosing which unit to move next.
+    pub fn is_idle(&self) -> bool {
+        match self.mode {
+            Mode::Normal => self.is_player_phase(),
+            _ => false,
+        }
+    }
+
+    // Moves the grid cursor, or the cursor of the open menu. Target menus are
+    // cycled with all four directions, other menus with up and down.
+    pub fn step_cursor(&mut self, (dx, dy): (i32, i32)) {
+        match self.mode {
+            Mode::Normal => {
+                self.cursor =
+                    step_coord(self.cursor, (dx, dy), self.battle.width, self.battle.height);
+            }
+            Mode::SelectTarget(_, ref mut menu) => {
+                menu.move_cursor(dx + dy);
+                if let Some(&target) = menu.selected() {
+                    self.cursor = target;
+                }
+            }
+            Mode::ActionMenu(ref mut menu) => menu.move_cursor(dy),
+            Mode::ItemMenu(ref mut menu) => menu.move_cursor(dy),
+            Mode::TradeMenu(_, ref mut menu) => menu.move_cursor(dy),
+            Mode::RewindMenu(ref mut menu) => menu.move_cursor(dy),
+        }
+    }
+
+    // Labels and cursor of the open menu, if any.
+    pub fn menu_labels(&self) -> Option<(Vec<&str>, usize)> {
+        match self.mode {
+            Mode::ActionMenu(ref menu) => Some((menu.labels(), menu.cursor())),
+            Mode::ItemMenu(ref menu) => Some((menu.labels(), menu.cursor())),
+            Mode::SelectTarget(_, ref menu) => Some((menu.labels(), menu.cursor())),
+            Mode::TradeMenu(_, ref menu) => Some((menu.labels(), menu.cursor())),
+            Mode::RewindMenu(ref menu) => Some((menu.labels(), menu.cursor())),
+            Mode::Normal => None,
+        }
+    }
+
+    pub fn set_menu_cursor(&mut self, index: usize) {
+        match self.mode {
+            Mode::ActionMenu(ref mut menu) => menu.set_cursor(index),
+            Mode::ItemMenu(ref mut menu) => menu.set_cursor(index),
+            Mode::SelectTarget(_, ref mut menu) => menu.set_cursor(index),
+            Mode::TradeMenu(_, ref mut menu) => menu.set_cursor(index),
+            Mode::RewindMenu(ref mut menu) => menu.set_cursor(index),
+            Mode::Normal => (),
+        }
+    }
+
+    // Points the target cursor and the grid cursor at the target on coord.
+    // Returns false if coord isn't one of the targets.
+    pub fn set_target_cursor(&mut self, coord: (u32, u32)) -> bool {
+        let found = match self.mode {
+            Mode::SelectTarget(_, ref mut menu) => menu.select(&coord),
+            _ => false,
+        };
+        if found {
+            self.cursor = coord;
+        }
+        found
+    }
+
+    // Acts on the cell under the grid cursor, or on the open menu.
+    pub fn confirm(&mut self) -> Option<Event> {
+        if !self.is_player_phase() {
+            return None;
+        }
+        match self.mode {
+            Mode::Normal => {
+                let cursor = self.cursor;
+                self.select(cursor)
+            }
+            _ => self.confirm_menu(),
+        }
+    }
+
+    // Starts moving the selected unit to coord if it can get there,
+    // otherwise selects the unit on coord (if any).
+    pub fn select(&mut self, coord: (u32, u32)) -> Option<Event> {
+        if !self.is_idle() {
+            return None;
+        }
+        if let Some(location) = self.selected {
+            let unit = self.battle.units[&location].borrow();
+            if self.battle.can_move(&unit)
+                && (coord == location || unit.reachable_coords.contains(&coord))
+            {
+                return Some(Event::Move(pathfinding::get_path(coord, &unit.paths)));
+            }
+        }
+        self.selected = if self.battle.units.contains_key(&coord) {
+            Some(coord)
+        } else {
+            None
+        };
+        None
+    }
+
+    // The selected unit has walked to destination. Records the move and
+    // offers what it can do there.
+    pub fn finish_move(&mut self, destination: (u32, u32)) {
+        let origin = match self.selected {
+            Some(origin) => origin,
+            None => return,
+        };
+        self.history.move_unit(&mut self.battle, origin, destination);
+        self.selected = Some(destination);
+        self.open_action_menu();
+    }
+
+    // Jumps to and selects the next player unit (by id) that can still move.
+    pub fn select_next_unit(&mut self) {
+        if !self.is_idle() {
+            return;
+        }
+        let mut candidates: Vec<(u32, (u32, u32))> = self
+            .battle
+            .units
+            .iter()
+            .map(|(&location, unit)| (unit.borrow(), location))
+            .filter(|&(ref unit, _)| self.battle.can_move(unit))
+            .map(|(unit, location)| (unit.id, location))
+            .collect();
+        if candidates.is_empty() {
+            return;
+        }
+        candidates.sort();
+        let current_id = self
+            .selected
+            .and_then(|location| self.battle.units.get(&location))
+            .map_or(0, |unit| unit.borrow().id);
+        let &(_, location) = candidates
+            .iter()
+            .find(|&&(id, _)| id > current_id)
+            .unwrap_or(&candidates[0]);
+        self.cursor = location;
+        self.selected = Some(location);
+    }
+
+    // Offers the actions available to the selected unit where it stands.
+    pub fn open_action_menu(&mut self) {
+        let location = match self.selected {
+            Some(location) => location,
+            None => return,
+        };
+        let entries = self
+            .battle
+            .available_actions(location)
+            .into_iter()
+            .map(|action| (action, action.label().to_string()))
+            .collect();
+        self.cursor = location;
+        self.mode = Mode::ActionMenu(Menu::new(entries));
+    }
+
+    fn open_item_menu(&mut self, location: (u32, u32)) {
+        let unit = self.battle.units[&location].borrow();
+        let entries = unit
+            .inventory
+            .items()
+            .iter()
+            .enumerate()
+            .map(|(index, item)| (index, format!("{} {}", item.name(), item.uses())))
+            .collect();
+        self.mode = Mode::ItemMenu(Menu::new(entries));
+    }
+
+    fn open_target_menu(&mut self, action: Action, targets: Vec<(u32, u32)>) {
+        if let Some(&target) = targets.first() {
+            self.cursor = target;
+        }
+        let entries = targets
+            .into_iter()
+            .map(|target| {
+                let unit = self.battle.units[&target].borrow();
+                (
+                    target,
+                    format!("{} {} {:?}", unit.class.name(), unit.id, target),
+                )
+            })
+            .collect();
+        self.mode = Mode::SelectTarget(action, Menu::new(entries));
+    }
+
+    fn open_trade_menu(&mut self, location: (u32, u32), partner: (u32, u32), cursor: usize) {
+        let unit = self.battle.units[&location].borrow();
+        let partner_unit = self.battle.units[&partner].borrow();
+        let mut entries: Vec<(TradeEntry, String)> = unit
+            .inventory
+            .items()
+            .iter()
+            .enumerate()
+            .map(|(index, item)| (TradeEntry::Give(index), format!("Give {}", item.name())))
+            .collect();
+        entries.extend(
+            partner_unit
+                .inventory
+                .items()
+                .iter()
+                .enumerate()
+                .map(|(index, item)| (TradeEntry::Take(index), format!("Take {}", item.name()))),
+        );
+        let mut menu = Menu::new(entries);
+        menu.set_cursor(cursor);
+        self.mode = Mode::TradeMenu(partner, menu);
+    }
+
+    // Offers to take back any number of the actions made this phase, if
+    // there are rewinds left. Returns false if there is nothing to offer.
+    pub fn open_rewind_menu(&mut self) -> bool {
+        if !self.is_idle() || self.battle.rewinds == 0 || self.history.actions() == 0 {
+            return false;
+        }
+        let entries = (1..self.history.actions() + 1)
+            .map(|actions| (actions, format!("Rewind {}", actions)))
+            .collect();
+        self.selected = None;
+        self.mode = Mode::RewindMenu(Menu::new(entries));
+        true
+    }
+
+    // Chooses the entry under the cursor of the open menu.
+    pub fn confirm_menu(&mut self) -> Option<Event> {
+        match (mem::replace(&mut self.mode, Mode::Normal), self.selected) {
+            (Mode::RewindMenu(menu), _) => match menu.selected().cloned() {
+                Some(actions) => {
+                    self.rewind(actions);
+                }
+                None => self.mode = Mode::RewindMenu(menu),
+            },
+            (Mode::ActionMenu(menu), Some(location)) => match menu.selected().cloned() {
+                Some(action) => self.choose_action(location, action),
+                None => self.mode = Mode::ActionMenu(menu),
+            },
+            (Mode::ItemMenu(menu), Some(location)) => match menu.selected().cloned() {
+                Some(index) => self.choose_item(location, index, menu),
+                None => self.mode = Mode::ItemMenu(menu),
+            },
+            (Mode::SelectTarget(action, menu), Some(location)) => match menu.selected().cloned() {
+                Some(target) => return self.choose_target(location, action, target),
+                None => self.mode = Mode::SelectTarget(action, menu),
+            },
+            (Mode::TradeMenu(partner, menu), Some(location)) => match menu.selected().cloned() {
+                Some(entry) => self.trade(location, partner, entry, menu.cursor()),
+                None => self.mode = Mode::TradeMenu(partner, menu),
+            },
+            (mode, _) => self.mode = mode,
+        }
+        None
+    }
+
+    fn choose_action(&mut self, location: (u32, u32), chosen: Action) {
+        let targets = {
+            let unit = self.battle.units[&location].borrow();
+            match chosen {
+                Action::Attack => action::attack_targets(&unit, &self.battle.units),
+                Action::Staff => action::staff_targets(&unit, &self.battle.units),
+                Action::Trade => action::trade_targets(&unit, &self.battle.units),
+                _ => vec![],
+            }
+        };
+        match chosen {
+            Action::Item => self.open_item_menu(location),
+            Action::Seize => {
+                self.battle.seize(location);
+                self.finish_action();
+            }
+            Action::Wait => self.finish_action(),
+            _ => self.open_target_menu(chosen, targets),
+        }
+    }
+
+    // Uses a consumable or equips a weapon. Equipping doesn't use up the
+    // unit's action.
+    fn choose_item(&mut self, location: (u32, u32), index: usize, menu: Menu<usize>) {
+        if self.battle.use_item(location, index) {
+            self.finish_action();
+        } else if self.battle.equip(location, index) {
+            self.open_action_menu();
+        } else {
+            self.mode = Mode::ItemMenu(menu);
+        }
+    }
+
+    fn choose_target(
+        &mut self,
+        location: (u32, u32),
+        chosen: Action,
+        target: (u32, u32),
+    ) -> Option<Event> {
+        match chosen {
+            Action::Attack => match self.battle.resolve_attack(location, target) {
+                Some(result) => return Some(Event::Attack(location, target, result)),
+                None => self.open_action_menu(),
+            },
+            Action::Staff => {
+                self.battle.use_staff(location, target);
+                self.finish_action();
+            }
+            Action::Trade => self.open_trade_menu(location, target, 0),
+            _ => (),
+        }
+        None
+    }
+
+    // Moves an item between the unit on location and partner if the
+    // receiver has room. The unit can still act afterwards, and cancelling
+    // its move takes the trade back too.
+    fn trade(
+        &mut self,
+        location: (u32, u32),
+        partner: (u32, u32),
+        entry: TradeEntry,
+        cursor: usize,
+    ) {
+        self.battle.trade(location, partner, entry);
+        self.open_trade_menu(location, partner, cursor);
+    }
+
+    // Forecast for the selected unit attacking the target under the cursor.
+    pub fn forecast(&self) -> Option<Forecast> {
+        let location = self.selected?;
+        match self.mode {
+            Mode::SelectTarget(Action::Attack, ref menu) => {
+                let &target = menu.selected()?;
+                self.battle.forecast(location, target)
+            }
+            _ => None,
+        }
+    }
+
+    // The fight from Event::Attack has been shown. The attacker is done for
+    // the turn.
+    pub fn finish_attack(
+        &mut self,
+        attacker: (u32, u32),
+        defender: (u32, u32),
+        result: &CombatResult,
+    ) {
+        self.battle.apply_combat(attacker, defender, result);
+        self.selected = None;
+        self.history.commit();
+        self.mode = Mode::Normal;
+    }
+
+    // The selected unit is done for the turn.
+    pub fn finish_action(&mut self) {
+        if let Some(location) = self.selected.take() {
+            self.battle.wait(location);
+        }
+        self.history.commit();
+        self.mode = Mode::Normal;
+    }
+
+    // Backs out of the open menu. Cancelling the action menu undoes the move.
+    pub fn cancel(&mut self) {
+        if !self.is_player_phase() {
+            return;
+        }
+        match self.mode {
+            Mode::Normal => self.selected = None,
+            Mode::ActionMenu(_) => {
+                self.undo();
+            }
+            Mode::ItemMenu(_) | Mode::SelectTarget(..) | Mode::TradeMenu(..) => {
+                self.open_action_menu()
+            }
+            Mode::RewindMenu(_) => self.mode = Mode::Normal,
+        }
+    }
+
+    // Takes back the selected unit's move from any of its menus. Returns
+    // false if there was no move to take back.
+    pub fn undo(&mut self) -> bool {
+        match self.mode {
+            Mode::Normal | Mode::RewindMenu(_) => return false,
+            _ => (),
+        }
+        match self.history.undo(&mut self.battle) {
+            Some(step) => {
+                self.selected = Some(step.origin);
+                self.cursor = step.origin;
+                self.mode = Mode::Normal;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Makes the move that was undone again, without walking. Returns false
+    // if there was nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        if !self.is_idle() {
+            return false;
+        }
+        match self.history.redo(&mut self.battle) {
+            Some(step) => {
+                self.selected = Some(step.destination);
+                self.open_action_menu();
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Takes back the phase's last actions, using up one of the battle's
+    // rewinds. Returns false if they can't be.
+    pub fn rewind(&mut self, actions: usize) -> bool {
+        if !self.is_idle() || !self.history.rewind(&mut self.battle, actions) {
+            return false;
+        }
+        self.selected = None;
+        true
+    }
+
+    // Puts the selected unit back to where it was before it moved, for
+    // saving the battle between moves.
+    pub fn abandon_move(&mut self) {
+        while self.history.undo(&mut self.battle).is_some() {}
+        self.selected = None;
+        self.mode = Mode::Normal;
+    }
+
+    // Ends the current phase and forgets its history. Returns the new phase.
+    pub fn end_phase(&mut self) -> Phase {
+        self.selected = None;
+        self.history.clear();
+        self.mode = Mode::Normal;
+        self.battle.end_phase()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chapter::UnitPlacement;
+    use history;
+    use terrain::Terrain;
+    use testing;
+
+    fn session(units: Vec<UnitPlacement>) -> Session {
+        let mut chapter = testing::chapter(8, 3, units);
+        chapter.terrain[2][1] = Terrain::Forest;
+        Session::new(Battle::new(chapter, testing::cost_table(), 1))
+    }
+
+    // Confirms like a frontend that doesn't animate anything.
+    fn confirm(session: &mut Session) {
+        match session.confirm() {
+            Some(Event::Move(path)) => session.finish_move(path[path.len() - 1]),
+            Some(Event::Attack(attacker, defender, result)) => {
+                session.finish_attack(attacker, defender, &result)
+            }
+            None => (),
+        }
+    }
+
+    fn press_all(session: &mut Session, commands: &str) {
+        for command in commands.chars() {
+            match command {
+                'w' => session.step_cursor((0, -1)),
+                'a' => session.step_cursor((-1, 0)),
+                's' => session.step_cursor((0, 1)),
+                'd' => session.step_cursor((1, 0)),
+                'x' => confirm(session),
+                'c' => session.cancel(),
+                'n' => session.select_next_unit(),
+                _ => panic!("unknown command {:?}", command),
+            }
+        }
+    }
+
+    #[test]
+    fn test_step_coord_stays_on_map() {
+        assert_eq!(step_coord((0, 0), (-1, 0), 5, 4), (0, 0));
+        assert_eq!(step_coord((4, 3), (1, 1), 5, 4), (4, 3));
+        assert_eq!(step_coord((2, 2), (1, -1), 5, 4), (3, 1));
+    }
+
+    #[test]
+    fn test_move_and_wait() {
+        let mut session = session(vec![
+            testing::placement(1, Faction::Player, (0, 0), &[]),
+            testing::placement(2, Faction::Enemy, (7, 2), &[]),
+        ]);
+        assert_eq!(session.cursor, (0, 0));
+        // Select and pick a cell two to the right
+        press_all(&mut session, "xdd");
+        assert_eq!(
+            session.confirm(),
+            Some(Event::Move(vec![(0, 0), (1, 0), (2, 0)]))
+        );
+        // Nothing moves until the frontend has shown the walk
+        assert!(session.battle.units.contains_key(&(0, 0)));
+        session.finish_move((2, 0));
+        assert_eq!(session.menu_labels(), Some((vec!["Wait"], 0)));
+        press_all(&mut session, "x");
+        assert!(session.battle.units[&(2, 0)].borrow().has_acted);
+        assert_eq!(session.selected, None);
+        assert!(session.is_idle());
+        assert!(session.battle.is_phase_over());
+    }
+
+    #[test]
+    fn test_cancel_undoes_move() {
+        let mut session = session(vec![
+            testing::placement(1, Faction::Player, (0, 0), &[]),
+            testing::placement(2, Faction::Enemy, (7, 2), &[]),
+        ]);
+        press_all(&mut session, "xsxc");
+        assert!(session.battle.units.contains_key(&(0, 0)));
+        assert_eq!(session.cursor, (0, 0));
+        assert_eq!(session.selected, Some((0, 0)));
+        press_all(&mut session, "c");
+        assert_eq!(session.selected, None);
+    }
+
+    #[test]
+    fn test_attack() {
+        let mut session = session(vec![
+            testing::placement(1, Faction::Player, (0, 0), &["Iron Sword"]),
+            testing::placement(2, Faction::Enemy, (2, 0), &[]),
+            testing::placement(3, Faction::Enemy, (7, 2), &[]),
+        ]);
+        session.battle.units[&(2, 0)].borrow_mut().hp = 1;
+        // Move next to the enemy and attack it
+        press_all(&mut session, "xdx");
+        assert_eq!(session.menu_labels().unwrap().0[0], "Attack");
+        press_all(&mut session, "x");
+        assert!(session.forecast().is_some());
+        let (attacker, defender, result) = match session.confirm() {
+            Some(Event::Attack(attacker, defender, result)) => (attacker, defender, result),
+            event => panic!("{:?}", event),
+        };
+        assert_eq!((attacker, defender), ((1, 0), (2, 0)));
+        // The fight only counts once it has been shown
+        assert!(session.battle.units.contains_key(&(2, 0)));
+        session.finish_attack(attacker, defender, &result);
+        assert!(!session.battle.units.contains_key(&(2, 0)));
+        assert_eq!(session.history.actions(), 1);
+    }
+
+    #[test]
+    fn test_cancel_reverts_trade() {
+        let mut session = session(vec![
+            testing::placement(1, Faction::Player, (0, 0), &["Iron Sword"]),
+            testing::placement(2, Faction::Player, (2, 0), &[]),
+            testing::placement(3, Faction::Enemy, (7, 2), &[]),
+        ]);
+        press_all(&mut session, "xdx");
+        assert_eq!(
+            session.menu_labels(),
+            Some((vec!["Item", "Trade", "Wait"], 0))
+        );
+        // Give the sword away and back out to the action menu
+        press_all(&mut session, "sxxxc");
+        assert_eq!(session.battle.units[&(1, 0)].borrow().inventory.len(), 0);
+        assert_eq!(session.battle.units[&(2, 0)].borrow().inventory.len(), 1);
+        match session.mode {
+            Mode::ActionMenu(_) => (),
+            ref mode => panic!("{:?}", mode),
+        }
+        // Cancelling the move gives the sword back
+        session.cancel();
+        assert_eq!(session.battle.units[&(0, 0)].borrow().inventory.len(), 1);
+        assert_eq!(session.battle.units[&(2, 0)].borrow().inventory.len(), 0);
+        assert!(!session.battle.units.contains_key(&(1, 0)));
+        match session.mode {
+            Mode::Normal => (),
+            ref mode => panic!("{:?}", mode),
+        }
+    }
+
+    #[test]
+    fn test_undo_redo_and_rewind() {
+        let mut session = session(vec![
+            testing::placement(1, Faction::Player, (0, 0), &[]),
+            testing::placement(2, Faction::Player, (0, 2), &[]),
+            testing::placement(3, Faction::Player, (5, 0), &[]),
+            testing::placement(4, Faction::Enemy, (7, 2), &[]),
+        ]);
+        press_all(&mut session, "xdx");
+        assert!(session.undo());
+        assert!(session.battle.units.contains_key(&(0, 0)));
+        assert_eq!(session.selected, Some((0, 0)));
+        assert!(session.redo());
+        assert!(session.battle.units.contains_key(&(1, 0)));
+        assert_eq!(session.selected, Some((1, 0)));
+        assert!(!session.redo());
+
+        // Wait with both units, then take both actions back
+        press_all(&mut session, "xndxx");
+        assert!(session.battle.units.contains_key(&(1, 2)));
+        assert!(!session.rewind(3));
+        assert!(session.open_rewind_menu());
+        assert_eq!(
+            session.menu_labels(),
+            Some((vec!["Rewind 1", "Rewind 2"], 0))
+        );
+        press_all(&mut session, "sx");
+        assert!(session.is_idle());
+        assert!(session.battle.units.contains_key(&(0, 0)));
+        assert!(session.battle.units.contains_key(&(0, 2)));
+        assert!(session.battle.can_move(&session.battle.units[&(0, 0)].borrow()));
+        assert_eq!(session.battle.rewinds, history::DEFAULT_REWINDS - 1);
+        assert!(!session.open_rewind_menu());
+    }
+
+    #[test]
+    fn test_abandon_move_and_end_phase() {
+        let mut session = session(vec![
+            testing::placement(1, Faction::Player, (0, 0), &[]),
+            testing::placement(2, Faction::Enemy, (7, 2), &[]),
+        ]);
+        press_all(&mut session, "xdx");
+        session.abandon_move();
+        assert!(session.is_idle());
+        assert!(session.battle.units.contains_key(&(0, 0)));
+
+        session.cursor = (0, 0);
+        press_all(&mut session, "x");
+        assert_eq!(session.selected, Some((0, 0)));
+        assert_eq!(session.end_phase(), Phase::Enemy);
+        assert_eq!(session.selected, None);
+        assert!(!session.is_idle());
+        // The player can't give orders in the enemy phase
+        press_all(&mut session, "x");
+        assert_eq!(session.selected, None);
+    }
+}
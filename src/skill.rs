@@ -0,0 +1,22 @@
+// Passive abilities that change how a unit follows the rules of the map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Skill {
+    // Ignores enemy zones of control
+    Pass,
+}
+
+impl Skill {
+    // Name used for the skill in data files.
+    pub fn from_name(name: &str) -> Option<Skill> {
+        match name {
+            "pass" => Some(Skill::Pass),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            &Skill::Pass => "pass",
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::cmp;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Stats {
+    pub hp: u32,
+    pub strength: u32,
+    pub magic: u32,
+    pub skill: u32,
+    pub speed: u32,
+    pub luck: u32,
+    pub defense: u32,
+    pub resistance: u32,
+    // Constitution. Reduces the speed penalty of heavy weapons.
+    pub constitution: u32,
+}
+
+impl Stats {
+    pub fn new(
+        hp: u32,
+        strength: u32,
+        magic: u32,
+        skill: u32,
+        speed: u32,
+        luck: u32,
+        defense: u32,
+        resistance: u32,
+        constitution: u32,
+    ) -> Self {
+        Stats {
+            hp,
+            strength,
+            magic,
+            skill,
+            speed,
+            luck,
+            defense,
+            resistance,
+            constitution,
+        }
+    }
+
+    pub fn add(&self, other: &Stats) -> Stats {
+        Stats {
+            hp: self.hp + other.hp,
+            strength: self.strength + other.strength,
+            magic: self.magic + other.magic,
+            skill: self.skill + other.skill,
+            speed: self.speed + other.speed,
+            luck: self.luck + other.luck,
+            defense: self.defense + other.defense,
+            resistance: self.resistance + other.resistance,
+            constitution: self.constitution + other.constitution,
+        }
+    }
+
+    // Each stat multiplied by numer / denom, rounded down.
+    pub fn scaled(&self, numer: u32, denom: u32) -> Stats {
+        let scale = |stat: u32| stat * numer / denom;
+        Stats {
+            hp: scale(self.hp),
+            strength: scale(self.strength),
+            magic: scale(self.magic),
+            skill: scale(self.skill),
+            speed: scale(self.speed),
+            luck: scale(self.luck),
+            defense: scale(self.defense),
+            resistance: scale(self.resistance),
+            constitution: scale(self.constitution),
+        }
+    }
+
+    // Limits each stat to the corresponding stat in caps.
+    pub fn capped(&self, caps: &Stats) -> Stats {
+        Stats {
+            hp: cmp::min(self.hp, caps.hp),
+            strength: cmp::min(self.strength, caps.strength),
+            magic: cmp::min(self.magic, caps.magic),
+            skill: cmp::min(self.skill, caps.skill),
+            speed: cmp::min(self.speed, caps.speed),
+            luck: cmp::min(self.luck, caps.luck),
+            defense: cmp::min(self.defense, caps.defense),
+            resistance: cmp::min(self.resistance, caps.resistance),
+            constitution: cmp::min(self.constitution, caps.constitution),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_add_capped() {
+        let stats = Stats::new(18, 5, 0, 5, 7, 5, 5, 0, 7);
+        let gains = Stats::new(1, 1, 0, 0, 15, 1, 0, 0, 0);
+        let caps = Stats::new(60, 20, 20, 20, 20, 30, 20, 20, 20);
+        assert_eq!(
+            stats.add(&gains).capped(&caps),
+            Stats::new(19, 6, 0, 5, 20, 6, 5, 0, 7)
+        );
+    }
+
+    #[test]
+    fn test_scaled() {
+        let growths = Stats::new(80, 45, 0, 50, 50, 50, 30, 25, 0);
+        assert_eq!(
+            growths.scaled(3, 100),
+            Stats::new(2, 1, 0, 1, 1, 1, 0, 0, 0)
+        );
+    }
+}
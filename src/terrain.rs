@@ -1,19 +1,203 @@
-use num::{One, Rational};
-use num::rational::Ratio;
+use std::collections::HashMap;
+use num::Rational;
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+// Every type of terrain, in the order of the map legend.
+pub const ALL_TERRAIN: [Terrain; 13] = [
+    Terrain::None,
+    Terrain::Wall,
+    Terrain::Sand,
+    Terrain::Forest,
+    Terrain::Mountain,
+    Terrain::Water,
+    Terrain::Fort,
+    Terrain::Gate,
+    Terrain::Village,
+    Terrain::Throne,
+    Terrain::Bridge,
+    Terrain::Door,
+    Terrain::Chest,
+];
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
 pub enum Terrain {
     Wall,
     Sand,
     None,
+    Forest,
+    Mountain,
+    Water,
+    Fort,
+    Gate,
+    Village,
+    Throne,
+    Bridge,
+    Door,
+    Chest,
 }
 
 impl Terrain {
-    pub fn cost(&self) -> Rational {
+    // Character used for the terrain in map files.
+    pub fn from_char(ch: char) -> Option<Terrain> {
+        match ch {
+            '0' => Some(Terrain::None),
+            'w' => Some(Terrain::Wall),
+            's' => Some(Terrain::Sand),
+            'f' => Some(Terrain::Forest),
+            'm' => Some(Terrain::Mountain),
+            '~' => Some(Terrain::Water),
+            'F' => Some(Terrain::Fort),
+            'g' => Some(Terrain::Gate),
+            'v' => Some(Terrain::Village),
+            't' => Some(Terrain::Throne),
+            'b' => Some(Terrain::Bridge),
+            'd' => Some(Terrain::Door),
+            'c' => Some(Terrain::Chest),
+            _ => None,
+        }
+    }
+
+    // All the characters that from_char() accepts.
+    pub fn legend() -> String {
+        ALL_TERRAIN
+            .iter()
+            .map(|terrain| terrain.to_char())
+            .collect()
+    }
+
+    pub fn to_char(&self) -> char {
+        match self {
+            &Terrain::None => '0',
+            &Terrain::Wall => 'w',
+            &Terrain::Sand => 's',
+            &Terrain::Forest => 'f',
+            &Terrain::Mountain => 'm',
+            &Terrain::Water => '~',
+            &Terrain::Fort => 'F',
+            &Terrain::Gate => 'g',
+            &Terrain::Village => 'v',
+            &Terrain::Throne => 't',
+            &Terrain::Bridge => 'b',
+            &Terrain::Door => 'd',
+            &Terrain::Chest => 'c',
+        }
+    }
+
+    // Name used for the terrain in data files.
+    pub fn from_name(name: &str) -> Option<Terrain> {
+        match name {
+            "wall" => Some(Terrain::Wall),
+            "sand" => Some(Terrain::Sand),
+            "none" => Some(Terrain::None),
+            "forest" => Some(Terrain::Forest),
+            "mountain" => Some(Terrain::Mountain),
+            "water" => Some(Terrain::Water),
+            "fort" => Some(Terrain::Fort),
+            "gate" => Some(Terrain::Gate),
+            "village" => Some(Terrain::Village),
+            "throne" => Some(Terrain::Throne),
+            "bridge" => Some(Terrain::Bridge),
+            "door" => Some(Terrain::Door),
+            "chest" => Some(Terrain::Chest),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            &Terrain::Wall => "wall",
+            &Terrain::Sand => "sand",
+            &Terrain::None => "none",
+            &Terrain::Forest => "forest",
+            &Terrain::Mountain => "mountain",
+            &Terrain::Water => "water",
+            &Terrain::Fort => "fort",
+            &Terrain::Gate => "gate",
+            &Terrain::Village => "village",
+            &Terrain::Throne => "throne",
+            &Terrain::Bridge => "bridge",
+            &Terrain::Door => "door",
+            &Terrain::Chest => "chest",
+        }
+    }
+
+    // Walls and closed doors, which no unit can stand on.
+    pub fn is_solid(&self) -> bool {
+        match self {
+            &Terrain::Wall | &Terrain::Door => true,
+            _ => false,
+        }
+    }
+
+    // Added to the defense (and resistance) of a unit standing on the terrain.
+    pub fn defense_bonus(&self) -> u32 {
+        match self {
+            &Terrain::Forest => 1,
+            &Terrain::Mountain | &Terrain::Fort => 2,
+            &Terrain::Gate | &Terrain::Throne => 3,
+            _ => 0,
+        }
+    }
+
+    // Added to the avoid of a unit standing on the terrain.
+    pub fn avoid_bonus(&self) -> u32 {
         match self {
-            &Terrain::Sand => Ratio::new(25, 10),
-            &Terrain::None => Ratio::one(),
-            &Terrain::Wall => unreachable!(),
+            &Terrain::Sand => 5,
+            &Terrain::Water | &Terrain::Village => 10,
+            &Terrain::Forest | &Terrain::Fort => 20,
+            &Terrain::Mountain | &Terrain::Gate | &Terrain::Throne => 30,
+            _ => 0,
+        }
+    }
+
+    // Percentage of max HP restored at the start of the turn for a unit
+    // standing on the terrain.
+    pub fn heal_percent(&self) -> u32 {
+        match self {
+            &Terrain::Fort => 20,
+            &Terrain::Gate | &Terrain::Throne => 10,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum MovementClass {
+    Infantry,
+    Cavalry,
+    Flier,
+    Armor,
+}
+
+impl MovementClass {
+    // Name used for the movement class in data files.
+    pub fn from_name(name: &str) -> Option<MovementClass> {
+        match name {
+            "infantry" => Some(MovementClass::Infantry),
+            "cavalry" => Some(MovementClass::Cavalry),
+            "flier" => Some(MovementClass::Flier),
+            "armor" => Some(MovementClass::Armor),
+            _ => None,
         }
     }
 }
+
+// Cost of entering each terrain for each movement class. Terrain without an
+// entry for a movement class is impassable for that class.
+#[derive(Debug, Clone, Default)]
+pub struct CostTable {
+    costs: HashMap<(Terrain, MovementClass), Rational>,
+}
+
+impl CostTable {
+    pub fn new() -> Self {
+        CostTable::default()
+    }
+
+    pub fn set_cost(&mut self, terrain: Terrain, movement_class: MovementClass, cost: Rational) {
+        self.costs.insert((terrain, movement_class), cost);
+    }
+
+    pub fn cost(&self, terrain: &Terrain, movement_class: MovementClass) -> Option<Rational> {
+        self.costs.get(&(*terrain, movement_class)).cloned()
+    }
+}
@@ -0,0 +1,68 @@
+// Fixtures shared by the tests of several modules.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use ai::Behavior;
+use chapter::{Chapter, DefeatCondition, UnitPlacement, VictoryCondition};
+use class::Class;
+use item::{Inventory, Item};
+use num::rational::Ratio;
+use terrain::{CostTable, MovementClass, Terrain};
+use unit::{Faction, Unit};
+
+// Puts a level 1 unit carrying the named items on location. Path data is left
+// empty.
+pub fn add_unit(
+    units: &mut HashMap<(u32, u32), Rc<RefCell<Unit>>>,
+    faction: Faction,
+    class: Class,
+    location: (u32, u32),
+    item_names: &[&str],
+) {
+    let mut inventory = Inventory::new();
+    for name in item_names {
+        inventory.add(Item::by_name(name).unwrap()).unwrap();
+    }
+    let unit = Unit::new(0, faction, class, 1, location, inventory);
+    units.insert(location, Rc::new(RefCell::new(unit)));
+}
+
+// A level 1 Lord carrying the named items.
+pub fn placement(id: u32, faction: Faction, location: (u32, u32), items: &[&str]) -> UnitPlacement {
+    let mut inventory = Inventory::new();
+    for name in items {
+        inventory.add(Item::by_name(name).unwrap()).unwrap();
+    }
+    UnitPlacement {
+        id,
+        faction,
+        class: Class::Lord,
+        level: 1,
+        location,
+        inventory,
+        behavior: Behavior::Aggressive,
+    }
+}
+
+// An open field that is won by defeating every enemy.
+pub fn chapter(width: u32, height: u32, units: Vec<UnitPlacement>) -> Chapter {
+    Chapter {
+        name: "Test".to_string(),
+        width,
+        height,
+        terrain: vec![vec![Terrain::None; height as usize]; width as usize],
+        zone_of_control: false,
+        units,
+        victory: VictoryCondition::Rout,
+        defeat: vec![DefeatCondition::AllUnitsDie],
+        reinforcements: vec![],
+    }
+}
+
+// Forests cost infantry two, everything else one.
+pub fn cost_table() -> CostTable {
+    let mut cost_table = CostTable::new();
+    cost_table.set_cost(Terrain::None, MovementClass::Infantry, Ratio::from_integer(1));
+    cost_table.set_cost(Terrain::Forest, MovementClass::Infantry, Ratio::from_integer(2));
+    cost_table
+}
@@ -0,0 +1,99 @@
+use unit::Faction;
+
+// Each faction moves all of its units during its own phase. A turn is one
+// player phase, one enemy phase and one other phase, in that order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Phase {
+    Player,
+    Enemy,
+    Other,
+}
+
+impl Phase {
+    pub fn faction(&self) -> Faction {
+        match self {
+            &Phase::Player => Faction::Player,
+            &Phase::Enemy => Faction::Enemy,
+            &Phase::Other => Faction::Other,
+        }
+    }
+
+    pub fn next(&self) -> Phase {
+        match self {
+            &Phase::Player => Phase::Enemy,
+            &Phase::Enemy => Phase::Other,
+            &Phase::Other => Phase::Player,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            &Phase::Player => "Player Phase",
+            &Phase::Enemy => "Enemy Phase",
+            &Phase::Other => "Other Phase",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TurnManager {
+    // Starts at 1
+    pub turn: u32,
+    pub phase: Phase,
+}
+
+impl TurnManager {
+    pub fn new() -> Self {
+        TurnManager {
+            turn: 1,
+            phase: Phase::Player,
+        }
+    }
+
+    // Advances to the next phase that has_units() returns true for, starting
+    // a new turn when wrapping around to the player phase. The player phase is
+    // never skipped so that this always terminates.
+    pub fn end_phase<F>(&mut self, has_units: F) -> Phase
+    where
+        F: Fn(Faction) -> bool,
+    {
+        loop {
+            self.phase = self.phase.next();
+            if self.phase == Phase::Player {
+                self.turn += 1;
+                break;
+            }
+            if has_units(self.phase.faction()) {
+                break;
+            }
+        }
+        self.phase
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_end_phase() {
+        let mut turns = TurnManager::new();
+        assert_eq!(turns.end_phase(|_| true), Phase::Enemy);
+        assert_eq!(turns.end_phase(|_| true), Phase::Other);
+        assert_eq!(turns.turn, 1);
+        assert_eq!(turns.end_phase(|_| true), Phase::Player);
+        assert_eq!(turns.turn, 2);
+    }
+
+    #[test]
+    fn test_end_phase_skips_empty_factions() {
+        let mut turns = TurnManager::new();
+        let has_units = |faction| faction != Faction::Other;
+        assert_eq!(turns.end_phase(&has_units), Phase::Enemy);
+        assert_eq!(turns.end_phase(&has_units), Phase::Player);
+        assert_eq!(turns.turn, 2);
+        // The player phase is never skipped
+        assert_eq!(turns.end_phase(|_| false), Phase::Player);
+        assert_eq!(turns.turn, 3);
+    }
+}
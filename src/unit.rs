@@ -1,41 +1,367 @@
-use ggez::graphics::Image;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use num::Rational;
+use pathfinding::Direction;
+use std::cmp;
+use ai::Behavior;
+use class::Class;
+use combat::Combatant;
+use item::{Inventory, Item, Weapon};
+use rng::Rng;
+use skill::Skill;
+use stats::Stats;
+use terrain::{MovementClass, Terrain};
+
+pub const MAX_LEVEL: u32 = 20;
+// Experience needed to gain a level
+pub const EXP_PER_LEVEL: u32 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Faction {
+    Player,
+    Enemy,
+    // Allied and neutral units
+    Other,
+}
+
+impl Faction {
+    // Player and other units are allied with each other.
+    pub fn is_hostile_to(&self, other: &Faction) -> bool {
+        (*self == Faction::Enemy) != (*other == Faction::Enemy)
+    }
+}
 
 #[derive(Debug)]
 pub struct Unit {
     pub id: u32,
+    pub faction: Faction,
+    pub class: Class,
+    pub level: u32,
+    pub exp: u32,
+    // Max HP is stats.hp
+    pub stats: Stats,
+    pub hp: u32,
+    pub inventory: Inventory,
+    pub skills: Vec<Skill>,
     pub movement_range: u32,
+    pub movement_class: MovementClass,
     pub location: (u32, u32),
-    pub animation_sprites: Vec<Image>,
-    pub animation_tick: f32,
+    // Reset at the start of the unit's phase. A unit that has acted is done
+    // for the turn.
+    pub has_moved: bool,
+    pub has_acted: bool,
+    // How the AI controls the unit when it isn't a player unit
+    pub behavior: Behavior,
     pub paths: HashMap<(u32, u32), (u32, u32)>,
     pub costs: HashMap<(u32, u32), Rational>,
     pub boundary: HashSet<(u32, u32)>,
     pub reachable_coords: HashSet<(u32, u32)>,
+    pub attack_coords: HashSet<(u32, u32)>,
+    // Sides of the reachable cells to outline when the unit is selected
+    pub border: Vec<((u32, u32), Vec<Direction>)>,
 }
 
 impl Unit {
+    // Path data is left empty until it's computed by the caller.
     pub fn new(
         id: u32,
-        movement_range: u32,
+        faction: Faction,
+        class: Class,
+        level: u32,
         location: (u32, u32),
-        animation_sprites: Vec<Image>,
-        paths: HashMap<(u32, u32), (u32, u32)>,
-        costs: HashMap<(u32, u32), Rational>,
-        boundary: HashSet<(u32, u32)>,
-        reachable_coords: HashSet<(u32, u32)>,
+        inventory: Inventory,
     ) -> Self {
+        let level = cmp::max(1, cmp::min(level, MAX_LEVEL));
+        let stats = class.stats_at_level(level);
         Unit {
             id,
-            movement_range,
+            faction,
+            class,
+            level,
+            exp: 0,
+            stats,
+            hp: stats.hp,
+            inventory,
+            skills: class.skills(),
+            movement_range: class.movement_range(),
+            movement_class: class.movement_class(),
             location,
-            animation_sprites,
-            animation_tick: 0.0,
-            paths,
-            costs,
-            boundary,
-            reachable_coords,
+            has_moved: false,
+            has_acted: false,
+            behavior: Behavior::Aggressive,
+            paths: HashMap::new(),
+            costs: HashMap::new(),
+            boundary: HashSet::new(),
+            reachable_coords: HashSet::new(),
+            attack_coords: HashSet::new(),
+            border: vec![],
+        }
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.hp > 0
+    }
+
+    // Restores up to amount HP without going over max HP. Returns the amount
+    // actually healed.
+    pub fn heal(&mut self, amount: u32) -> u32 {
+        let healed = cmp::min(amount, self.stats.hp - self.hp);
+        self.hp += healed;
+        healed
+    }
+
+    pub fn is_spent(&self) -> bool {
+        self.has_acted
+    }
+
+    pub fn reset_turn_flags(&mut self) {
+        self.has_moved = false;
+        self.has_acted = false;
+    }
+
+    pub fn has_skill(&self, skill: Skill) -> bool {
+        self.skills.contains(&skill)
+    }
+
+    pub fn equipped_weapon(&self) -> Option<&Weapon> {
+        self.inventory.equipped_weapon(&self.class)
+    }
+
+    // Every distance at which one of the weapons the unit can wield reaches.
+    // Empty if it can't attack.
+    pub fn attack_distances(&self) -> BTreeSet<u32> {
+        self.inventory
+            .usable_weapons(&self.class)
+            .iter()
+            .flat_map(|weapon| weapon.min_range..weapon.max_range + 1)
+            .collect()
+    }
+
+    // The unit as seen by combat, fighting with its equipped weapon while
+    // standing on terrain.
+    pub fn combatant(&self, terrain: Terrain) -> Combatant {
+        Combatant::new(
+            self.stats,
+            self.hp,
+            self.equipped_weapon().cloned(),
+            terrain,
+        )
+    }
+
+    // Like combatant(), but with the weapon the unit would equip to attack
+    // from distance cells away.
+    pub fn attacking_combatant(&self, terrain: Terrain, distance: u32) -> Combatant {
+        let weapon = self.inventory
+            .weapon_index_in_range(&self.class, distance)
+            .and_then(|index| match self.inventory.get(index) {
+                Some(&Item::Weapon(ref weapon)) => Some(weapon.clone()),
+                _ => None,
+            });
+        Combatant::new(self.stats, self.hp, weapon, terrain)
+    }
+
+    // Equips the first weapon that reaches distance. Returns false if there
+    // is none.
+    pub fn equip_for_distance(&mut self, distance: u32) -> bool {
+        match self.inventory.weapon_index_in_range(&self.class, distance) {
+            Some(index) => self.inventory.equip(index, &self.class),
+            None => false,
+        }
+    }
+
+    // Uses up count uses of the equipped weapon. Returns true if it broke.
+    pub fn use_equipped_weapon(&mut self, count: u32) -> bool {
+        match self.inventory.equipped_weapon_index(&self.class) {
+            Some(index) => self.inventory.use_item(index, count),
+            None => false,
+        }
+    }
+
+    // Adds experience and returns the number of levels gained. Experience
+    // stops accumulating at MAX_LEVEL.
+    pub fn gain_exp(&mut self, amount: u32) -> u32 {
+        if self.level >= MAX_LEVEL {
+            return 0;
         }
+        self.exp += amount;
+        let mut levels_gained = 0;
+        while self.exp >= EXP_PER_LEVEL && self.level < MAX_LEVEL {
+            self.exp -= EXP_PER_LEVEL;
+            self.level += 1;
+            levels_gained += 1;
+        }
+        if self.level >= MAX_LEVEL {
+            self.exp = 0;
+        }
+        levels_gained
+    }
+
+    // Rolls the stat gains of a level up. Each stat goes up by one with the
+    // class's growth for it as the percent chance.
+    pub fn roll_stat_gains(&self, rng: &mut Rng) -> Stats {
+        let growths = self.class.growths();
+        let mut roll = |growth: u32| if rng.check(growth) { 1 } else { 0 };
+        Stats {
+            hp: roll(growths.hp),
+            strength: roll(growths.strength),
+            magic: roll(growths.magic),
+            skill: roll(growths.skill),
+            speed: roll(growths.speed),
+            luck: roll(growths.luck),
+            defense: roll(growths.defense),
+            resistance: roll(growths.resistance),
+            constitution: roll(growths.constitution),
+        }
+    }
+
+    // Applies stat gains from a level up, limited by the class's stat caps.
+    // Current HP goes up by the same amount as max HP.
+    pub fn apply_stat_gains(&mut self, gains: &Stats) {
+        let old_max_hp = self.stats.hp;
+        self.stats = self.stats.add(gains).capped(&self.class.stat_caps());
+        self.hp += self.stats.hp - old_max_hp;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lord() -> Unit {
+        Unit::new(
+            1,
+            Faction::Player,
+            Class::Lord,
+            1,
+            (0, 0),
+            Inventory::new(),
+        )
+    }
+
+    fn with_items(mut unit: Unit, names: &[&str]) -> Unit {
+        for name in names {
+            unit.inventory.add(Item::by_name(name).unwrap()).unwrap();
+        }
+        unit
+    }
+
+    #[test]
+    fn test_new_uses_class() {
+        let unit = Unit::new(
+            1,
+            Faction::Enemy,
+            Class::Knight,
+            3,
+            (0, 0),
+            Inventory::new(),
+        );
+        // Two levels of average knight growths
+        assert_eq!(unit.stats, Stats::new(18, 5, 0, 2, 0, 0, 9, 0, 13));
+        assert_eq!(unit.hp, unit.stats.hp);
+        assert_eq!(unit.movement_range, 4);
+        assert_eq!(unit.movement_class, MovementClass::Armor);
+        assert_eq!(unit.level, 3);
+        assert!(!unit.has_skill(Skill::Pass));
+        let thief = Unit::new(
+            2,
+            Faction::Enemy,
+            Class::Thief,
+            1,
+            (0, 0),
+            Inventory::new(),
+        );
+        assert!(thief.has_skill(Skill::Pass));
+    }
+
+    #[test]
+    fn test_gain_exp() {
+        let mut unit = lord();
+        assert_eq!(unit.gain_exp(60), 0);
+        assert_eq!(unit.gain_exp(60), 1);
+        assert_eq!((unit.level, unit.exp), (2, 20));
+        assert_eq!(unit.gain_exp(250), 2);
+        assert_eq!((unit.level, unit.exp), (4, 70));
+    }
+
+    #[test]
+    fn test_roll_stat_gains() {
+        let unit = lord();
+        let mut rng = Rng::new(3);
+        let mut totals = Stats::default();
+        for _ in 0..100 {
+            totals = totals.add(&unit.roll_stat_gains(&mut rng));
+        }
+        // Roughly the growth rates, and never stats that don't grow
+        let growths = Class::Lord.growths();
+        assert!(totals.hp > growths.hp - 15 && totals.hp < growths.hp + 15);
+        assert_eq!((totals.magic, totals.constitution), (0, 0));
+    }
+
+    #[test]
+    fn test_gain_exp_max_level() {
+        let mut unit = lord();
+        unit.level = MAX_LEVEL - 1;
+        assert_eq!(unit.gain_exp(250), 1);
+        assert_eq!((unit.level, unit.exp), (MAX_LEVEL, 0));
+        assert_eq!(unit.gain_exp(50), 0);
+        assert_eq!(unit.exp, 0);
+    }
+
+    #[test]
+    fn test_apply_stat_gains() {
+        let mut unit = lord();
+        unit.hp = 10;
+        unit.apply_stat_gains(&Stats::new(2, 1, 0, 0, 30, 0, 0, 0, 0));
+        assert_eq!(unit.stats.hp, 20);
+        assert_eq!(unit.hp, 12);
+        assert_eq!(unit.stats.strength, 6);
+        assert_eq!(unit.stats.speed, Class::Lord.stat_caps().speed);
+    }
+
+    #[test]
+    fn test_attack_distances() {
+        assert_eq!(lord().attack_distances(), btreeset!{});
+        let unit = with_items(lord(), &["Vulnerary", "Iron Sword"]);
+        assert_eq!(unit.attack_distances(), btreeset!{1});
+        // Lords can't use lances
+        let unit = with_items(lord(), &["Iron Sword", "Javelin"]);
+        assert_eq!(unit.attack_distances(), btreeset!{1});
+        let mut unit = with_items(lord(), &["Iron Sword"]);
+        unit.class = Class::Cavalier;
+        let unit = with_items(unit, &["Javelin"]);
+        assert_eq!(unit.attack_distances(), btreeset!{1, 2});
+    }
+
+    #[test]
+    fn test_equip_for_distance() {
+        let mut unit = with_items(lord(), &["Iron Sword"]);
+        unit.class = Class::Cavalier;
+        let mut unit = with_items(unit, &["Javelin"]);
+        assert_eq!(
+            unit.attacking_combatant(Terrain::None, 2).weapon.unwrap().name,
+            "Javelin"
+        );
+        assert_eq!(unit.equipped_weapon().unwrap().name, "Iron Sword");
+        assert!(unit.equip_for_distance(2));
+        assert_eq!(unit.equipped_weapon().unwrap().name, "Javelin");
+        assert!(!unit.equip_for_distance(3));
+    }
+
+    #[test]
+    fn test_heal() {
+        let mut unit = lord();
+        unit.hp = 10;
+        assert_eq!(unit.heal(5), 5);
+        assert_eq!(unit.heal(20), unit.stats.hp - 15);
+        assert_eq!(unit.hp, unit.stats.hp);
+    }
+
+    #[test]
+    fn test_use_equipped_weapon() {
+        let mut unit = with_items(lord(), &["Vulnerary", "Iron Sword"]);
+        assert!(!unit.use_equipped_weapon(45));
+        assert_eq!(unit.equipped_weapon().unwrap().uses, 1);
+        assert!(unit.use_equipped_weapon(1));
+        assert_eq!(unit.equipped_weapon(), None);
+        assert_eq!(unit.inventory.len(), 1);
     }
 }
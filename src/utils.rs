@@ -1,5 +1,7 @@
 use num::Rational;
+use num::rational::Ratio;
 
+#[macro_export]
 macro_rules! tuple_as {
     ($t: expr, $(($var: ident, $ty: ty)),*) => {
         {
@@ -12,3 +14,43 @@ macro_rules! tuple_as {
 pub fn rat_to_f32(rat: &Rational) -> f32 {
     *rat.numer() as f32 / *rat.denom() as f32
 }
+
+// Parses an integer ("2"), decimal ("2.5") or fraction ("5/2").
+pub fn parse_rational(s: &str) -> Option<Rational> {
+    if let Some(idx) = s.find('/') {
+        let numer = s[..idx].trim().parse::<isize>().ok()?;
+        let denom = s[idx + 1..].trim().parse::<isize>().ok()?;
+        if denom == 0 {
+            return None;
+        }
+        return Some(Ratio::new(numer, denom));
+    }
+    if let Some(idx) = s.find('.') {
+        let frac_digits = &s[idx + 1..];
+        if frac_digits.is_empty() || !frac_digits.chars().all(|c| c.is_digit(10)) {
+            return None;
+        }
+        let numer = format!("{}{}", &s[..idx], frac_digits)
+            .parse::<isize>()
+            .ok()?;
+        let denom = 10isize.pow(frac_digits.len() as u32);
+        return Some(Ratio::new(numer, denom));
+    }
+    s.parse::<isize>().ok().map(Ratio::from_integer)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_rational() {
+        assert_eq!(parse_rational("2"), Some(Ratio::from_integer(2)));
+        assert_eq!(parse_rational("2.5"), Some(Ratio::new(5, 2)));
+        assert_eq!(parse_rational("0.25"), Some(Ratio::new(1, 4)));
+        assert_eq!(parse_rational("5/2"), Some(Ratio::new(5, 2)));
+        assert_eq!(parse_rational("5/0"), None);
+        assert_eq!(parse_rational("2."), None);
+        assert_eq!(parse_rational("abc"), None);
+    }
+}